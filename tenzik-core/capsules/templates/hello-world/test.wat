@@ -3,7 +3,7 @@
 
 (module
   ;; Import host functions (if needed)
-  ;; (import "env" "hash_commit" (func $hash_commit (param i32 i32) (result i32)))
+  ;; (import "env" "hash_commit" (func $hash_commit (param i32 i32 i32) (result i32)))
   
   ;; Export memory so host can read/write data
   (memory (export "memory") 1)
@@ -12,8 +12,6 @@ pub use http_server::{HttpServer, ServerConfig};
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
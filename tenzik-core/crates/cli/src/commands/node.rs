@@ -8,7 +8,6 @@ use std::net::SocketAddr;
 use std::path::Path;
 use tenzik_federation::{TenzikNode, NodeConfig};
 use tokio::signal;
-use tracing::{info, warn, error};
 
 /// Arguments for the node command
 pub struct NodeArgs {
@@ -137,12 +136,10 @@ async fn wait_for_shutdown() {
 pub fn validate_db_path(db_path: &str) -> Result<()> {
     let path = Path::new(db_path);
     
-    // Check if parent directory exists or can be created
-    if let Some(parent) = path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create database directory: {}", parent.display()))?;
-        }
+    // Check if the database directory exists or can be created
+    if !path.exists() {
+        std::fs::create_dir_all(path)
+            .with_context(|| format!("Failed to create database directory: {}", path.display()))?;
     }
     
     // Check write permissions by trying to create a test file
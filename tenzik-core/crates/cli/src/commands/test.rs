@@ -65,7 +65,11 @@ pub async fn execute_test_command(args: TestArgs) -> Result<()> {
                 Capability::Json,
                 Capability::Base64,
                 Capability::Time,
+                Capability::Log,
             ],
+            http: Default::default(),
+            secrets: Vec::new(),
+            secret_headers: Vec::new(),
         }
     };
 
@@ -78,7 +82,7 @@ pub async fn execute_test_command(args: TestArgs) -> Result<()> {
 
     // Create runtime with test signing key
     let signing_key = generate_test_signing_key();
-    let mut runtime = WasmRuntime::new(signing_key)?;
+    let runtime = WasmRuntime::new(signing_key)?;
 
     println!("🚀 Executing capsule...");
     let start_time = std::time::Instant::now();
@@ -88,6 +92,15 @@ pub async fn execute_test_command(args: TestArgs) -> Result<()> {
         Ok(result) => result,
         Err(e) => {
             println!("❌ Execution failed: {}", e);
+            if let Some(backtrace) = e.backtrace() {
+                println!("   Guest backtrace:");
+                for frame in backtrace.lines() {
+                    println!("     {}", frame);
+                }
+            }
+            if e.is_retryable() {
+                println!("   This failure depends on load; retrying may succeed");
+            }
             return Err(e.into());
         }
     };
@@ -116,6 +129,15 @@ pub async fn execute_test_command(args: TestArgs) -> Result<()> {
     }
     println!();
 
+    // Display capsule logs
+    if !result.logs.is_empty() {
+        println!("🪵 Capsule logs ({} lines):", result.logs.len());
+        for line in &result.logs {
+            println!("   [{:?}] {}", line.level, line.message);
+        }
+        println!();
+    }
+
     // Display execution metrics
     if args.metrics {
         println!("📊 Execution Metrics:");
@@ -123,6 +145,9 @@ pub async fn execute_test_command(args: TestArgs) -> Result<()> {
         println!("   Memory: {:.3} MB", result.metrics.memory_mb);
         println!("   Duration: {} ms", result.metrics.duration_ms);
         println!("   Host calls: {}", result.metrics.host_function_calls);
+        for (function, count) in &result.metrics.host_call_breakdown {
+            println!("     {}: {}", function, count);
+        }
         println!();
     }
 
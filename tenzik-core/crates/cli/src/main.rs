@@ -2,7 +2,7 @@ use clap::{Args, Parser, Subcommand};
 use anyhow::Result;
 
 mod commands;
-use commands::{TestArgs, execute_test_command, validate_capsule_file, NodeArgs, execute_node_command, validate_db_path, parse_peer_address};
+use commands::{TestArgs, execute_test_command, validate_capsule_file, execute_node_command, validate_db_path, parse_peer_address};
 
 #[derive(Parser)]
 #[command(name = "tenzik")]
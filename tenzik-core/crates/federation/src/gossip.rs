@@ -9,7 +9,7 @@ use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::time::{interval, Instant};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, warn};
 
 use crate::storage::EventDAG;
 use tenzik_protocol::Event;
@@ -204,7 +204,7 @@ impl GossipProtocol {
                 }
 
                 // Sync if never synced or last sync was long ago
-                peer.last_sync.map_or(true, |last| {
+                peer.last_sync.is_none_or(|last| {
                     last.elapsed() > Duration::from_millis(self.config.sync_interval_ms)
                 })
             })
@@ -286,11 +286,6 @@ impl GossipProtocol {
     async fn ping_peers(&mut self) {
         debug!("Pinging {} peers", self.peers.len());
 
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
-
         for (peer_addr, peer) in &mut self.peers {
             if peer.is_reachable {
                 // TODO: Send ping message via network
@@ -435,7 +430,7 @@ impl GossipProtocol {
         &mut self,
         from: SocketAddr,
         ping_timestamp: u64,
-        pong_timestamp: u64,
+        _pong_timestamp: u64,
     ) -> Result<Option<GossipMessage>> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
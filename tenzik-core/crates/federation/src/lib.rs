@@ -15,8 +15,6 @@ pub use tenzik_protocol::{DAGStats, Event, EventContent, EventType, NodeInfo};
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 use crate::storage::EventDAG;
 use tenzik_protocol::{Event, EventContent, EventType, NodeInfo};
@@ -66,6 +66,7 @@ pub struct TenzikNode {
     /// Local sequence counter
     sequence: u64,
     /// Node start time
+    #[allow(dead_code)]
     start_time: chrono::DateTime<chrono::Utc>,
 }
 
@@ -96,15 +97,15 @@ impl TenzikNode {
         info!("Starting Tenzik node on {}", self.config.listen_addr);
 
         // Bind to listen address
-        let listener = TcpListener::bind(self.config.listen_addr).await?;
+        let _listener = TcpListener::bind(self.config.listen_addr).await?;
         info!("Node listening on {}", self.config.listen_addr);
 
         // Announce ourselves to the network
         self.announce_self().await?;
 
         // Connect to initial peers
-        for peer_addr in &self.config.initial_peers {
-            if let Err(e) = self.connect_to_peer(*peer_addr).await {
+        for peer_addr in self.config.initial_peers.clone() {
+            if let Err(e) = self.connect_to_peer(peer_addr).await {
                 warn!("Failed to connect to initial peer {}: {}", peer_addr, e);
             }
         }
@@ -185,8 +186,8 @@ impl TenzikNode {
     }
 
     /// Get DAG statistics
-    pub fn get_dag_stats(&self) -> Result<crate::storage::DAGStats> {
-        self.dag.get_stats()
+    pub fn get_dag_stats(&self) -> Result<tenzik_protocol::DAGStats> {
+        Ok(self.dag.get_stats()?)
     }
 
     /// Add an event to the local DAG (e.g., from execution)
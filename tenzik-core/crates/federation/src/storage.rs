@@ -3,11 +3,11 @@
 //! This module implements a simple Directed Acyclic Graph (DAG) for storing
 //! and organizing federation events, with persistent storage using sled.
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use sled::{Db, Tree};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::path::Path;
-use tenzik_protocol::{DAGStats, Event, ExecutionReceipt, NodeInfo};
+use tenzik_protocol::{DAGStats, Event};
 use thiserror::Error;
 
 /// Storage-related errors
@@ -151,10 +151,9 @@ impl EventDAG {
 
     /// Check if an event exists
     pub fn has_event(&self, event_id: &str) -> Result<bool, StorageError> {
-        Ok(self
-            .events
+        self.events
             .contains_key(event_id)
-            .map_err(|e| StorageError::DatabaseError { source: e })?)
+            .map_err(|e| StorageError::DatabaseError { source: e })
     }
 
     /// Get current tips (events with no children)
@@ -188,7 +187,6 @@ impl EventDAG {
         since_event_id: Option<&str>,
     ) -> Result<Vec<Event>, StorageError> {
         let mut events = Vec::new();
-        let mut seen = HashSet::new();
 
         // If no since_event_id, return all events
         if since_event_id.is_none() {
@@ -400,6 +398,7 @@ impl EventDAG {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tenzik_protocol::ExecutionReceipt;
     use tempfile::TempDir;
 
     fn create_test_signing_key() -> ed25519_dalek::SigningKey {
@@ -414,6 +413,7 @@ mod tests {
             b"test input",
             b"test output",
             tenzik_protocol::ExecMetrics::default(),
+            Default::default(),
             &signing_key,
             1,
         )
@@ -27,6 +27,7 @@ pub enum EventType {
 
 /// Content of different event types.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum EventContent {
     /// Receipt content
     Receipt(ExecutionReceipt),
@@ -259,7 +260,7 @@ impl Event {
             })?;
 
         let signature =
-            Signature::from_bytes(&signature_bytes).map_err(|_| ProtocolError::InvalidFormat {
+            Signature::from_slice(&signature_bytes).map_err(|_| ProtocolError::InvalidFormat {
                 reason: "Invalid signature format".to_string(),
             })?;
 
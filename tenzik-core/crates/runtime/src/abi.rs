@@ -0,0 +1,269 @@
+//! Capsule ABI Module
+//!
+//! This module defines how the host passes input to a capsule's `run` export
+//! and reads its output back. Two ABI versions are supported and detected
+//! from the capsule's exports:
+//!
+//! **V1** (capsule exports `alloc` and `dealloc`):
+//! 1. The host calls `alloc(input_len) -> ptr` and writes the input there.
+//!    Ownership of the input buffer passes to the guest.
+//! 2. The host calls `run(ptr, len) -> result_ptr`. `result_ptr` points to
+//!    two little-endian `u32`s: the output pointer and the output length.
+//! 3. The host copies the output and calls `dealloc(out_ptr, out_len)`.
+//!
+//! **Legacy** (no `alloc` export): the input is written at a fixed offset of
+//! 1KB and `run` returns `(output_len << 16) | output_ptr`, which limits the
+//! output pointer and length to 64KB each. Kept for existing capsules.
+
+use crate::execution::ExecutionError;
+use crate::host::{guest_range, HostState};
+
+use wasmtime::{Instance, Memory, Module, Store, TypedFunc};
+
+/// Export that marks a V1 capsule and allocates guest buffers
+pub const ALLOC_EXPORT: &str = "alloc";
+/// Export that frees buffers returned by `alloc` or `run`
+pub const DEALLOC_EXPORT: &str = "dealloc";
+
+/// Fixed input offset used by the legacy ABI
+const LEGACY_INPUT_OFFSET: usize = 1024;
+
+/// Size of the `(ptr, len)` pair returned by a V1 `run`
+const V1_RESULT_LEN: usize = 8;
+
+/// Input/output convention implemented by a capsule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapsuleAbi {
+    /// Fixed 1KB input offset and `(len << 16) | ptr` packed return value
+    Legacy,
+    /// Guest `alloc`/`dealloc` and an out-pointer to a `(ptr, len)` pair
+    V1,
+}
+
+impl CapsuleAbi {
+    /// Detect the ABI version from a module's exports
+    pub fn detect(module: &Module) -> Self {
+        if module.get_export(ALLOC_EXPORT).is_some() {
+            CapsuleAbi::V1
+        } else {
+            CapsuleAbi::Legacy
+        }
+    }
+
+    /// Numeric ABI version (0 for legacy)
+    pub fn version(&self) -> u32 {
+        match self {
+            CapsuleAbi::Legacy => 0,
+            CapsuleAbi::V1 => 1,
+        }
+    }
+
+    /// Copy the input into guest memory, returning the pointer to pass to `run`
+    pub(crate) async fn write_input(
+        &self,
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        memory: Memory,
+        input: &[u8],
+    ) -> Result<i32, ExecutionError> {
+        let input_ptr = match self {
+            CapsuleAbi::Legacy => {
+                if LEGACY_INPUT_OFFSET + input.len() > memory.data_size(&*store) {
+                    return Err(ExecutionError::ExecutionFailed {
+                        reason: "Input too large for WASM memory".to_string(),
+                    });
+                }
+                LEGACY_INPUT_OFFSET
+            }
+            CapsuleAbi::V1 => {
+                let alloc: TypedFunc<i32, i32> = typed_export(store, instance, ALLOC_EXPORT)?;
+                let ptr = alloc
+                    .call_async(&mut *store, input.len() as i32)
+                    .await
+                    .map_err(ExecutionError::from_trap)?;
+                guest_range(memory.data_size(&*store), ptr, input.len() as i32).ok_or_else(
+                    || ExecutionError::ExecutionFailed {
+                        reason: format!(
+                            "Guest alloc returned out-of-bounds buffer {:#x} for {} bytes",
+                            ptr as u32,
+                            input.len()
+                        ),
+                    },
+                )?;
+                ptr as u32 as usize
+            }
+        };
+
+        memory.write(&mut *store, input_ptr, input).map_err(|e| {
+            ExecutionError::ExecutionFailed {
+                reason: format!("Failed to write input to memory: {}", e),
+            }
+        })?;
+
+        Ok(input_ptr as i32)
+    }
+
+    /// Decode the value returned by `run` and copy the output out of guest memory
+    pub(crate) async fn read_output(
+        &self,
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        memory: Memory,
+        result: i32,
+        max_io_size: usize,
+    ) -> Result<Vec<u8>, ExecutionError> {
+        let (output_ptr, output_len) = match self {
+            CapsuleAbi::Legacy => {
+                let packed = result as u32;
+                ((packed & 0xFFFF) as i32, (packed >> 16) as usize)
+            }
+            CapsuleAbi::V1 => {
+                let range = guest_range(memory.data_size(&*store), result, V1_RESULT_LEN as i32)
+                    .ok_or_else(|| ExecutionError::ExecutionFailed {
+                        reason: format!("Result pointer {:#x} is out of bounds", result as u32),
+                    })?;
+                let pair = &memory.data(&*store)[range];
+                let ptr = u32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]);
+                let len = u32::from_le_bytes([pair[4], pair[5], pair[6], pair[7]]);
+                (ptr as i32, len as usize)
+            }
+        };
+
+        if output_len > max_io_size {
+            return Err(ExecutionError::IOError {
+                reason: format!(
+                    "Output too large: {} bytes (max: {})",
+                    output_len, max_io_size
+                ),
+            });
+        }
+
+        let range = guest_range(memory.data_size(&*store), output_ptr, output_len as i32)
+            .ok_or_else(|| ExecutionError::ExecutionFailed {
+                reason: format!(
+                    "Failed to read output from memory: {} bytes at {:#x} out of bounds",
+                    output_len, output_ptr as u32
+                ),
+            })?;
+        let output = memory.data(&*store)[range].to_vec();
+
+        if *self == CapsuleAbi::V1 {
+            let dealloc: TypedFunc<(i32, i32), ()> = typed_export(store, instance, DEALLOC_EXPORT)?;
+            dealloc
+                .call_async(&mut *store, (output_ptr, output_len as i32))
+                .await
+                .map_err(ExecutionError::from_trap)?;
+        }
+
+        Ok(output)
+    }
+}
+
+/// Look up a typed function export required by the ABI
+fn typed_export<Params, Results>(
+    store: &mut Store<HostState>,
+    instance: &Instance,
+    name: &str,
+) -> Result<TypedFunc<Params, Results>, ExecutionError>
+where
+    Params: wasmtime::WasmParams,
+    Results: wasmtime::WasmResults,
+{
+    instance
+        .get_typed_func(&mut *store, name)
+        .map_err(|e| ExecutionError::ExecutionFailed {
+            reason: format!("Failed to get '{}' function: {}", name, e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::WasmRuntime;
+    use crate::receipts::generate_test_signing_key;
+    use crate::sandbox::ResourceLimits;
+
+    /// V1 capsule with a bump allocator. `run` echoes its input unless the
+    /// first byte is `k`, in which case it returns the static data at 1KB,
+    /// or `!`, in which case it returns an out-of-bounds result pointer.
+    const V1_WAT: &str = r#"
+        (module
+          (memory (export "memory") 4)
+          (global $heap (mut i32) (i32.const 4096))
+          (data (i32.const 1024) "kept")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $heap))
+            (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+            (local.get $ptr))
+          (func (export "dealloc") (param i32 i32))
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 33))
+              (then (return (i32.const -16))))
+            (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 107))
+              (then
+                (local.set $ptr (i32.const 1024))
+                (local.set $len (i32.const 4))))
+            (i32.store (i32.const 16) (local.get $ptr))
+            (i32.store (i32.const 20) (local.get $len))
+            (i32.const 16)))
+    "#;
+
+    /// Legacy capsule that echoes its input with the packed return value
+    const LEGACY_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (i32.or (i32.shl (local.get $len) (i32.const 16)) (local.get $ptr))))
+    "#;
+
+    async fn run(wat: &str, input: &[u8]) -> Result<Vec<u8>, ExecutionError> {
+        let capsule = wat::parse_str(wat).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        runtime
+            .execute(&capsule, input, ResourceLimits::default())
+            .await
+            .map(|result| result.output)
+    }
+
+    #[test]
+    fn test_detect_abi() {
+        let engine = wasmtime::Engine::default();
+        let v1 = Module::new(&engine, V1_WAT).unwrap();
+        let legacy = Module::new(&engine, LEGACY_WAT).unwrap();
+
+        assert_eq!(CapsuleAbi::detect(&v1), CapsuleAbi::V1);
+        assert_eq!(CapsuleAbi::detect(&legacy), CapsuleAbi::Legacy);
+        assert_eq!(CapsuleAbi::V1.version(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_v1_output_larger_than_64kb() {
+        let input: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let output = run(V1_WAT, &input).await.unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[tokio::test]
+    async fn test_v1_does_not_clobber_guest_data() {
+        let mut input = vec![b'k'];
+        input.extend([b'x'; 2000]);
+        let output = run(V1_WAT, &input).await.unwrap();
+        assert_eq!(output, b"kept");
+    }
+
+    #[tokio::test]
+    async fn test_v1_rejects_out_of_bounds_result() {
+        let result = run(V1_WAT, b"!").await;
+        assert!(matches!(
+            result,
+            Err(ExecutionError::ExecutionFailed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_abi_still_supported() {
+        let output = run(LEGACY_WAT, b"legacy").await.unwrap();
+        assert_eq!(output, b"legacy");
+    }
+}
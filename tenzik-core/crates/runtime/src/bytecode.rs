@@ -0,0 +1,419 @@
+//! Static Bytecode Analysis
+//!
+//! `WasmValidator` walks a capsule's sections and code with this module to
+//! catch constructs that make a run nondeterministic or abusive before it is
+//! ever instantiated: floating point instructions when NaN results are not
+//! canonicalized, `start` functions, oversized memories and tables, and too
+//! many functions, locals or nested blocks.
+//!
+//! Every finding carries the byte offset in the capsule binary it was found
+//! at, and findings inside a function body also carry the function index, so
+//! they can be matched against `wasm-objdump` or a disassembly.
+
+use serde::{Deserialize, Serialize};
+use wasmparser::{FunctionBody, Operator, Parser, Payload, TypeRef};
+
+use crate::limiter::MAX_TABLE_ELEMENTS;
+use crate::validation::ValidationError;
+
+/// Default limit on the initial pages of a memory (64MB)
+pub const DEFAULT_MAX_MEMORY_PAGES: u64 = 1024;
+
+/// Default limit on the functions a capsule defines or imports
+pub const DEFAULT_MAX_FUNCTIONS: u32 = 1024;
+
+/// Default limit on the locals of one function
+pub const DEFAULT_MAX_LOCALS: u32 = 1024;
+
+/// Default limit on nested blocks, loops and ifs in one function
+pub const DEFAULT_MAX_NESTING_DEPTH: u32 = 128;
+
+/// Limits enforced on a capsule's bytecode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BytecodeLimits {
+    /// Initial 64KB pages a memory may declare; a larger declared maximum
+    /// is only a warning since the runtime caps growth
+    pub max_memory_pages: u64,
+    /// Initial elements a table may declare
+    pub max_table_elements: u64,
+    /// Functions a capsule may define or import
+    pub max_functions: u32,
+    /// Locals one function may declare, excluding parameters
+    pub max_locals: u32,
+    /// Depth of nested blocks, loops and ifs in one function
+    pub max_nesting_depth: u32,
+    /// Whether floating point instructions are allowed. Ignored when the
+    /// feature policy canonicalizes NaNs, which makes them deterministic.
+    pub allow_float: bool,
+}
+
+impl Default for BytecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_memory_pages: DEFAULT_MAX_MEMORY_PAGES,
+            max_table_elements: MAX_TABLE_ELEMENTS as u64,
+            max_functions: DEFAULT_MAX_FUNCTIONS,
+            max_locals: DEFAULT_MAX_LOCALS,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            allow_float: false,
+        }
+    }
+}
+
+/// Errors and warnings found in a capsule's bytecode
+#[derive(Debug, Default)]
+pub(crate) struct BytecodeReport {
+    pub(crate) errors: Vec<ValidationError>,
+    pub(crate) warnings: Vec<String>,
+}
+
+/// Analyze `wasm_bytes` against `limits`. Floating point instructions are
+/// allowed if `limits.allow_float` or `nan_canonicalization` is set.
+pub(crate) fn analyze(
+    wasm_bytes: &[u8],
+    limits: &BytecodeLimits,
+    nan_canonicalization: bool,
+) -> BytecodeReport {
+    let mut analyzer = Analyzer {
+        limits,
+        allow_float: limits.allow_float || nan_canonicalization,
+        imported_functions: 0,
+        next_function: 0,
+        memories: 0,
+        tables: 0,
+        report: BytecodeReport::default(),
+    };
+    if let Err(e) = analyzer.run(wasm_bytes) {
+        analyzer.report.errors.push(ValidationError::InvalidModule {
+            reason: format!("{} (at offset {:#x})", e.message(), e.offset()),
+        });
+    }
+    analyzer.report
+}
+
+struct Analyzer<'l> {
+    limits: &'l BytecodeLimits,
+    allow_float: bool,
+    imported_functions: u32,
+    /// Index of the next function body in the code section
+    next_function: u32,
+    /// Memories and tables seen so far, imported ones first
+    memories: u32,
+    tables: u32,
+    report: BytecodeReport,
+}
+
+impl Analyzer<'_> {
+    fn run(&mut self, wasm_bytes: &[u8]) -> wasmparser::Result<()> {
+        for payload in Parser::new(0).parse_all(wasm_bytes) {
+            match payload? {
+                Payload::ImportSection(reader) => {
+                    for import in reader.into_iter_with_offsets() {
+                        let (offset, import) = import?;
+                        match import.ty {
+                            TypeRef::Func(_) => self.imported_functions += 1,
+                            TypeRef::Memory(ty) => self.check_memory(ty, offset),
+                            TypeRef::Table(ty) => self.check_table(ty, offset),
+                            _ => {}
+                        }
+                    }
+                    self.next_function = self.imported_functions;
+                }
+                Payload::FunctionSection(reader) => {
+                    let count = self.imported_functions as u64 + reader.count() as u64;
+                    if count > self.limits.max_functions as u64 {
+                        self.report.errors.push(ValidationError::TooManyFunctions {
+                            count,
+                            max: self.limits.max_functions,
+                            offset: reader.range().start,
+                        });
+                    }
+                }
+                Payload::TableSection(reader) => {
+                    for table in reader.into_iter_with_offsets() {
+                        let (offset, table) = table?;
+                        self.check_table(table.ty, offset);
+                    }
+                }
+                Payload::MemorySection(reader) => {
+                    for memory in reader.into_iter_with_offsets() {
+                        let (offset, memory) = memory?;
+                        self.check_memory(memory, offset);
+                    }
+                }
+                Payload::StartSection { func, range } => {
+                    self.report.errors.push(ValidationError::StartFunction {
+                        function_index: func,
+                        offset: range.start,
+                    });
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let function_index = self.next_function;
+                    self.next_function += 1;
+                    self.check_function(function_index, &body)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn check_memory(&mut self, ty: wasmparser::MemoryType, offset: usize) {
+        let index = self.memories;
+        self.memories += 1;
+        let max_pages = self.limits.max_memory_pages;
+        if ty.initial > max_pages {
+            self.report.errors.push(ValidationError::MemoryTooLarge {
+                index,
+                pages: ty.initial,
+                max_pages,
+                offset,
+            });
+        } else if ty.maximum.is_some_and(|maximum| maximum > max_pages) {
+            self.report.warnings.push(format!(
+                "Memory {} declares a maximum of {} pages; growth stops at {} pages \
+                 (offset {:#x})",
+                index,
+                ty.maximum.unwrap_or_default(),
+                max_pages,
+                offset
+            ));
+        }
+    }
+
+    fn check_table(&mut self, ty: wasmparser::TableType, offset: usize) {
+        let index = self.tables;
+        self.tables += 1;
+        if ty.initial > self.limits.max_table_elements {
+            self.report.errors.push(ValidationError::TableTooLarge {
+                index,
+                elements: ty.initial,
+                max_elements: self.limits.max_table_elements,
+                offset,
+            });
+        }
+    }
+
+    fn check_function(
+        &mut self,
+        function_index: u32,
+        body: &FunctionBody<'_>,
+    ) -> wasmparser::Result<()> {
+        let mut locals = 0u64;
+        for local in body.get_locals_reader()? {
+            let (count, _) = local?;
+            locals += count as u64;
+        }
+        if locals > self.limits.max_locals as u64 {
+            self.report.errors.push(ValidationError::TooManyLocals {
+                function_index,
+                count: locals,
+                max: self.limits.max_locals,
+                offset: body.range().start,
+            });
+        }
+
+        // Report at most one float and one nesting error per function
+        let mut float_reported = self.allow_float;
+        let mut nesting_reported = false;
+        let mut depth = 0u32;
+        let mut operators = body.get_operators_reader()?;
+        while !operators.eof() {
+            let offset = operators.original_position();
+            let operator = operators.read()?;
+            match operator {
+                Operator::Block { .. }
+                | Operator::Loop { .. }
+                | Operator::If { .. }
+                | Operator::Try { .. }
+                | Operator::TryTable { .. } => {
+                    depth += 1;
+                    if depth > self.limits.max_nesting_depth && !nesting_reported {
+                        nesting_reported = true;
+                        self.report.errors.push(ValidationError::NestingTooDeep {
+                            function_index,
+                            depth,
+                            max: self.limits.max_nesting_depth,
+                            offset,
+                        });
+                    }
+                }
+                Operator::End => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+
+            if !float_reported {
+                if let Some(name) = float_operator_name(&operator) {
+                    float_reported = true;
+                    self.report.errors.push(ValidationError::FloatingPoint {
+                        function_index,
+                        operator: name.to_string(),
+                        offset,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of `operator` if it operates on or produces floating point values.
+///
+/// Every `Operator` variant gets its own match arm from wasmparser's operator
+/// list, and whether it is a float operator is decided at compile time.
+fn float_operator_name(operator: &Operator<'_>) -> Option<&'static str> {
+    macro_rules! match_float_operators {
+        ($(@$proposal:ident $op:ident $({ $($arg:ident: $argty:ty),* })? => $visit:ident ($($ann:tt)*))*) => {
+            match operator {
+                $(Operator::$op { .. } => {
+                    const IS_FLOAT: bool = names_float_type(stringify!($op));
+                    if IS_FLOAT {
+                        Some(stringify!($op))
+                    } else {
+                        None
+                    }
+                })*
+            }
+        };
+    }
+    wasmparser::for_each_operator!(match_float_operators)
+}
+
+/// Whether an operator name has an `F32` or `F64` operand or result,
+/// including SIMD lanes (`F32x4`, `F64x2`)
+const fn names_float_type(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i + 3 <= bytes.len() {
+        if bytes[i] == b'F'
+            && ((bytes[i + 1] == b'3' && bytes[i + 2] == b'2')
+                || (bytes[i + 1] == b'6' && bytes[i + 2] == b'4'))
+        {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze_wat(wat: &str, limits: &BytecodeLimits) -> BytecodeReport {
+        analyze(&wat::parse_str(wat).unwrap(), limits, false)
+    }
+
+    #[test]
+    fn test_findings_carry_function_index_and_offset() {
+        let wat = r#"
+            (module
+              (import "env" "log" (func $log (param i32)))
+              (func $int (result i32) (i32.const 1))
+              (func $float (param f32) (result f32)
+                (f32.add (local.get 0) (local.get 0))))
+        "#;
+        let wasm = wat::parse_str(wat).unwrap();
+        let report = analyze(&wasm, &BytecodeLimits::default(), false);
+        match &report.errors[..] {
+            [ValidationError::FloatingPoint {
+                function_index,
+                operator,
+                offset,
+            }] => {
+                assert_eq!(*function_index, 2);
+                assert_eq!(operator, "F32Add");
+                // f32.add is the opcode 0x92
+                assert_eq!(wasm[*offset], 0x92);
+            }
+            other => panic!("expected one float error, got {:?}", other),
+        }
+
+        // Deterministic under NaN canonicalization
+        assert!(analyze(&wasm, &BytecodeLimits::default(), true)
+            .errors
+            .is_empty());
+    }
+
+    #[test]
+    fn test_float_operators_are_classified_by_operand_type() {
+        for (operator, expected) in [
+            (Operator::F32Add, Some("F32Add")),
+            (Operator::I32TruncF64S, Some("I32TruncF64S")),
+            (Operator::F64ReinterpretI64, Some("F64ReinterpretI64")),
+            (Operator::F32x4Sqrt, Some("F32x4Sqrt")),
+            (Operator::I32x4TruncSatF32x4S, Some("I32x4TruncSatF32x4S")),
+            (Operator::I32Add, None),
+            (Operator::I64x2Add, None),
+            (Operator::Nop, None),
+        ] {
+            assert_eq!(float_operator_name(&operator), expected);
+        }
+    }
+
+    #[test]
+    fn test_module_structure_limits() {
+        let limits = BytecodeLimits {
+            max_memory_pages: 4,
+            max_table_elements: 8,
+            max_functions: 1,
+            max_locals: 2,
+            max_nesting_depth: 1,
+            ..Default::default()
+        };
+        let report = analyze_wat(
+            r#"
+            (module
+              (memory 5)
+              (table 9 funcref)
+              (func $a (local i32 i32 i64)
+                (block (block (nop))))
+              (func $b)
+              (start $b))
+            "#,
+            &limits,
+        );
+        let errors = &report.errors;
+        assert_eq!(errors.len(), 6, "{:?}", errors);
+        assert!(matches!(
+            errors[0],
+            ValidationError::TooManyFunctions { count: 2, .. }
+        ));
+        assert!(matches!(
+            errors[1],
+            ValidationError::TableTooLarge { elements: 9, .. }
+        ));
+        assert!(matches!(
+            errors[2],
+            ValidationError::MemoryTooLarge { pages: 5, .. }
+        ));
+        assert!(matches!(
+            errors[3],
+            ValidationError::StartFunction {
+                function_index: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            errors[4],
+            ValidationError::TooManyLocals {
+                function_index: 0,
+                count: 3,
+                ..
+            }
+        ));
+        assert!(matches!(
+            errors[5],
+            ValidationError::NestingTooDeep {
+                function_index: 0,
+                depth: 2,
+                ..
+            }
+        ));
+
+        let report = analyze_wat("(module (memory 1 100000))", &BytecodeLimits::default());
+        assert!(report.errors.is_empty());
+        assert_eq!(report.warnings.len(), 1);
+    }
+}
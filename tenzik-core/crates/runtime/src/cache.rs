@@ -0,0 +1,318 @@
+//! Module Cache
+//!
+//! This module caches compiled capsules so repeated executions of the same
+//! capsule skip validation and Cranelift compilation. Modules are keyed by
+//! the Blake3 hash of the capsule bytes (the receipt `capsule_id`).
+//!
+//! The in-memory tier is a bounded LRU of `wasmtime::Module`s. The optional
+//! on-disk tier stores precompiled artifacts under a directory named after the
+//! engine's compatibility fingerprint, so changing the engine configuration or
+//! upgrading wasmtime starts a fresh cache instead of loading stale code.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use wasmtime::{Engine, Module};
+
+/// File extension used for precompiled artifacts
+const ARTIFACT_EXTENSION: &str = "cwasm";
+
+/// Cache hit/miss counters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups served from memory
+    pub memory_hits: u64,
+    /// Lookups served from the on-disk cache
+    pub disk_hits: u64,
+    /// Lookups that required compilation
+    pub misses: u64,
+    /// Modules evicted from memory to stay within capacity
+    pub evictions: u64,
+}
+
+/// LRU cache of compiled modules with an optional on-disk tier
+pub struct ModuleCache {
+    /// Maximum number of modules kept in memory
+    capacity: usize,
+    /// Compiled modules keyed by capsule hash
+    entries: HashMap<blake3::Hash, Module>,
+    /// Capsule hashes from least to most recently used
+    recency: VecDeque<blake3::Hash>,
+    /// On-disk artifact store, if configured
+    disk: Option<DiskCache>,
+    /// Hit/miss counters
+    stats: CacheStats,
+}
+
+impl ModuleCache {
+    /// Create an in-memory cache holding up to `capacity` modules
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+            disk: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Add an on-disk tier rooted at `dir` for artifacts built by `engine`
+    pub fn with_disk_cache(mut self, engine: &Engine, dir: impl Into<PathBuf>) -> Result<Self> {
+        self.disk = Some(DiskCache::new(engine, dir.into())?);
+        Ok(self)
+    }
+
+    /// Look up a module in memory, marking it most recently used
+    pub fn get(&mut self, capsule_id: &blake3::Hash) -> Option<Module> {
+        let module = self.entries.get(capsule_id)?.clone();
+        self.touch(capsule_id);
+        self.stats.memory_hits += 1;
+        Some(module)
+    }
+
+    /// Load a precompiled module from disk, if the on-disk tier has one.
+    ///
+    /// The caller is responsible for re-checking the module against its
+    /// validation policy before inserting it.
+    pub fn load_from_disk(&mut self, engine: &Engine, capsule_id: &blake3::Hash) -> Option<Module> {
+        let module = self.disk.as_ref()?.load(engine, capsule_id)?;
+        self.stats.disk_hits += 1;
+        Some(module)
+    }
+
+    /// Record that a lookup missed every tier
+    pub fn record_miss(&mut self) {
+        self.stats.misses += 1;
+    }
+
+    /// Insert a freshly compiled module into memory and, if configured, disk
+    pub fn insert(&mut self, capsule_id: blake3::Hash, module: Module) {
+        if let Some(disk) = &self.disk {
+            if let Err(e) = disk.store(&capsule_id, &module) {
+                tracing::warn!("Failed to write module cache artifact: {}", e);
+            }
+        }
+        self.insert_in_memory(capsule_id, module);
+    }
+
+    /// Insert a module into the in-memory tier only
+    pub fn insert_in_memory(&mut self, capsule_id: blake3::Hash, module: Module) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.insert(capsule_id, module).is_some() {
+            self.touch(&capsule_id);
+            return;
+        }
+        self.recency.push_back(capsule_id);
+
+        while self.entries.len() > self.capacity {
+            if let Some(oldest) = self.recency.pop_front() {
+                self.entries.remove(&oldest);
+                self.stats.evictions += 1;
+            }
+        }
+    }
+
+    /// Whether a module is held in memory
+    pub fn contains(&self, capsule_id: &blake3::Hash) -> bool {
+        self.entries.contains_key(capsule_id)
+    }
+
+    /// Number of modules held in memory
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the in-memory tier is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop every in-memory module (the on-disk tier is left intact)
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// Get hit/miss counters
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    /// Directory holding artifacts for the current engine, if any
+    pub fn disk_dir(&self) -> Option<&Path> {
+        self.disk.as_ref().map(|disk| disk.dir.as_path())
+    }
+
+    /// Move a capsule hash to the most recently used position
+    fn touch(&mut self, capsule_id: &blake3::Hash) {
+        if let Some(pos) = self.recency.iter().position(|id| id == capsule_id) {
+            self.recency.remove(pos);
+        }
+        self.recency.push_back(*capsule_id);
+    }
+}
+
+/// Directory of precompiled artifacts for one engine configuration
+struct DiskCache {
+    /// `<root>/<engine fingerprint>`
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Create the artifact directory for `engine` under `root`
+    fn new(engine: &Engine, root: PathBuf) -> Result<Self> {
+        let dir = root.join(engine_fingerprint(engine));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create module cache dir {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// Path of the artifact for a capsule
+    fn artifact_path(&self, capsule_id: &blake3::Hash) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}", capsule_id.to_hex(), ARTIFACT_EXTENSION))
+    }
+
+    /// Load an artifact, treating unreadable or corrupt files as a miss.
+    ///
+    /// Each artifact is prefixed with the Blake3 hash of its body, so a
+    /// truncated or partially written file is never handed to wasmtime.
+    fn load(&self, engine: &Engine, capsule_id: &blake3::Hash) -> Option<Module> {
+        let bytes = fs::read(self.artifact_path(capsule_id)).ok()?;
+        if bytes.len() < blake3::OUT_LEN {
+            return None;
+        }
+        let (checksum, body) = bytes.split_at(blake3::OUT_LEN);
+        if blake3::hash(body).as_bytes() != checksum {
+            return None;
+        }
+
+        // SAFETY: the body was produced by `Module::serialize` for an engine
+        // with the same compatibility fingerprint and passed its checksum.
+        // wasmtime additionally rejects artifacts from a different version or
+        // configuration. The cache directory must not be writable by
+        // untrusted parties.
+        unsafe { Module::deserialize(engine, body) }.ok()
+    }
+
+    /// Write an artifact atomically (temp file + rename)
+    fn store(&self, capsule_id: &blake3::Hash, module: &Module) -> Result<()> {
+        let body = module.serialize().context("Failed to serialize module")?;
+        let mut bytes = Vec::with_capacity(blake3::OUT_LEN + body.len());
+        bytes.extend_from_slice(blake3::hash(&body).as_bytes());
+        bytes.extend_from_slice(&body);
+
+        let path = self.artifact_path(capsule_id);
+        let tmp = path.with_extension(format!("{}.tmp", ARTIFACT_EXTENSION));
+        fs::write(&tmp, &bytes).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to rename {}", tmp.display()))?;
+        Ok(())
+    }
+}
+
+/// Fingerprint of everything that makes a precompiled artifact loadable:
+/// the wasmtime version, target and engine configuration
+fn engine_fingerprint(engine: &Engine) -> String {
+    let mut hasher = DefaultHasher::new();
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn compile(engine: &Engine, export: &str) -> (blake3::Hash, Module) {
+        let wasm = wat::parse_str(format!(r#"(module (func (export "{}")))"#, export)).unwrap();
+        (
+            blake3::hash(&wasm),
+            Module::from_binary(engine, &wasm).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let engine = Engine::default();
+        let mut cache = ModuleCache::new(2);
+
+        let (a, module_a) = compile(&engine, "a");
+        let (b, module_b) = compile(&engine, "b");
+        let (c, module_c) = compile(&engine, "c");
+
+        cache.insert(a, module_a);
+        cache.insert(b, module_b);
+        assert!(cache.get(&a).is_some()); // `b` is now least recently used
+        cache.insert(c, module_c);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&a));
+        assert!(!cache.contains(&b));
+        assert!(cache.contains(&c));
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.stats().memory_hits, 1);
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_memory_tier() {
+        let engine = Engine::default();
+        let mut cache = ModuleCache::new(0);
+
+        let (a, module_a) = compile(&engine, "a");
+        cache.insert(a, module_a);
+
+        assert!(cache.is_empty());
+        assert!(cache.get(&a).is_none());
+    }
+
+    #[test]
+    fn test_disk_round_trip_and_corruption() {
+        let engine = Engine::default();
+        let dir = TempDir::new().unwrap();
+        let (a, module_a) = compile(&engine, "a");
+
+        let mut writer = ModuleCache::new(4)
+            .with_disk_cache(&engine, dir.path())
+            .unwrap();
+        writer.insert(a, module_a);
+
+        let mut reader = ModuleCache::new(4)
+            .with_disk_cache(&engine, dir.path())
+            .unwrap();
+        let loaded = reader.load_from_disk(&engine, &a).unwrap();
+        assert!(loaded.get_export("a").is_some());
+        assert_eq!(reader.stats().disk_hits, 1);
+
+        // A corrupted artifact is ignored rather than deserialized
+        let path = reader.disk.as_ref().unwrap().artifact_path(&a);
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        assert!(reader.load_from_disk(&engine, &a).is_none());
+    }
+
+    #[test]
+    fn test_engine_config_changes_fingerprint() {
+        let default_engine = Engine::default();
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let fuel_engine = Engine::new(&config).unwrap();
+
+        assert_ne!(
+            engine_fingerprint(&default_engine),
+            engine_fingerprint(&fuel_engine)
+        );
+        assert_eq!(
+            engine_fingerprint(&default_engine),
+            engine_fingerprint(&Engine::default())
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Chunked I/O
+//!
+//! In chunked I/O mode (`ExecutionOptions::with_chunked_io`) the input is
+//! not copied into guest memory. The capsule reads it one chunk at a time
+//! with `io_read_chunk` and appends its output with `io_write_chunk`, so a
+//! run can process payloads far larger than `RuntimeConfig::max_io_size`.
+//!
+//! Input and output are split into chunks of the run's chunk size (the last
+//! chunk may be shorter) and committed as Blake3 Merkle roots in the
+//! receipt's `input_commit` and `output_commit`. [`chunk_proof`] proves that
+//! one chunk is part of a committed payload without the rest of it.
+
+use crate::merkle::{self, MerkleProof};
+
+/// Domain separation prefix of chunk leaves
+const LEAF_DOMAIN: &[u8] = b"TENZIK_CHUNK_LEAF_V1";
+
+/// Domain separation prefix of the root of an empty payload
+const EMPTY_DOMAIN: &[u8] = b"TENZIK_CHUNK_EMPTY_V1";
+
+/// Default chunk size of chunked I/O runs
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Default limit on the input and output of a chunked I/O run
+pub const MAX_CHUNKED_IO_SIZE: usize = 64 * 1024 * 1024;
+
+/// Leaf hash of one chunk
+fn leaf_hash(chunk: &[u8]) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(LEAF_DOMAIN);
+    hasher.update(&(chunk.len() as u64).to_le_bytes());
+    hasher.update(chunk);
+    hasher.finalize()
+}
+
+/// Leaf hashes of `data` split into `chunk_size` chunks
+fn leaves(data: &[u8], chunk_size: usize) -> Vec<blake3::Hash> {
+    data.chunks(chunk_size).map(leaf_hash).collect()
+}
+
+/// Hex Merkle root of `data` split into `chunk_size` chunks.
+///
+/// # Panics
+///
+/// Panics if `chunk_size` is 0.
+pub fn chunk_root(data: &[u8], chunk_size: usize) -> String {
+    match merkle::merkle_root(&leaves(data, chunk_size)) {
+        Some(root) => root.to_hex().to_string(),
+        None => blake3::hash(EMPTY_DOMAIN).to_hex().to_string(),
+    }
+}
+
+/// Number of `chunk_size` chunks `data` splits into, the leaf count of its
+/// root.
+///
+/// # Panics
+///
+/// Panics if `chunk_size` is 0.
+pub fn chunk_count(data: &[u8], chunk_size: usize) -> usize {
+    data.len().div_ceil(chunk_size)
+}
+
+/// Inclusion proof of chunk `index` of `data`, if it exists.
+///
+/// # Panics
+///
+/// Panics if `chunk_size` is 0.
+pub fn chunk_proof(data: &[u8], chunk_size: usize, index: usize) -> Option<MerkleProof> {
+    MerkleProof::build(&leaves(data, chunk_size), index)
+}
+
+/// Check that `chunk` is the chunk at `proof.index` of the payload whose
+/// hex root is `root`
+pub fn verify_chunk(root: &str, chunk: &[u8], proof: &MerkleProof) -> bool {
+    proof
+        .root(leaf_hash(chunk))
+        .is_some_and(|computed| computed.to_hex().as_str() == root)
+}
+
+/// Input and output of one chunked I/O run
+#[derive(Debug)]
+pub(crate) struct ChunkedIo {
+    input: Vec<u8>,
+    chunk_size: usize,
+    output: Vec<u8>,
+    max_output: usize,
+}
+
+impl ChunkedIo {
+    /// Serve `input` in `chunk_size` chunks and accept up to `max_output`
+    /// output bytes
+    pub(crate) fn new(input: Vec<u8>, chunk_size: usize, max_output: usize) -> Self {
+        Self {
+            input,
+            chunk_size,
+            output: Vec::new(),
+            max_output,
+        }
+    }
+
+    /// Number of input chunks
+    pub(crate) fn input_chunk_count(&self) -> usize {
+        chunk_count(&self.input, self.chunk_size)
+    }
+
+    /// Input chunk `index`, if it exists
+    pub(crate) fn input_chunk(&self, index: usize) -> Option<&[u8]> {
+        let start = index.checked_mul(self.chunk_size)?;
+        if start >= self.input.len() {
+            return None;
+        }
+        let end = self.input.len().min(start + self.chunk_size);
+        Some(&self.input[start..end])
+    }
+
+    /// Append to the output, returning whether it fits
+    pub(crate) fn write_output(&mut self, data: &[u8]) -> bool {
+        if self.output.len() + data.len() > self.max_output {
+            return false;
+        }
+        self.output.extend_from_slice(data);
+        true
+    }
+
+    /// Output written by the capsule
+    pub(crate) fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::{ExecutionError, ExecutionOptions, RuntimeConfig, WasmRuntime};
+    use crate::receipts::{
+        generate_test_signing_key, ReceiptVerifier, ReexecutionVerdict, ReplayContext,
+    };
+    use crate::sandbox::ResourceLimits;
+
+    #[test]
+    fn test_chunk_proofs_verify_against_root() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let root = chunk_root(&data, 128);
+
+        for (index, chunk) in data.chunks(128).enumerate() {
+            let proof = chunk_proof(&data, 128, index).unwrap();
+            assert!(verify_chunk(&root, chunk, &proof));
+            assert!(!verify_chunk(&root, &chunk[1..], &proof));
+        }
+        assert!(chunk_proof(&data, 128, 8).is_none());
+
+        assert_ne!(root, chunk_root(&data, 64));
+        assert_ne!(chunk_root(b"", 128), chunk_root(b"\0", 128));
+    }
+
+    #[test]
+    fn test_chunked_io_serves_chunks_and_bounds_output() {
+        let mut io = ChunkedIo::new(b"abcdefg".to_vec(), 3, 4);
+        assert_eq!(io.input_chunk_count(), 3);
+        assert_eq!(io.input_chunk(2), Some(&b"g"[..]));
+        assert_eq!(io.input_chunk(3), None);
+
+        assert!(io.write_output(b"abc"));
+        assert!(!io.write_output(b"de"));
+        assert!(io.write_output(b"d"));
+        assert_eq!(io.into_output(), b"abcd");
+    }
+
+    /// Copies every input chunk to the output, then returns an empty result
+    const CHUNKED_ECHO_WAT: &str = r#"
+        (module
+          (import "env" "io_read_chunk" (func $read (param i32 i32 i32) (result i32)))
+          (import "env" "io_write_chunk" (func $write (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (local $index i32)
+            (local $n i32)
+            (block $done
+              (loop $next
+                (local.set $n
+                  (call $read (local.get $index) (i32.const 4096) (i32.const 4096)))
+                (br_if $done (i32.lt_s (local.get $n) (i32.const 0)))
+                (drop (call $write (i32.const 4096) (local.get $n)))
+                (local.set $index (i32.add (local.get $index) (i32.const 1)))
+                (br $next)))
+            (i32.const 0)))
+    "#;
+
+    #[tokio::test]
+    async fn test_chunked_io_commits_merkle_roots() {
+        // Chunked runs may exceed the regular I/O limit
+        let config = RuntimeConfig {
+            max_io_size: 2048,
+            ..Default::default()
+        };
+        let runtime = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let capsule = wat::parse_str(CHUNKED_ECHO_WAT).unwrap();
+        let input: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        let options = ExecutionOptions::default().with_chunked_io(1024);
+
+        let result = runtime
+            .execute_with_options(&capsule, &input, ResourceLimits::default(), options)
+            .await
+            .unwrap();
+        assert_eq!(result.output, input);
+        assert_eq!(result.metrics.host_call_breakdown["io_read_chunk"], 6);
+
+        let receipt = &result.receipt;
+        assert_eq!(receipt.chunk_size, Some(1024));
+        assert_eq!(
+            (receipt.input_chunks, receipt.output_chunks),
+            (Some(5), Some(5))
+        );
+        assert_eq!(receipt.input_commit, chunk_root(&input, 1024));
+        let seed = ReplayContext::derive_random_seed(
+            &receipt.capsule_id,
+            &receipt.input_commit,
+            receipt.nonce,
+        );
+        assert_eq!(receipt.replay.random_seed_bytes().unwrap(), seed);
+        assert!(receipt.verify_node_signature().unwrap());
+        let proof = chunk_proof(&result.output, 1024, 4).unwrap();
+        assert!(receipt.verify_output_chunk(&result.output[4096..], &proof));
+        assert!(receipt.verify_input_chunk(&input[4096..], &proof));
+        assert!(!receipt.verify_output_chunk(&input[3072..4096], &proof));
+        // The proof must describe the tree the receipt signed
+        let mut reshaped = proof.clone();
+        reshaped.leaf_count = 6;
+        assert!(!receipt.verify_output_chunk(&result.output[4096..], &reshaped));
+
+        let verdict = ReceiptVerifier::default()
+            .verify_by_reexecution(&runtime, &capsule, &input, receipt, None)
+            .await
+            .unwrap();
+        assert_eq!(verdict, ReexecutionVerdict::Match);
+
+        // The chunk functions only exist in chunked I/O mode
+        assert!(runtime
+            .execute(&capsule, b"small", ResourceLimits::default())
+            .await
+            .is_err());
+        let zero = ExecutionOptions::default().with_chunked_io(0);
+        let err = runtime
+            .execute_with_options(&capsule, &input, ResourceLimits::default(), zero)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ExecutionError::IOError { .. }));
+    }
+}
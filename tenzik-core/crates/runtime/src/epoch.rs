@@ -0,0 +1,84 @@
+//! Epoch Ticker Module
+//!
+//! This module drives wasmtime's epoch-based interruption. A background
+//! thread increments the engine epoch at a fixed interval; every store gets a
+//! deadline callback that yields to the async executor on each tick and traps
+//! once the execution's wall-clock deadline has passed. This preempts tight
+//! guest loops that never return to the host.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use wasmtime::{Engine, Store, UpdateDeadline};
+
+use crate::host::HostState;
+
+/// Background thread that advances an engine's epoch
+pub(crate) struct EpochTicker {
+    /// Set to stop the thread
+    stop: Arc<AtomicBool>,
+    /// Ticker thread handle, joined on drop
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EpochTicker {
+    /// Start incrementing `engine`'s epoch every `interval`
+    pub(crate) fn start(engine: Engine, interval: Duration) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let handle = std::thread::Builder::new()
+            .name("tenzik-epoch-ticker".to_string())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(interval);
+                    engine.increment_epoch();
+                }
+            })
+            .context("Failed to spawn epoch ticker thread")?;
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Arm a store so guest code yields on every epoch tick and traps once
+/// `timeout` has elapsed from now.
+///
+/// The deadline is also stored in `HostState::deadline` for host calls that
+/// await. Every tick notes the fuel left in `HostState::fuel_remaining`.
+/// When the deadline passes, `HostState::deadline_exceeded` is set so the
+/// caller can tell a timeout apart from other traps.
+pub(crate) fn set_store_deadline(store: &mut Store<HostState>, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    store.data_mut().deadline = Some(deadline);
+
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(move |mut context| {
+        if let Ok(remaining) = context.get_fuel() {
+            context
+                .data()
+                .fuel_remaining
+                .store(remaining, Ordering::Relaxed);
+        }
+        if Instant::now() >= deadline {
+            context.data_mut().deadline_exceeded = true;
+            return Err(anyhow!("execution deadline exceeded"));
+        }
+        Ok(UpdateDeadline::Yield(1))
+    });
+}
@@ -3,24 +3,67 @@
 //! This module provides the main execution engine for Tenzik WASM capsules.
 //! It integrates validation, sandboxing, resource limits, and receipt generation.
 
-use crate::receipts::{ExecMetrics, ExecutionReceipt, ReceiptError};
-use crate::sandbox::{ResourceLimits, SecuritySandbox, SandboxError};
-use crate::validation::{WasmValidator, ValidationError, ValidationResult};
+use crate::abi::CapsuleAbi;
+use crate::bytecode::BytecodeLimits;
+use crate::cache::{CacheStats, ModuleCache};
+use crate::chunked::{self, ChunkedIo, MAX_CHUNKED_IO_SIZE};
+use crate::epoch::{self, EpochTicker};
+use crate::features::WasmFeaturePolicy;
+use crate::guest_log::{self, LogLine, DEFAULT_MAX_LOG_BYTES};
+use crate::host::{self, HostState};
+use crate::http::{self, HttpClient};
+use crate::limiter::{ExecutionLimiter, MAX_TABLE_ELEMENTS};
+use crate::metrics::{MetricsRegistry, MetricsSnapshot};
+use crate::receipts::{
+    BatchItemProof, BatchReceipt, ExecMetrics, ExecutionReceipt, ReceiptError, ReplayContext,
+};
+use crate::sandbox::{Capability, ResourceLimits, SecuritySandbox, SandboxError};
+use crate::secrets::{GrantedSecrets, MemorySecretStore, SecretStore};
+use crate::signing::CapsuleSigner;
+use crate::state::{
+    self, CapsuleState, FileStateStore, MemoryStateStore, NamespaceLocks, StateError, StateStore,
+    DEFAULT_MAX_STATE_BYTES,
+};
+use crate::transcript::{ReplayDivergence, Transcript, TranscriptMode};
+use crate::validation::{ValidationResult, ValidatorConfig, WasmValidator, ValidationError};
 
 use anyhow::{Context, Result};
-use blake3;
-use ed25519_dalek::SigningKey;
-use std::sync::Arc;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio::sync::Semaphore;
 use tokio::time::timeout;
 use wasmtime::{
-    Config, Engine, Func, Instance, Linker, Memory, MemoryType, Module, Store, TypedFunc, Val,
+    Config, Engine, InstanceAllocationStrategy, Instance, Linker, Module, PoolingAllocationConfig,
+    Store, Trap, TypedFunc, WasmBacktrace,
 };
 
 /// Maximum input/output size in bytes (1MB)
 const MAX_IO_SIZE: usize = 1024 * 1024;
 
+/// Default number of compiled modules kept in memory
+const DEFAULT_CACHE_CAPACITY: usize = 64;
+
+/// Default interval between epoch ticks in milliseconds
+const DEFAULT_EPOCH_TICK_MS: u64 = 10;
+
+/// Default number of capsules that may execute at the same time
+const DEFAULT_MAX_CONCURRENCY: usize = 8;
+
+/// Default linear memory reserved per pooled instance slot in MB
+const DEFAULT_MAX_MEMORY_MB: u32 = 64;
+
+/// Fuel consumed between async yields when fuel metering is enabled
+const FUEL_YIELD_INTERVAL: u64 = 10_000;
+
+/// Extra time the async backstop timeout allows beyond the epoch deadline,
+/// so a runaway capsule is normally stopped by the epoch path, which can
+/// report partial fuel usage
+const TIMEOUT_GRACE: Duration = Duration::from_millis(100);
+
 /// Execution errors
 #[derive(Error, Debug)]
 pub enum ExecutionError {
@@ -33,8 +76,11 @@ pub enum ExecutionError {
     #[error("WASM execution failed: {reason}")]
     ExecutionFailed { reason: String },
 
-    #[error("Timeout after {timeout_ms}ms")]
-    Timeout { timeout_ms: u64 },
+    /// `fuel_used` is exact when the epoch deadline stopped the capsule. When
+    /// only the async backstop fired, it is the fuel used as of the last host
+    /// call or epoch tick.
+    #[error("Timeout after {timeout_ms}ms ({fuel_used} fuel used)")]
+    Timeout { timeout_ms: u64, fuel_used: u64 },
 
     #[error("Resource limit exceeded: {limit_type}")]
     ResourceLimitExceeded { limit_type: String },
@@ -47,6 +93,185 @@ pub enum ExecutionError {
 
     #[error("Host function error: {function} - {reason}")]
     HostFunctionError { function: String, reason: String },
+
+    #[error("Executor queue full ({max_queue_depth} jobs waiting)")]
+    QueueFull { max_queue_depth: usize },
+
+    #[error("State error: {source}")]
+    StateError { source: StateError },
+
+    #[error("Replay diverged at {divergence}")]
+    ReplayDiverged { divergence: ReplayDivergence },
+
+    #[error("Pipeline step {step} failed: {source}")]
+    PipelineStepFailed {
+        step: usize,
+        source: Box<ExecutionError>,
+    },
+
+    #[error("Batch item {index} failed: {source}")]
+    BatchItemFailed {
+        index: usize,
+        source: Box<ExecutionError>,
+    },
+
+    #[error("Out of fuel ({fuel_used} fuel used)")]
+    OutOfFuel {
+        fuel_used: u64,
+        backtrace: Option<String>,
+    },
+
+    #[error("Out-of-bounds memory access ({fuel_used} fuel used)")]
+    MemoryOutOfBounds {
+        fuel_used: u64,
+        backtrace: Option<String>,
+    },
+
+    #[error("Unreachable instruction executed ({fuel_used} fuel used)")]
+    Unreachable {
+        fuel_used: u64,
+        backtrace: Option<String>,
+    },
+
+    #[error("Call stack exhausted ({fuel_used} fuel used)")]
+    StackOverflow {
+        fuel_used: u64,
+        backtrace: Option<String>,
+    },
+
+    #[error("Guest trapped: {trap} ({fuel_used} fuel used)")]
+    GuestTrap {
+        trap: String,
+        fuel_used: u64,
+        backtrace: Option<String>,
+    },
+
+    #[error("Host function trapped: {reason} ({fuel_used} fuel used)")]
+    HostTrap {
+        reason: String,
+        fuel_used: u64,
+        backtrace: Option<String>,
+    },
+}
+
+impl ExecutionError {
+    /// Classify an error raised while guest code was running.
+    ///
+    /// wasmtime traps map to their own variants; anything else was returned
+    /// by a host function. Fuel is filled in by `execute_module`, which
+    /// knows the fuel limit.
+    pub(crate) fn from_trap(error: anyhow::Error) -> Self {
+        let backtrace = error
+            .downcast_ref::<WasmBacktrace>()
+            .filter(|backtrace| !backtrace.frames().is_empty())
+            .map(|backtrace| backtrace.to_string());
+        let fuel_used = 0;
+
+        match error.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => ExecutionError::OutOfFuel { fuel_used, backtrace },
+            Some(Trap::MemoryOutOfBounds) => {
+                ExecutionError::MemoryOutOfBounds { fuel_used, backtrace }
+            }
+            Some(Trap::UnreachableCodeReached) => {
+                ExecutionError::Unreachable { fuel_used, backtrace }
+            }
+            Some(Trap::StackOverflow) => ExecutionError::StackOverflow { fuel_used, backtrace },
+            Some(trap) => ExecutionError::GuestTrap {
+                trap: trap.to_string(),
+                fuel_used,
+                backtrace,
+            },
+            None => ExecutionError::HostTrap {
+                reason: error.root_cause().to_string(),
+                fuel_used,
+                backtrace,
+            },
+        }
+    }
+
+    /// Record the fuel consumed before a trap
+    fn with_trap_fuel(mut self, used: u64) -> Self {
+        match &mut self {
+            ExecutionError::OutOfFuel { fuel_used, .. }
+            | ExecutionError::MemoryOutOfBounds { fuel_used, .. }
+            | ExecutionError::Unreachable { fuel_used, .. }
+            | ExecutionError::StackOverflow { fuel_used, .. }
+            | ExecutionError::GuestTrap { fuel_used, .. }
+            | ExecutionError::HostTrap { fuel_used, .. } => *fuel_used = used,
+            _ => {}
+        }
+        self
+    }
+
+    /// Fuel consumed before the capsule trapped or timed out
+    pub fn fuel_used(&self) -> Option<u64> {
+        match self {
+            ExecutionError::Timeout { fuel_used, .. }
+            | ExecutionError::OutOfFuel { fuel_used, .. }
+            | ExecutionError::MemoryOutOfBounds { fuel_used, .. }
+            | ExecutionError::Unreachable { fuel_used, .. }
+            | ExecutionError::StackOverflow { fuel_used, .. }
+            | ExecutionError::GuestTrap { fuel_used, .. }
+            | ExecutionError::HostTrap { fuel_used, .. } => Some(*fuel_used),
+            ExecutionError::PipelineStepFailed { source, .. }
+            | ExecutionError::BatchItemFailed { source, .. } => source.fuel_used(),
+            _ => None,
+        }
+    }
+
+    /// Guest backtrace of a trap, if wasmtime captured one
+    pub fn backtrace(&self) -> Option<&str> {
+        match self {
+            ExecutionError::OutOfFuel { backtrace, .. }
+            | ExecutionError::MemoryOutOfBounds { backtrace, .. }
+            | ExecutionError::Unreachable { backtrace, .. }
+            | ExecutionError::StackOverflow { backtrace, .. }
+            | ExecutionError::GuestTrap { backtrace, .. }
+            | ExecutionError::HostTrap { backtrace, .. } => backtrace.as_deref(),
+            ExecutionError::PipelineStepFailed { source, .. }
+            | ExecutionError::BatchItemFailed { source, .. } => source.backtrace(),
+            _ => None,
+        }
+    }
+
+    /// Short snake_case name of the error, used to label failure metrics.
+    /// Pipeline and batch failures report the kind of the failing step.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExecutionError::ValidationFailed { .. } => "validation_failed",
+            ExecutionError::SandboxError { .. } => "sandbox",
+            ExecutionError::ExecutionFailed { .. } => "execution_failed",
+            ExecutionError::Timeout { .. } => "timeout",
+            ExecutionError::ResourceLimitExceeded { .. } => "resource_limit_exceeded",
+            ExecutionError::IOError { .. } => "io",
+            ExecutionError::ReceiptError { .. } => "receipt",
+            ExecutionError::HostFunctionError { .. } => "host_function",
+            ExecutionError::QueueFull { .. } => "queue_full",
+            ExecutionError::StateError { .. } => "state",
+            ExecutionError::ReplayDiverged { .. } => "replay_diverged",
+            ExecutionError::PipelineStepFailed { source, .. }
+            | ExecutionError::BatchItemFailed { source, .. } => source.kind(),
+            ExecutionError::OutOfFuel { .. } => "out_of_fuel",
+            ExecutionError::MemoryOutOfBounds { .. } => "memory_out_of_bounds",
+            ExecutionError::Unreachable { .. } => "unreachable",
+            ExecutionError::StackOverflow { .. } => "stack_overflow",
+            ExecutionError::GuestTrap { .. } => "guest_trap",
+            ExecutionError::HostTrap { .. } => "host_trap",
+        }
+    }
+
+    /// Whether running the same job again could succeed.
+    ///
+    /// Traps are deterministic for a given capsule, input and limits, so
+    /// only failures caused by load are worth retrying.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ExecutionError::Timeout { .. } | ExecutionError::QueueFull { .. } => true,
+            ExecutionError::PipelineStepFailed { source, .. }
+            | ExecutionError::BatchItemFailed { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
 }
 
 /// Execution result containing output and metrics
@@ -58,6 +283,103 @@ pub struct ExecutionResult {
     pub metrics: ExecMetrics,
     /// Generated execution receipt
     pub receipt: ExecutionReceipt,
+    /// Host calls recorded or replayed during the run, if requested
+    pub transcript: Option<Transcript>,
+    /// Lines the capsule wrote with `log_write`; the receipt only commits
+    /// to their hash
+    pub logs: Vec<LogLine>,
+}
+
+/// Result of one input of a batch
+#[derive(Debug, Clone)]
+pub struct BatchItem {
+    /// Output of the capsule for this input
+    pub output: Vec<u8>,
+    /// Execution metrics of this input
+    pub metrics: ExecMetrics,
+    /// Proof that the input and output belong to the batch receipt
+    pub proof: BatchItemProof,
+}
+
+/// Result of a batch execution
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    /// One item per input, in input order
+    pub items: Vec<BatchItem>,
+    /// Signed receipt for the whole batch
+    pub receipt: BatchReceipt,
+}
+
+/// Per-execution options that pin the inputs a capsule cannot observe
+/// directly, so another node can reproduce the run exactly
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOptions {
+    /// Logical execution timestamp (Unix milliseconds) served by the `time_*`
+    /// host functions. Defaults to the wall clock when the run starts.
+    pub logical_time_ms: Option<u64>,
+    /// Seed for the `random_*` host functions. Defaults to a seed derived
+    /// from the capsule id, input commitment and receipt nonce.
+    pub random_seed: Option<[u8; 32]>,
+    /// Receipt ID of the previous pipeline step, recorded in the receipt
+    pub parent_receipt_id: Option<String>,
+    /// Record every host call and commit the transcript in the receipt
+    pub record_transcript: bool,
+    /// Check host calls against this transcript, recomputing them or
+    /// answering them from it
+    pub replay_transcript: Option<Transcript>,
+    /// Public key of the node that recorded `replay_transcript`, under whose
+    /// capsule key replayed signatures must verify. Defaults to this
+    /// runtime's key.
+    pub replay_node_key: Option<VerifyingKey>,
+    /// Chunk size of chunked I/O mode, in which the capsule streams its
+    /// input and output through host calls
+    pub chunk_size: Option<usize>,
+}
+
+impl ExecutionOptions {
+    /// Pin the logical execution timestamp
+    pub fn with_logical_time_ms(mut self, logical_time_ms: u64) -> Self {
+        self.logical_time_ms = Some(logical_time_ms);
+        self
+    }
+
+    /// Pin the random seed
+    pub fn with_random_seed(mut self, random_seed: [u8; 32]) -> Self {
+        self.random_seed = Some(random_seed);
+        self
+    }
+
+    /// Link the receipt to the receipt of the previous pipeline step
+    pub fn with_parent_receipt_id(mut self, parent_receipt_id: impl Into<String>) -> Self {
+        self.parent_receipt_id = Some(parent_receipt_id.into());
+        self
+    }
+
+    /// Record a transcript of every host call
+    pub fn with_transcript_recording(mut self) -> Self {
+        self.record_transcript = true;
+        self
+    }
+
+    /// Replay a recorded transcript, reporting the first divergence as
+    /// `ExecutionError::ReplayDiverged`
+    pub fn with_replay_transcript(mut self, transcript: Transcript) -> Self {
+        self.replay_transcript = Some(transcript);
+        self
+    }
+
+    /// Check replayed signatures against the key of the node `node_key`
+    pub fn with_replay_node_key(mut self, node_key: VerifyingKey) -> Self {
+        self.replay_node_key = Some(node_key);
+        self
+    }
+
+    /// Stream input and output in `chunk_size` chunks and commit them as
+    /// Merkle roots (see `chunked.rs`)
+    pub fn with_chunked_io(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
 }
 
 /// Runtime configuration
@@ -67,10 +389,35 @@ pub struct RuntimeConfig {
     pub enable_fuel: bool,
     /// Whether to enable compilation caching
     pub enable_cache: bool,
+    /// Maximum number of compiled modules kept in memory
+    pub cache_capacity: usize,
+    /// Directory for precompiled artifacts shared across runtime instances
+    pub cache_dir: Option<PathBuf>,
     /// Maximum input/output size
     pub max_io_size: usize,
+    /// Maximum input/output size of chunked I/O runs
+    pub max_chunked_io_size: usize,
     /// Whether to collect detailed metrics
     pub detailed_metrics: bool,
+    /// Interval between epoch ticks; bounds how far past its deadline a
+    /// capsule can run
+    pub epoch_tick_ms: u64,
+    /// Number of capsules that may execute at the same time; further
+    /// executions wait for a free instance slot
+    pub max_concurrency: usize,
+    /// Whether to preallocate instance slots with wasmtime's pooling allocator
+    pub pooling_allocator: bool,
+    /// Largest `memory_limit_mb` a capsule may request
+    pub max_memory_mb: u32,
+    /// Message bytes a capsule may write with `log_write` per run
+    pub max_log_bytes: usize,
+    /// Directory for persistent capsule state; in memory if unset
+    pub state_dir: Option<PathBuf>,
+    /// Key and value bytes one capsule's state namespace may hold
+    pub max_state_bytes: usize,
+    /// WebAssembly features and engine settings; also used by the validator
+    /// and committed in every receipt
+    pub features: WasmFeaturePolicy,
 }
 
 impl Default for RuntimeConfig {
@@ -78,67 +425,28 @@ impl Default for RuntimeConfig {
         Self {
             enable_fuel: true,
             enable_cache: true,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            cache_dir: None,
             max_io_size: MAX_IO_SIZE,
+            max_chunked_io_size: MAX_CHUNKED_IO_SIZE,
             detailed_metrics: true,
+            epoch_tick_ms: DEFAULT_EPOCH_TICK_MS,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            pooling_allocator: true,
+            max_memory_mb: DEFAULT_MAX_MEMORY_MB,
+            max_log_bytes: DEFAULT_MAX_LOG_BYTES,
+            state_dir: None,
+            max_state_bytes: DEFAULT_MAX_STATE_BYTES,
+            features: WasmFeaturePolicy::default(),
         }
     }
 }
 
-/// Host function implementation
-struct HostFunctions {
-    sandbox: Arc<SecuritySandbox>,
-}
-
-impl HostFunctions {
-    fn new(sandbox: Arc<SecuritySandbox>) -> Self {
-        Self { sandbox }
-    }
-
-    /// Blake3 hash commit function
-    fn hash_commit(&self, mut caller: wasmtime::Caller<'_, ()>, ptr: i32, len: i32) -> i32 {
-        // Implementation would read from WASM memory, compute hash, write back
-        // For now, return success (0)
-        0
-    }
-
-    /// JSON path extraction function
-    fn json_path(
-        &self,
-        mut caller: wasmtime::Caller<'_, ()>,
-        data_ptr: i32,
-        data_len: i32,
-        path_ptr: i32,
-        path_len: i32,
-    ) -> i32 {
-        // Implementation would extract JSON path and return result
-        // For now, return success (0)
-        0
-    }
-
-    /// Base64 encoding function
-    fn base64_encode(&self, mut caller: wasmtime::Caller<'_, ()>, ptr: i32, len: i32) -> i32 {
-        // Implementation would base64 encode and return result
-        // For now, return success (0)
-        0
-    }
-
-    /// Get current timestamp in milliseconds
-    fn time_now_ms(&self, mut caller: wasmtime::Caller<'_, ()>) -> i64 {
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as i64
-    }
-
-    /// Generate random bytes
-    fn random_bytes(&self, mut caller: wasmtime::Caller<'_, ()>, ptr: i32, len: i32) -> i32 {
-        // Implementation would generate deterministic random bytes
-        // For now, return success (0)
-        0
-    }
-}
-
-/// Main WASM runtime for executing capsules
+/// Main WASM runtime for executing capsules.
+///
+/// The runtime is `Send + Sync`: executions take `&self`, so one runtime can
+/// be shared behind an `Arc` and run several capsules at once. See
+/// [`crate::executor::CapsuleExecutor`] for a bounded worker pool on top.
 pub struct WasmRuntime {
     /// Wasmtime engine
     engine: Engine,
@@ -146,10 +454,27 @@ pub struct WasmRuntime {
     config: RuntimeConfig,
     /// WASM validator
     validator: WasmValidator,
+    /// Compiled modules keyed by capsule hash
+    module_cache: Mutex<ModuleCache>,
     /// Signing key for receipts
     signing_key: SigningKey,
     /// Nonce counter for receipts
-    nonce_counter: u64,
+    nonce_counter: AtomicU64,
+    /// One permit per instance slot, so concurrent executions never exhaust
+    /// the pooling allocator
+    instance_slots: Arc<Semaphore>,
+    /// Persistent capsule state, namespaced by capsule id
+    state_store: Arc<dyn StateStore>,
+    /// Serializes runs that use the same state namespace
+    state_locks: NamespaceLocks,
+    /// Client shared by the `http_request` calls of every run
+    http_client: reqwest::Client,
+    /// Node secrets capsules may be granted
+    secret_store: Arc<dyn SecretStore>,
+    /// Usage of every execution, per capsule and in total
+    metrics: MetricsRegistry,
+    /// Advances the engine epoch that preempts running capsules
+    _epoch_ticker: EpochTicker,
 }
 
 impl WasmRuntime {
@@ -162,267 +487,789 @@ impl WasmRuntime {
     pub fn with_config(signing_key: SigningKey, config: RuntimeConfig) -> Result<Self> {
         // Configure Wasmtime engine
         let mut wasmtime_config = Config::new();
-        wasmtime_config.wasm_simd(false); // Disable SIMD for smaller capsules
-        wasmtime_config.wasm_multi_value(false); // Disable multi-value
-        wasmtime_config.wasm_bulk_memory(false); // Disable bulk memory
+        config.features.apply(&mut wasmtime_config);
         wasmtime_config.consume_fuel(config.enable_fuel);
+        wasmtime_config.epoch_interruption(true);
+        wasmtime_config.async_support(true);
+
+        let max_concurrency = config.max_concurrency.max(1);
+        if config.pooling_allocator {
+            wasmtime_config.allocation_strategy(InstanceAllocationStrategy::Pooling(
+                pooling_config(max_concurrency as u32, config.max_memory_mb),
+            ));
+        }
 
         let engine = Engine::new(&wasmtime_config).context("Failed to create Wasmtime engine")?;
+        let epoch_ticker =
+            EpochTicker::start(engine.clone(), Duration::from_millis(config.epoch_tick_ms.max(1)))?;
+
+        // Validate with the execution engine so the module compiled during
+        // validation is the one that runs
+        let validator_config = ValidatorConfig {
+            features: config.features.clone(),
+            bytecode: BytecodeLimits {
+                max_memory_pages: config.max_memory_mb as u64 * 16,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let validator = WasmValidator::with_engine(engine.clone(), validator_config)
+            .context("Failed to create WASM validator")?;
 
-        let validator = WasmValidator::new().context("Failed to create WASM validator")?;
+        let capacity = if config.enable_cache {
+            config.cache_capacity
+        } else {
+            0
+        };
+        let mut module_cache = ModuleCache::new(capacity);
+        if let (true, Some(dir)) = (config.enable_cache, &config.cache_dir) {
+            module_cache = module_cache
+                .with_disk_cache(&engine, dir)
+                .context("Failed to open module cache")?;
+        }
+        let state_store: Arc<dyn StateStore> = match &config.state_dir {
+            Some(dir) => Arc::new(FileStateStore::new(dir).context("Failed to open state store")?),
+            None => Arc::new(MemoryStateStore::new()),
+        };
+        let http_client = http::build_client().context("Failed to create HTTP client")?;
 
         Ok(Self {
             engine,
             config,
             validator,
+            module_cache: Mutex::new(module_cache),
             signing_key,
-            nonce_counter: 1,
+            nonce_counter: AtomicU64::new(1),
+            instance_slots: Arc::new(Semaphore::new(max_concurrency)),
+            state_store,
+            state_locks: NamespaceLocks::default(),
+            http_client,
+            secret_store: Arc::new(MemorySecretStore::new()),
+            metrics: MetricsRegistry::default(),
+            _epoch_ticker: epoch_ticker,
         })
     }
 
+    /// Use `secret_store` for the secrets granted through
+    /// `ResourceLimits::secrets`
+    pub fn with_secret_store(mut self, secret_store: Arc<dyn SecretStore>) -> Self {
+        self.secret_store = secret_store;
+        self
+    }
+
     /// Execute a WASM capsule with the given input
     pub async fn execute(
-        &mut self,
+        &self,
         capsule_bytes: &[u8],
         input: &[u8],
         resource_limits: ResourceLimits,
     ) -> Result<ExecutionResult, ExecutionError> {
-        let start_time = Instant::now();
+        self.execute_with_options(
+            capsule_bytes,
+            input,
+            resource_limits,
+            ExecutionOptions::default(),
+        )
+        .await
+    }
 
+    /// Execute a WASM capsule with explicit execution options
+    pub async fn execute_with_options(
+        &self,
+        capsule_bytes: &[u8],
+        input: &[u8],
+        resource_limits: ResourceLimits,
+        options: ExecutionOptions,
+    ) -> Result<ExecutionResult, ExecutionError> {
+        let capsule_id = blake3::hash(capsule_bytes).to_hex();
+        let result = self.run_with_options(capsule_bytes, input, resource_limits, options).await;
+        match &result {
+            Ok(result) => self.metrics.record_success(&capsule_id, &result.metrics),
+            Err(error) => self.metrics.record_failure(&capsule_id, error),
+        }
+        result
+    }
+
+    /// Body of `execute_with_options`
+    async fn run_with_options(
+        &self,
+        capsule_bytes: &[u8],
+        input: &[u8],
+        resource_limits: ResourceLimits,
+        options: ExecutionOptions,
+    ) -> Result<ExecutionResult, ExecutionError> {
         // Validate input size
-        if input.len() > self.config.max_io_size {
+        let max_input_size = match options.chunk_size {
+            Some(chunk_size) if chunk_size == 0 || chunk_size > self.config.max_io_size => {
+                return Err(ExecutionError::IOError {
+                    reason: format!(
+                        "Invalid chunk size: {} bytes (max: {})",
+                        chunk_size, self.config.max_io_size
+                    ),
+                });
+            }
+            Some(_) => self.config.max_chunked_io_size,
+            None => self.config.max_io_size,
+        };
+        if input.len() > max_input_size {
             return Err(ExecutionError::IOError {
                 reason: format!(
                     "Input too large: {} bytes (max: {})",
                     input.len(),
-                    self.config.max_io_size
+                    max_input_size
                 ),
             });
         }
-
-        // Step 1: Validate WASM capsule
-        let validation_result = self
-            .validator
-            .validate(capsule_bytes)
-            .map_err(|e| ExecutionError::ValidationFailed {
-                source: ValidationError::InvalidModule {
-                    reason: e.to_string(),
-                },
-            })?;
-
-        if !validation_result.is_valid {
-            return Err(ExecutionError::ValidationFailed {
-                source: validation_result.errors[0].clone(),
+        if resource_limits.memory_limit_mb > self.config.max_memory_mb {
+            return Err(ExecutionError::ResourceLimitExceeded {
+                limit_type: format!(
+                    "memory: {}MB requested (runtime maximum: {}MB)",
+                    resource_limits.memory_limit_mb, self.config.max_memory_mb
+                ),
             });
         }
 
+        // Step 1: Validate and compile WASM capsule (cached by capsule hash)
+        let module = self.load_module(capsule_bytes)?;
+
         // Step 2: Set up security sandbox
         let sandbox = Arc::new(SecuritySandbox::new(resource_limits.clone()));
 
-        // Step 3: Compile WASM module
-        let module = Module::from_binary(&self.engine, capsule_bytes)
-            .map_err(|e| ExecutionError::ExecutionFailed {
-                reason: format!("Module compilation failed: {}", e),
-            })?;
+        // Step 3: Execute with timeout. The epoch deadline preempts guest
+        // code and bounds host calls that await; the async timeout is a
+        // backstop for anything else.
+        let execution_timeout =
+            Duration::from_millis(resource_limits.execution_time_ms) + TIMEOUT_GRACE;
+
+        // Fix the logical clock and random seed once per run so the receipt
+        // commits to everything the capsule observed
+        let logical_time_ms = options.logical_time_ms.unwrap_or_else(current_time_ms);
+        let nonce = self.take_nonce();
+        let random_seed = options.random_seed.unwrap_or_else(|| {
+            // Derived from the input as the receipt commits to it, which is
+            // the chunk root for chunked runs
+            let input_commit = match options.chunk_size {
+                Some(chunk_size) => chunked::chunk_root(input, chunk_size),
+                None => blake3::hash(input).to_hex().to_string(),
+            };
+            ReplayContext::derive_random_seed(
+                &blake3::hash(capsule_bytes).to_hex(),
+                &input_commit,
+                nonce,
+            )
+        });
+        let mut replay = ReplayContext::new(logical_time_ms, random_seed);
+        replay.resource_limits = Some(resource_limits.clone());
+        replay.feature_policy = self.config.features.commitment();
+        // State, HTTP responses and secrets come from outside the run and
+        // signatures need the node key, so runs that may use any of them
+        // always record a transcript for verifiers to replay. Replay then
+        // recomputes state from the recorded pre-state.
+        let record_transcript = options.record_transcript
+            || [
+                Capability::State,
+                Capability::Http,
+                Capability::Secrets,
+                Capability::Sign,
+            ]
+            .into_iter()
+            .any(|capability| resource_limits.has_capability(capability));
+        let transcript_mode = match options.replay_transcript {
+            Some(transcript) => TranscriptMode::replay(transcript),
+            None if record_transcript => TranscriptMode::Record(Transcript::default()),
+            None => TranscriptMode::Off,
+        };
+        let mut host_state = HostState::new(
+            ExecutionLimiter::new(resource_limits.memory_limit_mb),
+            logical_time_ms,
+            random_seed,
+        )
+        .with_transcript(transcript_mode)
+        .with_log_budget(self.config.max_log_bytes);
+        if resource_limits.has_capability(Capability::Http) {
+            host_state = host_state.with_http(
+                HttpClient::new(self.http_client.clone(), resource_limits.http.clone())
+                    .with_secret_headers(resource_limits.secret_headers.clone()),
+            );
+        }
+        if resource_limits.has_capability(Capability::Secrets) {
+            host_state = host_state.with_secrets(GrantedSecrets::resolve(
+                &*self.secret_store,
+                &resource_limits.secrets,
+            ));
+        }
+        if resource_limits.has_capability(Capability::Sign) {
+            let capsule_id = blake3::hash(capsule_bytes).to_hex();
+            let signer = if host_state.transcript.is_replay() {
+                let node_key = options
+                    .replay_node_key
+                    .unwrap_or_else(|| self.signing_key.verifying_key());
+                CapsuleSigner::verifier(&node_key, &capsule_id)
+            } else {
+                CapsuleSigner::derive(&self.signing_key, &capsule_id)
+            };
+            host_state = host_state.with_signer(signer);
+        }
+        // Chunked runs read their input through host calls, so `run` gets
+        // an empty one
+        let run_input = match options.chunk_size {
+            Some(chunk_size) => {
+                host_state = host_state.with_chunked_io(ChunkedIo::new(
+                    input.to_vec(),
+                    chunk_size,
+                    self.config.max_chunked_io_size,
+                ));
+                &[][..]
+            }
+            None => input,
+        };
 
-        // Step 4: Execute with timeout
-        let execution_timeout = Duration::from_millis(resource_limits.execution_time_ms);
+        // Load the capsule's state namespace and hold its lock until the
+        // new state is written back. Replayed runs start from the state
+        // recorded in the transcript and never touch the store.
+        let mut state_namespace = None;
+        let mut pre_state_root = None;
+        if resource_limits.has_capability(Capability::State) {
+            let entries = if host_state.transcript.is_replay() {
+                host_state.transcript.replayed_pre_state()
+            } else {
+                let namespace = blake3::hash(capsule_bytes).to_hex().to_string();
+                let guard = self.state_locks.lock(&namespace).await;
+                let entries = self
+                    .state_store
+                    .load(&namespace)
+                    .map_err(|e| ExecutionError::StateError { source: e })?;
+                host_state.transcript.record_pre_state(&entries);
+                state_namespace = Some((namespace, guard));
+                entries
+            };
+            pre_state_root = Some(state::state_root(&entries));
+            host_state =
+                host_state.with_state(CapsuleState::new(entries, self.config.max_state_bytes));
+        }
 
-        let execution_future = self.execute_module(module, input, sandbox.clone());
+        // Wait for a free instance slot; the deadline starts once it runs
+        let _slot = self
+            .instance_slots
+            .acquire()
+            .await
+            .map_err(|e| ExecutionError::ExecutionFailed {
+                reason: format!("Instance slots closed: {}", e),
+            })?;
 
-        let (output, exec_metrics) = match timeout(execution_timeout, execution_future).await {
-            Ok(result) => result?,
-            Err(_) => {
-                return Err(ExecutionError::Timeout {
-                    timeout_ms: resource_limits.execution_time_ms,
-                })
+        let fuel_remaining = host_state.fuel_remaining.clone();
+        let execution_future =
+            self.execute_module(module, run_input, sandbox.clone(), host_state);
+
+        let (mut output, exec_metrics, mut host_state) =
+            match timeout(execution_timeout, execution_future).await {
+                Ok(result) => result?,
+                Err(_) => {
+                    return Err(ExecutionError::Timeout {
+                        timeout_ms: resource_limits.execution_time_ms,
+                        fuel_used: self.backstop_fuel_used(&sandbox, &fuel_remaining),
+                    })
+                }
+            };
+
+        let transcript = host_state
+            .transcript
+            .finish()
+            .map_err(|divergence| ExecutionError::ReplayDiverged { divergence })?;
+        if let Some(transcript) = &transcript {
+            replay.transcript_commit = transcript.commitment();
+        }
+        if let Some(chunked_io) = host_state.chunked_io.take() {
+            if !output.is_empty() {
+                return Err(ExecutionError::IOError {
+                    reason: "Chunked I/O capsules must write their output with io_write_chunk"
+                        .to_string(),
+                });
             }
-        };
+            output = chunked_io.into_output();
+        }
+        let logs = host_state.log.into_lines();
+        let secrets_used = host_state.secrets.used_refs();
+
+        // Commit the new state only once the run has succeeded
+        let mut state_roots = None;
+        if let Some(pre_state_root) = pre_state_root {
+            let entries = host_state.state.entries();
+            let post_state_root = state::state_root(entries);
+            if let Some((namespace, _guard)) = state_namespace {
+                if post_state_root != pre_state_root {
+                    self.state_store
+                        .store(&namespace, entries)
+                        .map_err(|e| ExecutionError::StateError { source: e })?;
+                }
+            }
+            state_roots = Some((pre_state_root, post_state_root));
+        }
 
-        // Step 5: Generate execution receipt
-        let receipt = ExecutionReceipt::new(
+        // Step 4: Generate execution receipt, signed once all of its fields
+        // are known
+        let mut receipt = ExecutionReceipt::builder(
             capsule_bytes,
             input,
             &output,
             exec_metrics.clone(),
-            &self.signing_key,
-            self.nonce_counter,
-        )
-        .map_err(|e| ExecutionError::ReceiptError { source: e })?;
-
-        self.nonce_counter += 1;
+            replay,
+            nonce,
+        );
+        if let Some(chunk_size) = options.chunk_size {
+            receipt = receipt.chunked_io(chunk_size, input, &output);
+        }
+        if !logs.is_empty() {
+            receipt = receipt.log_commit(guest_log::log_commitment(&logs));
+        }
+        if let Some((pre_state_root, post_state_root)) = state_roots {
+            receipt = receipt.state_roots(pre_state_root, post_state_root);
+        }
+        if !secrets_used.is_empty() {
+            receipt = receipt.secrets_used(secrets_used);
+        }
+        if let Some(parent_receipt_id) = options.parent_receipt_id {
+            receipt = receipt.parent_receipt_id(parent_receipt_id);
+        }
+        let receipt = receipt.finish(&self.signing_key);
 
         Ok(ExecutionResult {
             output,
             metrics: exec_metrics,
             receipt,
+            transcript,
+            logs,
         })
     }
 
-    /// Execute a compiled WASM module
+    /// Run a capsule on every input of a batch with one compiled module and
+    /// sign a single `BatchReceipt` committing to all inputs and outputs.
+    ///
+    /// Items run in order under `resource_limits`, with the batch's logical
+    /// clock and a random seed derived per item
+    /// (`BatchReceipt::item_random_seed`). Capabilities whose effects only a
+    /// per-run receipt records (state, HTTP, secrets, signing and logs) are
+    /// rejected. Stops at the first failing item and reports it as
+    /// `ExecutionError::BatchItemFailed` with the zero-based item index.
+    ///
+    /// Every successful item counts as one execution in `metrics`, and a
+    /// failed batch as one failed execution.
+    pub async fn execute_batch<I: AsRef<[u8]>>(
+        &self,
+        capsule_bytes: &[u8],
+        inputs: &[I],
+        resource_limits: ResourceLimits,
+    ) -> Result<BatchResult, ExecutionError> {
+        let capsule_id = blake3::hash(capsule_bytes).to_hex();
+        let result = self.run_batch(&capsule_id, capsule_bytes, inputs, resource_limits).await;
+        if let Err(error) = &result {
+            self.metrics.record_failure(&capsule_id, error);
+        }
+        result
+    }
+
+    /// Body of `execute_batch`, recording the metrics of every item
+    async fn run_batch<I: AsRef<[u8]>>(
+        &self,
+        capsule_id: &str,
+        capsule_bytes: &[u8],
+        inputs: &[I],
+        resource_limits: ResourceLimits,
+    ) -> Result<BatchResult, ExecutionError> {
+        if inputs.is_empty() {
+            return Err(ExecutionError::ExecutionFailed {
+                reason: "Batch has no inputs".to_string(),
+            });
+        }
+        let per_run_capabilities = [
+            Capability::Log,
+            Capability::State,
+            Capability::Http,
+            Capability::Secrets,
+            Capability::Sign,
+        ];
+        if let Some(capability) = per_run_capabilities
+            .into_iter()
+            .find(|&capability| resource_limits.has_capability(capability))
+        {
+            return Err(ExecutionError::ExecutionFailed {
+                reason: format!("Capability {:?} cannot be used in a batch", capability),
+            });
+        }
+        if resource_limits.memory_limit_mb > self.config.max_memory_mb {
+            return Err(ExecutionError::ResourceLimitExceeded {
+                limit_type: format!(
+                    "memory: {}MB requested (runtime maximum: {}MB)",
+                    resource_limits.memory_limit_mb, self.config.max_memory_mb
+                ),
+            });
+        }
+
+        let module = self.load_module(capsule_bytes)?;
+        let sandbox = Arc::new(SecuritySandbox::new(resource_limits.clone()));
+        let execution_timeout =
+            Duration::from_millis(resource_limits.execution_time_ms) + TIMEOUT_GRACE;
+
+        let inputs: Vec<&[u8]> = inputs.iter().map(AsRef::as_ref).collect();
+        let logical_time_ms = current_time_ms();
+        let nonce = self.take_nonce();
+        let mut inputs_hasher = blake3::Hasher::new();
+        for input in &inputs {
+            inputs_hasher.update(blake3::hash(input).as_bytes());
+        }
+        let batch_seed = ReplayContext::derive_random_seed(
+            capsule_id,
+            &inputs_hasher.finalize().to_hex(),
+            nonce,
+        );
+
+        let mut metrics = ExecMetrics::default();
+        let mut runs = Vec::with_capacity(inputs.len());
+        for (index, input) in inputs.iter().enumerate() {
+            let item_failed = |source| ExecutionError::BatchItemFailed {
+                index,
+                source: Box::new(source),
+            };
+            if input.len() > self.config.max_io_size {
+                return Err(item_failed(ExecutionError::IOError {
+                    reason: format!(
+                        "Input too large: {} bytes (max: {})",
+                        input.len(),
+                        self.config.max_io_size
+                    ),
+                }));
+            }
+
+            let host_state = HostState::new(
+                ExecutionLimiter::new(resource_limits.memory_limit_mb),
+                logical_time_ms,
+                BatchReceipt::derive_item_seed(&batch_seed, index as u64),
+            );
+            // Each item takes its own instance slot so other executions
+            // can interleave with a long batch
+            let run = async {
+                let _slot = self.instance_slots.acquire().await.map_err(|e| {
+                    ExecutionError::ExecutionFailed {
+                        reason: format!("Instance slots closed: {}", e),
+                    }
+                })?;
+                let fuel_remaining = host_state.fuel_remaining.clone();
+                let execution_future =
+                    self.execute_module(module.clone(), input, sandbox.clone(), host_state);
+                match timeout(execution_timeout, execution_future).await {
+                    Ok(result) => result,
+                    Err(_) => Err(ExecutionError::Timeout {
+                        timeout_ms: resource_limits.execution_time_ms,
+                        fuel_used: self.backstop_fuel_used(&sandbox, &fuel_remaining),
+                    }),
+                }
+            };
+            let (output, item_metrics, _) = run.await.map_err(item_failed)?;
+            self.metrics.record_success(capsule_id, &item_metrics);
+            metrics.accumulate(&item_metrics);
+            runs.push((output, item_metrics));
+        }
+
+        let mut replay = ReplayContext::new(logical_time_ms, batch_seed);
+        replay.resource_limits = Some(resource_limits);
+        replay.feature_policy = self.config.features.commitment();
+        let outputs: Vec<&[u8]> = runs.iter().map(|(output, _)| output.as_slice()).collect();
+        let (receipt, proofs) = BatchReceipt::new(
+            capsule_bytes,
+            &inputs,
+            &outputs,
+            metrics,
+            replay,
+            &self.signing_key,
+            nonce,
+        )
+        .map_err(|e| ExecutionError::ReceiptError { source: e })?;
+
+        let items = runs
+            .into_iter()
+            .zip(proofs)
+            .map(|((output, metrics), proof)| BatchItem {
+                output,
+                metrics,
+                proof,
+            })
+            .collect();
+        Ok(BatchResult { items, receipt })
+    }
+
+    /// Get a validated, compiled module for the capsule.
+    ///
+    /// Modules are looked up in memory, then on disk, and only validated and
+    /// compiled on a miss. Only modules that passed validation are cached.
+    ///
+    /// The cache lock is not held while compiling, so concurrent misses for
+    /// the same capsule may both compile it; the second insert is a no-op.
+    fn load_module(&self, capsule_bytes: &[u8]) -> Result<Module, ExecutionError> {
+        let capsule_id = blake3::hash(capsule_bytes);
+
+        if let Some(module) = self.cache().get(&capsule_id) {
+            return Ok(module);
+        }
+
+        // Artifacts on disk may come from a runtime with a different
+        // validation policy, so re-check them before use
+        let from_disk = self.cache().load_from_disk(&self.engine, &capsule_id);
+        if let Some(module) = from_disk {
+            let validation_result = self
+                .validator
+                .validate_module(capsule_bytes, &module)
+                .map_err(Self::validation_error)?;
+            Self::ensure_valid(&validation_result)?;
+            self.cache().insert_in_memory(capsule_id, module.clone());
+            return Ok(module);
+        }
+
+        self.cache().record_miss();
+        let (validation_result, module) = self
+            .validator
+            .validate_and_compile(capsule_bytes)
+            .map_err(Self::validation_error)?;
+        Self::ensure_valid(&validation_result)?;
+
+        let module = module.ok_or_else(|| ExecutionError::ExecutionFailed {
+            reason: "Validation produced no compiled module".to_string(),
+        })?;
+        self.cache().insert(capsule_id, module.clone());
+        Ok(module)
+    }
+
+    /// Lock the module cache. A panic while holding the lock cannot leave
+    /// the cache inconsistent, so a poisoned lock is recovered.
+    fn cache(&self) -> MutexGuard<'_, ModuleCache> {
+        self.module_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Wrap an unexpected validator failure
+    fn validation_error(error: anyhow::Error) -> ExecutionError {
+        ExecutionError::ValidationFailed {
+            source: ValidationError::InvalidModule {
+                reason: error.to_string(),
+            },
+        }
+    }
+
+    /// Surface the first validation error of a failed result
+    fn ensure_valid(validation_result: &ValidationResult) -> Result<(), ExecutionError> {
+        if validation_result.is_valid {
+            return Ok(());
+        }
+        Err(ExecutionError::ValidationFailed {
+            source: validation_result.errors[0].clone(),
+        })
+    }
+
+    /// Execute a compiled WASM module, returning the host state so the
+    /// caller can collect the transcript
     async fn execute_module(
         &self,
         module: Module,
         input: &[u8],
         sandbox: Arc<SecuritySandbox>,
-    ) -> Result<(Vec<u8>, ExecMetrics), ExecutionError> {
+        host_state: HostState,
+    ) -> Result<(Vec<u8>, ExecMetrics, HostState), ExecutionError> {
         let start_time = Instant::now();
 
-        // Create store with fuel if enabled
-        let mut store = Store::new(&self.engine, ());
+        // Create store with fuel if enabled. The host state's limiter
+        // enforces memory and table limits and tracks peak usage.
+        let mut store = Store::new(&self.engine, host_state);
+        store.limiter(|state| &mut state.limiter);
         if self.config.enable_fuel {
             store
-                .add_fuel(sandbox.resource_limits().fuel_limit)
+                .set_fuel(sandbox.resource_limits().fuel_limit)
                 .map_err(|e| ExecutionError::ExecutionFailed {
                     reason: format!("Failed to add fuel: {}", e),
                 })?;
+            store
+                .data()
+                .fuel_remaining
+                .store(sandbox.resource_limits().fuel_limit, Ordering::Relaxed);
+            // Compiled code only writes its fuel counter back to the store at
+            // calls and yields; yielding periodically keeps the count reported
+            // for a preempted capsule accurate
+            store
+                .fuel_async_yield_interval(Some(FUEL_YIELD_INTERVAL))
+                .map_err(|e| ExecutionError::ExecutionFailed {
+                    reason: format!("Failed to set fuel yield interval: {}", e),
+                })?;
         }
 
-        // Set memory limits
-        store.limiter(|_| {
-            wasmtime::ResourceLimiterAsync::new(
-                sandbox.resource_limits().memory_limit_mb as usize * 1024 * 1024, // Convert MB to bytes
-                1000, // Max table elements
-                10,   // Max instances
-                1000, // Max tables
-                1000, // Max memories
-            )
-        });
+        // Preempt guest code once the execution time limit has passed
+        let timeout_ms = sandbox.resource_limits().execution_time_ms;
+        epoch::set_store_deadline(&mut store, Duration::from_millis(timeout_ms));
 
-        // Create linker with host functions
+        // Create linker with host functions based on capabilities
         let mut linker = Linker::new(&self.engine);
+        host::link_host_functions(&mut linker, &sandbox)?;
+        if store.data().chunked_io.is_some() {
+            host::link_chunked_io(&mut linker)?;
+        }
 
-        // Add host functions based on capabilities
-        let host_functions = HostFunctions::new(sandbox.clone());
+        let output = match self
+            .run_instance(&mut store, &linker, &module, input)
+            .await
+        {
+            Ok(result) => result,
+            Err(_) if store.data().deadline_exceeded => {
+                return Err(ExecutionError::Timeout {
+                    timeout_ms,
+                    fuel_used: self.fuel_used(&store, &sandbox),
+                })
+            }
+            Err(e) => {
+                let state = store.data();
+                if let Some(divergence) = state.transcript.divergence() {
+                    return Err(ExecutionError::ReplayDiverged {
+                        divergence: divergence.clone(),
+                    });
+                }
+                return Err(match state.limiter.exceeded() {
+                    Some(limit_type) => ExecutionError::ResourceLimitExceeded {
+                        limit_type: limit_type.to_string(),
+                    },
+                    None => e.with_trap_fuel(self.fuel_used(&store, &sandbox)),
+                });
+            }
+        };
 
-        if sandbox.has_capability(crate::sandbox::Capability::Hash) {
-            linker
-                .func_wrap(
-                    "env",
-                    "hash_commit",
-                    |caller: wasmtime::Caller<'_, ()>, ptr: i32, len: i32| -> i32 { 0 },
-                )
-                .map_err(|e| ExecutionError::ExecutionFailed {
-                    reason: format!("Failed to link hash_commit: {}", e),
-                })?;
-        }
+        // Collect execution metrics
+        let duration = start_time.elapsed();
+        let limiter = &store.data().limiter;
 
-        if sandbox.has_capability(crate::sandbox::Capability::Json) {
-            linker
-                .func_wrap(
-                    "env",
-                    "json_path",
-                    |caller: wasmtime::Caller<'_, ()>,
-                     data_ptr: i32,
-                     data_len: i32,
-                     path_ptr: i32,
-                     path_len: i32|
-                     -> i32 { 0 },
-                )
-                .map_err(|e| ExecutionError::ExecutionFailed {
-                    reason: format!("Failed to link json_path: {}", e),
-                })?;
-        }
+        let metrics = ExecMetrics {
+            fuel_used: self.fuel_used(&store, &sandbox),
+            memory_mb: limiter.peak_memory_bytes() as f64 / (1024.0 * 1024.0),
+            table_elements: limiter.peak_table_elements() as u32,
+            duration_ms: duration.as_millis() as u64,
+            host_function_calls: store.data().total_host_calls(),
+            host_call_breakdown: store.data().host_call_breakdown(),
+        };
 
-        if sandbox.has_capability(crate::sandbox::Capability::Time) {
-            linker
-                .func_wrap("env", "time_now_ms", |caller: wasmtime::Caller<'_, ()>| -> i64 {
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_millis() as i64
-                })
-                .map_err(|e| ExecutionError::ExecutionFailed {
-                    reason: format!("Failed to link time_now_ms: {}", e),
-                })?;
-        }
+        Ok((output, metrics, store.into_data()))
+    }
 
+    /// Instantiate the module, pass the input through the capsule's ABI and
+    /// call `run`, returning the output
+    async fn run_instance(
+        &self,
+        store: &mut Store<HostState>,
+        linker: &Linker<HostState>,
+        module: &Module,
+        input: &[u8],
+    ) -> Result<Vec<u8>, ExecutionError> {
         // Instantiate the module
-        let instance = linker
-            .instantiate_async(&mut store, &module)
+        let instance: Instance = linker
+            .instantiate_async(&mut *store, module)
             .await
-            .map_err(|e| ExecutionError::ExecutionFailed {
-                reason: format!("Module instantiation failed: {}", e),
+            .map_err(|e| {
+                // A trapping start function is classified like any other trap
+                if e.is::<Trap>() {
+                    ExecutionError::from_trap(e)
+                } else {
+                    ExecutionError::ExecutionFailed {
+                        reason: format!("Module instantiation failed: {}", e),
+                    }
+                }
             })?;
 
         // Get the main function and memory
         let run_func: TypedFunc<(i32, i32), i32> = instance
-            .get_typed_func(&mut store, "run")
+            .get_typed_func(&mut *store, "run")
             .map_err(|e| ExecutionError::ExecutionFailed {
                 reason: format!("Failed to get 'run' function: {}", e),
             })?;
 
         let memory = instance
-            .get_memory(&mut store, "memory")
+            .get_memory(&mut *store, "memory")
             .ok_or_else(|| ExecutionError::ExecutionFailed {
                 reason: "Module missing 'memory' export".to_string(),
             })?;
 
-        // Write input to WASM memory
-        let input_ptr = 1024; // Start at 1KB offset
-        if input_ptr + input.len() > memory.data_size(&store) {
-            return Err(ExecutionError::ExecutionFailed {
-                reason: "Input too large for WASM memory".to_string(),
-            });
-        }
-
-        memory
-            .write(&mut store, input_ptr, input)
-            .map_err(|e| ExecutionError::ExecutionFailed {
-                reason: format!("Failed to write input to memory: {}", e),
-            })?;
+        // Write input to WASM memory using the capsule's ABI
+        let abi = CapsuleAbi::detect(module);
+        let input_ptr = abi.write_input(store, &instance, memory, input).await?;
 
         // Execute the function
         let result = run_func
-            .call_async(&mut store, (input_ptr as i32, input.len() as i32))
+            .call_async(&mut *store, (input_ptr, input.len() as i32))
             .await
-            .map_err(|e| ExecutionError::ExecutionFailed {
-                reason: format!("Function execution failed: {}", e),
-            })?;
-
-        // Extract output from result (encoded as length in high bits, ptr in low bits)
-        let output_len = (result >> 16) as usize;
-        let output_ptr = (result & 0xFFFF) as usize;
-
-        if output_len > self.config.max_io_size {
-            return Err(ExecutionError::IOError {
-                reason: format!(
-                    "Output too large: {} bytes (max: {})",
-                    output_len, self.config.max_io_size
-                ),
-            });
-        }
+            .map_err(ExecutionError::from_trap)?;
 
         // Read output from WASM memory
-        let mut output = vec![0u8; output_len];
-        memory
-            .read(&store, output_ptr, &mut output)
-            .map_err(|e| ExecutionError::ExecutionFailed {
-                reason: format!("Failed to read output from memory: {}", e),
-            })?;
+        let output = abi
+            .read_output(store, &instance, memory, result, self.config.max_io_size)
+            .await?;
 
-        // Collect execution metrics
-        let duration = start_time.elapsed();
-        let fuel_used = if self.config.enable_fuel {
-            sandbox.resource_limits().fuel_limit
-                - store.fuel_remaining().unwrap_or(0)
+        Ok(output)
+    }
+
+    /// Fuel consumed so far by a store
+    fn fuel_used(&self, store: &Store<HostState>, sandbox: &SecuritySandbox) -> u64 {
+        if self.config.enable_fuel {
+            sandbox.resource_limits().fuel_limit - store.get_fuel().unwrap_or(0)
         } else {
             0
-        };
-
-        let metrics = ExecMetrics {
-            fuel_used,
-            memory_mb: memory.data_size(&store) as f64 / (1024.0 * 1024.0),
-            duration_ms: duration.as_millis() as u64,
-            host_function_calls: 0, // TODO: Track from sandbox access log
-        };
+        }
+    }
 
-        Ok((output, metrics))
+    /// Fuel consumed by a run the async backstop stopped, as of its last
+    /// host call or epoch tick
+    fn backstop_fuel_used(&self, sandbox: &SecuritySandbox, fuel_remaining: &AtomicU64) -> u64 {
+        if self.config.enable_fuel {
+            sandbox
+                .resource_limits()
+                .fuel_limit
+                .saturating_sub(fuel_remaining.load(Ordering::Relaxed))
+        } else {
+            0
+        }
     }
 
     /// Get the next nonce value
     pub fn next_nonce(&self) -> u64 {
-        self.nonce_counter
+        self.nonce_counter.load(Ordering::Relaxed)
+    }
+
+    /// Reserve the next receipt nonce
+    pub(crate) fn take_nonce(&self) -> u64 {
+        self.nonce_counter.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Key used to sign receipts
+    pub(crate) fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Store holding every capsule's persistent state
+    pub fn state_store(&self) -> &dyn StateStore {
+        self.state_store.as_ref()
+    }
+
+    /// Snapshot of the usage of every execution so far, per capsule and in
+    /// total. `MetricsSnapshot::to_prometheus` renders it for scraping.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Get module cache hit/miss counters
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache().stats().clone()
+    }
+
+    /// Drop every compiled module held in memory
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    /// Get the runtime configuration
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.config
     }
 
     /// Get the runtime's public key
@@ -431,16 +1278,41 @@ impl WasmRuntime {
     }
 }
 
-/// Execution metrics for monitoring and optimization
-#[derive(Debug, Clone)]
+/// Pooling allocator sized for `slots` concurrent capsule instances, each
+/// with up to `max_memory_mb` of linear memory
+fn pooling_config(slots: u32, max_memory_mb: u32) -> PoolingAllocationConfig {
+    let mut pooling = PoolingAllocationConfig::default();
+    pooling
+        .total_core_instances(slots)
+        .total_memories(slots)
+        .total_tables(slots)
+        .total_stacks(slots)
+        .max_memory_size(max_memory_mb as usize * 1024 * 1024)
+        .table_elements(MAX_TABLE_ELEMENTS);
+    pooling
+}
+
+/// Current wall-clock time in Unix milliseconds
+fn current_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Execution metrics for monitoring and optimization, aggregated over every
+/// execution of a runtime (see `WasmRuntime::metrics`)
+#[derive(Debug, Clone, PartialEq)]
 pub struct ExecutionMetrics {
-    /// Number of executions performed
+    /// Number of executions performed, including failed ones
     pub total_executions: u64,
-    /// Average execution time in milliseconds
+    /// Number of executions that returned an error
+    pub failed_executions: u64,
+    /// Average execution time of successful executions in milliseconds
     pub avg_execution_time_ms: f64,
     /// Peak memory usage across all executions
     pub peak_memory_mb: f64,
-    /// Total fuel consumed
+    /// Total fuel consumed, including by failed executions
     pub total_fuel_used: u64,
 }
 
@@ -448,6 +1320,7 @@ impl Default for ExecutionMetrics {
     fn default() -> Self {
         Self {
             total_executions: 0,
+            failed_executions: 0,
             avg_execution_time_ms: 0.0,
             peak_memory_mb: 0.0,
             total_fuel_used: 0,
@@ -459,7 +1332,6 @@ impl Default for ExecutionMetrics {
 mod tests {
     use super::*;
     use crate::receipts::generate_test_signing_key;
-    use crate::sandbox::Capability;
 
     fn create_minimal_wasm() -> Vec<u8> {
         // A minimal WASM module that exports 'run' and 'memory'
@@ -470,6 +1342,19 @@ mod tests {
         ]
     }
 
+    /// A capsule that echoes its input back
+    fn create_echo_wasm() -> Vec<u8> {
+        wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                (i32.or (i32.shl (local.get $len) (i32.const 16)) (local.get $ptr))))
+            "#,
+        )
+        .unwrap()
+    }
+
     #[tokio::test]
     async fn test_runtime_creation() {
         let signing_key = generate_test_signing_key();
@@ -480,7 +1365,7 @@ mod tests {
     #[tokio::test]
     async fn test_input_size_validation() {
         let signing_key = generate_test_signing_key();
-        let mut runtime = WasmRuntime::new(signing_key).unwrap();
+        let runtime = WasmRuntime::new(signing_key).unwrap();
 
         let large_input = vec![0u8; MAX_IO_SIZE + 1];
         let capsule = create_minimal_wasm();
@@ -490,13 +1375,322 @@ mod tests {
         assert!(matches!(result, Err(ExecutionError::IOError { .. })));
     }
 
+    #[tokio::test]
+    async fn test_module_cache_reuses_compiled_module() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = create_echo_wasm();
+
+        for input in [&b"one"[..], b"two", b"three"] {
+            let result = runtime
+                .execute(&capsule, input, ResourceLimits::default())
+                .await
+                .unwrap();
+            assert_eq!(result.output, input);
+        }
+
+        assert_eq!(runtime.cache_stats().misses, 1);
+        assert_eq!(runtime.cache_stats().memory_hits, 2);
+
+        runtime.clear_cache();
+        runtime
+            .execute(&capsule, b"four", ResourceLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(runtime.cache_stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_module_cache_disabled() {
+        let config = RuntimeConfig {
+            enable_cache: false,
+            ..Default::default()
+        };
+        let runtime = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let capsule = create_echo_wasm();
+
+        for _ in 0..2 {
+            runtime
+                .execute(&capsule, b"in", ResourceLimits::default())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(runtime.cache_stats().memory_hits, 0);
+        assert_eq!(runtime.cache_stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_shared_between_runtimes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = RuntimeConfig {
+            cache_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let capsule = create_echo_wasm();
+
+        let first = WasmRuntime::with_config(generate_test_signing_key(), config.clone()).unwrap();
+        first
+            .execute(&capsule, b"in", ResourceLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(first.cache_stats().misses, 1);
+
+        let second = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let result = second
+            .execute(&capsule, b"in", ResourceLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(result.output, b"in");
+        assert_eq!(second.cache_stats().disk_hits, 1);
+        assert_eq!(second.cache_stats().misses, 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_capsule_is_not_cached() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = wat::parse_str(r#"(module (func (export "run")))"#).unwrap();
+
+        for _ in 0..2 {
+            let result = runtime
+                .execute(&capsule, b"", ResourceLimits::default())
+                .await;
+            assert!(matches!(
+                result,
+                Err(ExecutionError::ValidationFailed { .. })
+            ));
+        }
+        assert_eq!(runtime.cache_stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_items_are_provable_against_receipt() {
+        let signing_key = generate_test_signing_key();
+        let verifying_key = signing_key.verifying_key();
+        let runtime = WasmRuntime::new(signing_key).unwrap();
+        let capsule = create_echo_wasm();
+        let inputs = [&b"one"[..], b"two", b"three"];
+
+        let batch = runtime
+            .execute_batch(&capsule, &inputs, ResourceLimits::default())
+            .await
+            .unwrap();
+        let receipt = &batch.receipt;
+        assert_eq!(receipt.item_count, 3);
+        assert!(receipt.verify(&verifying_key).unwrap());
+        assert_eq!(runtime.cache_stats().misses, 1);
+
+        for (input, item) in inputs.iter().zip(&batch.items) {
+            assert_eq!(item.output, *input);
+            assert!(receipt.verify_item(input, &item.output, &item.proof));
+            assert!(!receipt.verify_item(input, b"forged", &item.proof));
+        }
+        assert!(!receipt.verify_item(b"one", b"one", &batch.items[1].proof));
+        assert_ne!(
+            receipt.item_random_seed(0).unwrap(),
+            receipt.item_random_seed(1).unwrap()
+        );
+
+        let empty: [&[u8]; 0] = [];
+        let result = runtime.execute_batch(&capsule, &empty, ResourceLimits::default()).await;
+        assert!(matches!(result, Err(ExecutionError::ExecutionFailed { .. })));
+
+        let large_input = vec![0u8; MAX_IO_SIZE + 1];
+        let inputs = [&b"ok"[..], &large_input];
+        let result = runtime.execute_batch(&capsule, &inputs, ResourceLimits::default()).await;
+        match result {
+            Err(ExecutionError::BatchItemFailed { index, source }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*source, ExecutionError::IOError { .. }));
+            }
+            other => panic!("expected failed batch item, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runaway_capsule_is_preempted() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "run") (param i32 i32) (result i32)
+                (loop (br 0))
+                (i32.const 0)))
+            "#,
+        )
+        .unwrap();
+        let limits = ResourceLimits {
+            execution_time_ms: 100,
+            fuel_limit: 1 << 40,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let result = runtime.execute(&capsule, b"", limits).await;
+
+        match result {
+            Err(ExecutionError::Timeout {
+                timeout_ms,
+                fuel_used,
+            }) => {
+                assert_eq!(timeout_ms, 100);
+                assert!(fuel_used > 0);
+            }
+            other => panic!("expected timeout, got {:?}", other.map(|r| r.output)),
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_backstop_timeout_reports_fuel_from_last_host_call() {
+        // Epoch ticks are too slow to stop the capsule, so the async
+        // backstop fires first and cannot read the store
+        let config = RuntimeConfig {
+            epoch_tick_ms: 1_000,
+            ..Default::default()
+        };
+        let runtime = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let capsule = wat::parse_str(
+            r#"
+            (module
+              (import "env" "hash_commit" (func $hash (param i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (func (export "run") (param i32 i32) (result i32)
+                (loop (drop (call $hash (i32.const 0) (i32.const 64) (i32.const 64))) (br 0))
+                (i32.const 0)))
+            "#,
+        )
+        .unwrap();
+        let limits = ResourceLimits {
+            execution_time_ms: 50,
+            fuel_limit: 1 << 40,
+            ..Default::default()
+        };
+
+        match runtime.execute(&capsule, b"", limits).await {
+            Err(ExecutionError::Timeout {
+                timeout_ms,
+                fuel_used,
+            }) => {
+                assert_eq!(timeout_ms, 50);
+                assert!(fuel_used > 0);
+            }
+            other => panic!("expected timeout, got {:?}", other.map(|r| r.output)),
+        }
+    }
+
+    /// Capsule that grows its memory by the page count in the first input byte
+    fn create_grow_wasm() -> Vec<u8> {
+        wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                (drop (memory.grow (i32.load8_u (local.get $ptr))))
+                (i32.const 0)))
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_peak_memory_reported_in_metrics() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let result = runtime
+            .execute(&create_grow_wasm(), &[15], ResourceLimits::default())
+            .await
+            .unwrap();
+
+        // 1 initial page + 15 grown pages of 64KB
+        assert_eq!(result.metrics.memory_mb, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_growth_past_limit() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let limits = ResourceLimits {
+            memory_limit_mb: 1,
+            ..Default::default()
+        };
+        let result = runtime.execute(&create_grow_wasm(), &[16], limits).await;
+
+        match result {
+            Err(ExecutionError::ResourceLimitExceeded { limit_type }) => {
+                assert!(limit_type.starts_with("memory"));
+            }
+            other => panic!("expected limit error, got {:?}", other.map(|r| r.output)),
+        }
+    }
+
+    /// Run a capsule whose `run` calls a `$crash` function with `body`
+    async fn run_trap(body: &str, fuel_limit: u64) -> ExecutionError {
+        let capsule = wat::parse_str(format!(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func $crash (param i32) (result i32) {})
+              (func (export "run") (param i32 i32) (result i32)
+                (call $crash (local.get 1))))
+            "#,
+            body
+        ))
+        .unwrap();
+        let limits = ResourceLimits {
+            fuel_limit,
+            ..Default::default()
+        };
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        match runtime.execute(&capsule, b"", limits).await {
+            Err(e) => e,
+            Ok(_) => panic!("expected a trap"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_traps_are_classified() {
+        let error = run_trap("unreachable", 1_000_000).await;
+        assert!(matches!(error, ExecutionError::Unreachable { .. }));
+        assert!(error.fuel_used().unwrap() > 0);
+        assert!(error.backtrace().unwrap().contains("crash"));
+        assert!(!error.is_retryable());
+
+        let error = run_trap("(i32.load (i32.const -4))", 1_000_000).await;
+        assert!(matches!(error, ExecutionError::MemoryOutOfBounds { .. }));
+
+        let error = run_trap("(call $crash (local.get 0))", 1 << 40).await;
+        assert!(matches!(error, ExecutionError::StackOverflow { .. }));
+
+        let error = run_trap("(loop (br 0)) (i32.const 0)", 10_000).await;
+        match error {
+            ExecutionError::OutOfFuel { fuel_used, .. } => assert_eq!(fuel_used, 10_000),
+            other => panic!("expected out of fuel, got {:?}", other),
+        }
+
+        let error = run_trap("(i32.div_u (i32.const 1) (local.get 0))", 1_000_000).await;
+        match error {
+            ExecutionError::GuestTrap { trap, .. } => assert!(trap.contains("divide by zero")),
+            other => panic!("expected guest trap, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_runtime_config() {
         let config = RuntimeConfig {
             enable_fuel: false,
             enable_cache: true,
+            cache_capacity: 8,
+            cache_dir: None,
             max_io_size: 512,
+            max_chunked_io_size: 4096,
             detailed_metrics: false,
+            epoch_tick_ms: 5,
+            max_concurrency: 2,
+            pooling_allocator: false,
+            max_memory_mb: 16,
+            max_log_bytes: 256,
+            state_dir: None,
+            max_state_bytes: 4096,
+            features: WasmFeaturePolicy::default(),
         };
 
         assert!(!config.enable_fuel);
@@ -509,4 +1703,31 @@ mod tests {
         assert_eq!(metrics.total_executions, 0);
         assert_eq!(metrics.avg_execution_time_ms, 0.0);
     }
+
+    #[tokio::test]
+    async fn test_runtime_metrics_count_runs_and_failures() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = create_echo_wasm();
+        let capsule_id = blake3::hash(&capsule).to_hex().to_string();
+        let limits = ResourceLimits::default();
+
+        runtime.execute(&capsule, b"one", limits.clone()).await.unwrap();
+        runtime.execute_batch(&capsule, &[&b"two"[..], b"three"], limits.clone()).await.unwrap();
+        let large_input = vec![0u8; MAX_IO_SIZE + 1];
+        assert!(runtime.execute(&capsule, &large_input, limits).await.is_err());
+
+        let snapshot = runtime.metrics();
+        assert_eq!(snapshot.runtime.total_executions, 4);
+        assert_eq!(snapshot.runtime.failed_executions, 1);
+        assert!(snapshot.runtime.total_fuel_used > 0);
+
+        let capsule_metrics = &snapshot.capsules[&capsule_id];
+        assert_eq!(capsule_metrics.executions, 3);
+        assert_eq!(capsule_metrics.fuel_used.count, 3);
+        assert_eq!(capsule_metrics.failures["io"], 1);
+        assert!(snapshot.to_prometheus().contains(&format!(
+            "tenzik_capsule_executions_total{{capsule_id=\"{}\"}} 3\n",
+            capsule_id
+        )));
+    }
 }
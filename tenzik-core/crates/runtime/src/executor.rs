@@ -0,0 +1,382 @@
+//! Capsule Executor
+//!
+//! This module runs capsules in parallel on a bounded worker pool over one
+//! shared [`WasmRuntime`]. Each job runs as its own tokio task, so jobs are
+//! spread across the worker threads of a multi-threaded tokio runtime.
+//!
+//! A job that is submitted while every worker is busy waits in a bounded
+//! queue; once the queue is full further submissions are rejected with
+//! `ExecutionError::QueueFull` instead of piling up. Queue depth and job
+//! outcomes are exposed through [`ExecutorStats`].
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::execution::{ExecutionError, ExecutionOptions, ExecutionResult, WasmRuntime};
+use crate::sandbox::ResourceLimits;
+
+/// Default number of jobs allowed to wait for a worker
+pub const DEFAULT_MAX_QUEUE_DEPTH: usize = 256;
+
+/// A capsule execution request
+#[derive(Debug, Clone)]
+pub struct ExecutionJob {
+    /// WASM capsule bytes, shared between jobs running the same capsule
+    pub capsule: Arc<[u8]>,
+    /// Input passed to the capsule
+    pub input: Vec<u8>,
+    /// Resource limits for the run
+    pub resource_limits: ResourceLimits,
+    /// Logical time and random seed overrides
+    pub options: ExecutionOptions,
+}
+
+impl ExecutionJob {
+    /// Create a job with default execution options
+    pub fn new(
+        capsule: impl Into<Arc<[u8]>>,
+        input: impl Into<Vec<u8>>,
+        resource_limits: ResourceLimits,
+    ) -> Self {
+        Self {
+            capsule: capsule.into(),
+            input: input.into(),
+            resource_limits,
+            options: ExecutionOptions::default(),
+        }
+    }
+
+    /// Set the execution options
+    pub fn with_options(mut self, options: ExecutionOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// Snapshot of executor queue depth and job outcomes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutorStats {
+    /// Jobs waiting for a worker
+    pub queued: usize,
+    /// Jobs currently executing
+    pub running: usize,
+    /// Highest number of jobs that have waited at once
+    pub peak_queue_depth: usize,
+    /// Jobs that finished successfully
+    pub completed: u64,
+    /// Jobs that finished with an error
+    pub failed: u64,
+    /// Jobs rejected because the queue was full
+    pub rejected: u64,
+}
+
+/// Live counters behind [`ExecutorStats`]
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    peak_queue_depth: AtomicUsize,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Decrements a gauge when dropped, so cancelled or panicking jobs are
+/// still accounted for
+struct GaugeGuard<'a>(&'a AtomicUsize);
+
+impl<'a> GaugeGuard<'a> {
+    fn enter(gauge: &'a AtomicUsize) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Bounded worker pool executing capsules on a shared runtime
+#[derive(Clone)]
+pub struct CapsuleExecutor {
+    /// Runtime shared by every worker
+    runtime: Arc<WasmRuntime>,
+    /// One permit per worker
+    workers: Arc<Semaphore>,
+    /// Maximum number of jobs waiting for a worker
+    max_queue_depth: usize,
+    /// Queue depth and outcome counters
+    counters: Arc<Counters>,
+}
+
+impl CapsuleExecutor {
+    /// Create an executor with one worker per runtime instance slot
+    /// (`RuntimeConfig::max_concurrency`)
+    pub fn new(runtime: Arc<WasmRuntime>) -> Self {
+        Self::with_queue_depth(runtime, DEFAULT_MAX_QUEUE_DEPTH)
+    }
+
+    /// Create an executor allowing up to `max_queue_depth` waiting jobs
+    pub fn with_queue_depth(runtime: Arc<WasmRuntime>, max_queue_depth: usize) -> Self {
+        let workers = runtime.config().max_concurrency.max(1);
+        Self {
+            runtime,
+            workers: Arc::new(Semaphore::new(workers)),
+            max_queue_depth,
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    /// Submit a job, returning a handle to its result.
+    ///
+    /// The job starts immediately if a worker is free and is queued
+    /// otherwise. Must be called from within a tokio runtime.
+    pub fn submit(
+        &self,
+        job: ExecutionJob,
+    ) -> Result<JoinHandle<Result<ExecutionResult, ExecutionError>>, ExecutionError> {
+        let permit = match self.workers.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                self.reserve_queue_slot()?;
+                None
+            }
+        };
+
+        let executor = self.clone();
+        Ok(tokio::spawn(async move { executor.run(job, permit).await }))
+    }
+
+    /// Submit a job and wait for its result
+    pub async fn execute(&self, job: ExecutionJob) -> Result<ExecutionResult, ExecutionError> {
+        let handle = self.submit(job)?;
+        join(handle).await
+    }
+
+    /// Submit every job and wait for all of them, returning results in
+    /// submission order
+    pub async fn execute_all(
+        &self,
+        jobs: Vec<ExecutionJob>,
+    ) -> Vec<Result<ExecutionResult, ExecutionError>> {
+        let handles: Vec<_> = jobs.into_iter().map(|job| self.submit(job)).collect();
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(match handle {
+                Ok(handle) => join(handle).await,
+                Err(e) => Err(e),
+            });
+        }
+        results
+    }
+
+    /// Get a snapshot of queue depth and job outcomes
+    pub fn stats(&self) -> ExecutorStats {
+        let counters = &self.counters;
+        ExecutorStats {
+            queued: counters.queued.load(Ordering::Relaxed),
+            running: counters.running.load(Ordering::Relaxed),
+            peak_queue_depth: counters.peak_queue_depth.load(Ordering::Relaxed),
+            completed: counters.completed.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+            rejected: counters.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get the shared runtime
+    pub fn runtime(&self) -> &Arc<WasmRuntime> {
+        &self.runtime
+    }
+
+    /// Count a job into the queue, or reject it if the queue is full
+    fn reserve_queue_slot(&self) -> Result<(), ExecutionError> {
+        let counters = &self.counters;
+        let reserved =
+            counters
+                .queued
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                    (queued < self.max_queue_depth).then_some(queued + 1)
+                });
+
+        match reserved {
+            Ok(previous) => {
+                counters
+                    .peak_queue_depth
+                    .fetch_max(previous + 1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(_) => {
+                counters.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(ExecutionError::QueueFull {
+                    max_queue_depth: self.max_queue_depth,
+                })
+            }
+        }
+    }
+
+    /// Wait for a worker if needed, then execute the job
+    async fn run(
+        &self,
+        job: ExecutionJob,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<ExecutionResult, ExecutionError> {
+        let counters = &self.counters;
+        let _permit = match permit {
+            Some(permit) => permit,
+            None => {
+                // The queue slot was reserved in `submit`; release it once a
+                // worker is free (or if this task is cancelled while waiting)
+                let _queued = GaugeGuard(&counters.queued);
+                self.workers.clone().acquire_owned().await.map_err(|e| {
+                    ExecutionError::ExecutionFailed {
+                        reason: format!("Executor closed: {}", e),
+                    }
+                })?
+            }
+        };
+
+        let _running = GaugeGuard::enter(&counters.running);
+        let result = self
+            .runtime
+            .execute_with_options(&job.capsule, &job.input, job.resource_limits, job.options)
+            .await;
+
+        match &result {
+            Ok(_) => counters.completed.fetch_add(1, Ordering::Relaxed),
+            Err(_) => counters.failed.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+}
+
+/// Wait for a spawned job, surfacing a panicked task as an execution error
+async fn join(
+    handle: JoinHandle<Result<ExecutionResult, ExecutionError>>,
+) -> Result<ExecutionResult, ExecutionError> {
+    handle.await.unwrap_or_else(|e| {
+        Err(ExecutionError::ExecutionFailed {
+            reason: format!("Execution task failed: {}", e),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::RuntimeConfig;
+    use crate::receipts::generate_test_signing_key;
+    use std::collections::HashSet;
+
+    /// Legacy-ABI capsule that echoes its input
+    const ECHO_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (i32.or (i32.shl (local.get $len) (i32.const 16)) (local.get $ptr))))
+    "#;
+
+    /// Capsule that spins until its deadline
+    const SPIN_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "run") (param i32 i32) (result i32)
+            (loop (br 0))
+            (i32.const 0)))
+    "#;
+
+    fn executor(max_concurrency: usize, max_queue_depth: usize) -> CapsuleExecutor {
+        let config = RuntimeConfig {
+            max_concurrency,
+            ..Default::default()
+        };
+        let runtime = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        CapsuleExecutor::with_queue_depth(Arc::new(runtime), max_queue_depth)
+    }
+
+    #[test]
+    fn test_runtime_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<WasmRuntime>();
+        assert_send_sync::<CapsuleExecutor>();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_parallel_execution_with_unique_nonces() {
+        let executor = executor(4, 64);
+        let capsule: Arc<[u8]> = wat::parse_str(ECHO_WAT).unwrap().into();
+
+        let jobs = (0..32)
+            .map(|i| {
+                ExecutionJob::new(
+                    capsule.clone(),
+                    format!("job-{}", i),
+                    ResourceLimits::default(),
+                )
+            })
+            .collect();
+        let results = executor.execute_all(jobs).await;
+
+        let mut nonces = HashSet::new();
+        for (i, result) in results.into_iter().enumerate() {
+            let result = result.unwrap();
+            assert_eq!(result.output, format!("job-{}", i).as_bytes());
+            assert!(nonces.insert(result.receipt.nonce));
+        }
+
+        let stats = executor.stats();
+        assert_eq!(stats.completed, 32);
+        assert_eq!(stats.queued, 0);
+        assert_eq!(stats.running, 0);
+        assert_eq!(executor.runtime().next_nonce(), 33);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_full_queue_rejects_jobs() {
+        let executor = executor(1, 1);
+        let spin = wat::parse_str(SPIN_WAT).unwrap();
+        let echo = wat::parse_str(ECHO_WAT).unwrap();
+        let spin_limits = ResourceLimits {
+            execution_time_ms: 200,
+            fuel_limit: 1 << 40,
+            ..Default::default()
+        };
+
+        let running = executor
+            .submit(ExecutionJob::new(spin, b"".to_vec(), spin_limits))
+            .unwrap();
+        let queued = executor
+            .submit(ExecutionJob::new(
+                echo.clone(),
+                b"queued".to_vec(),
+                ResourceLimits::default(),
+            ))
+            .unwrap();
+        let rejected = executor.submit(ExecutionJob::new(
+            echo,
+            b"rejected".to_vec(),
+            ResourceLimits::default(),
+        ));
+
+        assert!(matches!(
+            rejected,
+            Err(ExecutionError::QueueFull { max_queue_depth: 1 })
+        ));
+        assert!(matches!(
+            join(running).await,
+            Err(ExecutionError::Timeout { .. })
+        ));
+        assert_eq!(join(queued).await.unwrap().output, b"queued");
+
+        let stats = executor.stats();
+        assert_eq!(stats.peak_queue_depth, 1);
+        assert_eq!(stats.completed, 1);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.rejected, 1);
+    }
+}
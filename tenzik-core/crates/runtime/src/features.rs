@@ -0,0 +1,247 @@
+//! WASM Feature Policy
+//!
+//! A [`WasmFeaturePolicy`] fixes the engine semantics capsules run under:
+//! which WebAssembly proposals are enabled, whether NaN results are
+//! canonicalized and how deep the wasm stack may grow. `WasmValidator` and
+//! `WasmRuntime` build their engines from the same policy, so a capsule that
+//! passes validation compiles for execution too.
+//!
+//! The policy's Blake3 commitment is signed into every receipt
+//! (`ReplayContext::feature_policy`), so a verifier knows which semantics
+//! produced the output and can refuse to re-execute under different ones.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use wasmtime::{Config, Engine};
+
+/// Domain separation prefix of the policy commitment
+const POLICY_DOMAIN: &[u8] = b"TENZIK_WASM_FEATURES_V2";
+
+/// Version of the wasmtime dependency the policy is interpreted by, matching
+/// the workspace requirement. Bump it together with the dependency: a new
+/// engine may give the same settings different semantics.
+pub const WASMTIME_VERSION: &str = "26.0";
+
+/// Default maximum wasm stack size in bytes
+pub const DEFAULT_MAX_WASM_STACK: usize = 512 * 1024;
+
+/// WebAssembly proposals and engine settings shared by validation and
+/// execution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WasmFeaturePolicy {
+    /// Fixed-width SIMD
+    pub simd: bool,
+    /// Relaxed SIMD; requires `simd`
+    pub relaxed_simd: bool,
+    /// Multiple results from functions and blocks
+    pub multi_value: bool,
+    /// Bulk memory operations
+    pub bulk_memory: bool,
+    /// Reference types; requires `bulk_memory`
+    pub reference_types: bool,
+    /// Threads and shared memories; requires `bulk_memory`
+    pub threads: bool,
+    /// Tail calls
+    pub tail_call: bool,
+    /// Multiple memories per module
+    pub multi_memory: bool,
+    /// Extended constant expressions
+    pub extended_const: bool,
+    /// Typed function references; requires `reference_types`
+    pub function_references: bool,
+    /// Garbage collected types; requires `function_references`
+    pub gc: bool,
+    /// 64-bit memories
+    pub memory64: bool,
+    /// Custom memory page sizes
+    pub custom_page_sizes: bool,
+    /// Canonicalize the bits of NaN results, so floating point code
+    /// produces the same output on every host
+    pub nan_canonicalization: bool,
+    /// Maximum wasm stack size in bytes
+    pub max_wasm_stack: usize,
+}
+
+impl Default for WasmFeaturePolicy {
+    /// Small, deterministic capsules: every optional proposal disabled and
+    /// NaN canonicalization on
+    fn default() -> Self {
+        Self {
+            simd: false,
+            relaxed_simd: false,
+            multi_value: false,
+            bulk_memory: false,
+            reference_types: false,
+            threads: false,
+            tail_call: false,
+            multi_memory: false,
+            extended_const: false,
+            function_references: false,
+            gc: false,
+            memory64: false,
+            custom_page_sizes: false,
+            nan_canonicalization: true,
+            max_wasm_stack: DEFAULT_MAX_WASM_STACK,
+        }
+    }
+}
+
+impl WasmFeaturePolicy {
+    /// Apply the policy to a wasmtime configuration. Every proposal the
+    /// engine knows is set explicitly, so wasmtime's own defaults never
+    /// leak into the semantics; relaxed SIMD always uses its deterministic
+    /// lowering.
+    pub fn apply(&self, config: &mut Config) {
+        config
+            .wasm_simd(self.simd)
+            .wasm_relaxed_simd(self.relaxed_simd)
+            .relaxed_simd_deterministic(true)
+            .wasm_multi_value(self.multi_value)
+            .wasm_bulk_memory(self.bulk_memory)
+            .wasm_reference_types(self.reference_types)
+            .wasm_threads(self.threads)
+            .wasm_tail_call(self.tail_call)
+            .wasm_multi_memory(self.multi_memory)
+            .wasm_extended_const(self.extended_const)
+            .wasm_function_references(self.function_references)
+            .wasm_gc(self.gc)
+            .wasm_memory64(self.memory64)
+            .wasm_custom_page_sizes(self.custom_page_sizes)
+            .cranelift_nan_canonicalization(self.nan_canonicalization)
+            .max_wasm_stack(self.max_wasm_stack);
+    }
+
+    /// Engine with only this policy applied, for compiling without running
+    pub fn engine(&self) -> Result<Engine> {
+        let mut config = Config::new();
+        self.apply(&mut config);
+        Engine::new(&config).context("Invalid WASM feature policy")
+    }
+
+    /// Hex Blake3 commitment over the canonical encoding of the policy and
+    /// the wasmtime version interpreting it
+    pub fn commitment(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(POLICY_DOMAIN);
+        hasher.update(&(WASMTIME_VERSION.len() as u64).to_le_bytes());
+        hasher.update(WASMTIME_VERSION.as_bytes());
+        for enabled in [
+            self.simd,
+            self.relaxed_simd,
+            self.multi_value,
+            self.bulk_memory,
+            self.reference_types,
+            self.threads,
+            self.tail_call,
+            self.multi_memory,
+            self.extended_const,
+            self.function_references,
+            self.gc,
+            self.memory64,
+            self.custom_page_sizes,
+            self.nan_canonicalization,
+        ] {
+            hasher.update(&[enabled as u8]);
+        }
+        hasher.update(&(self.max_wasm_stack as u64).to_le_bytes());
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::Module;
+
+    /// A module using a bulk memory instruction
+    const BULK_MEMORY_WAT: &str = r#"
+        (module
+          (memory 1)
+          (func (memory.fill (i32.const 0) (i32.const 0) (i32.const 1))))
+    "#;
+
+    #[test]
+    fn test_policy_controls_accepted_proposals() {
+        let wasm = wat::parse_str(BULK_MEMORY_WAT).unwrap();
+        let strict = WasmFeaturePolicy::default();
+        assert!(Module::new(&strict.engine().unwrap(), &wasm).is_err());
+
+        let bulk_memory = WasmFeaturePolicy {
+            bulk_memory: true,
+            ..Default::default()
+        };
+        assert!(Module::new(&bulk_memory.engine().unwrap(), &wasm).is_ok());
+
+        let inconsistent = WasmFeaturePolicy {
+            reference_types: true,
+            ..Default::default()
+        };
+        assert!(inconsistent.engine().is_err());
+    }
+
+    #[test]
+    fn test_policy_disables_engine_defaults() {
+        // wasmtime enables tail calls, multi-memory and extended constants
+        // by default; the strict policy must not inherit them
+        let strict = WasmFeaturePolicy::default().engine().unwrap();
+        for wat in [
+            "(module (func (return_call 0)))",
+            "(module (memory 1) (memory 1))",
+            "(module (global i32 (i32.add (i32.const 1) (i32.const 2))))",
+        ] {
+            let wasm = wat::parse_str(wat).unwrap();
+            assert!(Module::new(&strict, &wasm).is_err(), "{}", wat);
+        }
+
+        let permissive = WasmFeaturePolicy {
+            tail_call: true,
+            multi_memory: true,
+            extended_const: true,
+            ..Default::default()
+        }
+        .engine()
+        .unwrap();
+        let wasm = wat::parse_str("(module (func (return_call 0)))").unwrap();
+        assert!(Module::new(&permissive, &wasm).is_ok());
+    }
+
+    #[test]
+    fn test_commitment_covers_every_setting() {
+        let policy = WasmFeaturePolicy::default();
+        assert_eq!(
+            policy.commitment(),
+            WasmFeaturePolicy::default().commitment()
+        );
+
+        let variants = [
+            WasmFeaturePolicy {
+                simd: true,
+                ..Default::default()
+            },
+            WasmFeaturePolicy {
+                tail_call: true,
+                ..Default::default()
+            },
+            WasmFeaturePolicy {
+                memory64: true,
+                ..Default::default()
+            },
+            WasmFeaturePolicy {
+                custom_page_sizes: true,
+                ..Default::default()
+            },
+            WasmFeaturePolicy {
+                nan_canonicalization: false,
+                ..Default::default()
+            },
+            WasmFeaturePolicy {
+                max_wasm_stack: 256 * 1024,
+                ..Default::default()
+            },
+        ];
+        for variant in variants {
+            assert_ne!(variant.commitment(), policy.commitment());
+        }
+    }
+}
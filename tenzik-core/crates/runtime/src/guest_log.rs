@@ -0,0 +1,144 @@
+//! Guest Logging
+//!
+//! This module collects the lines a capsule writes with the `log_write` host
+//! function. Logs are returned to the caller in `ExecutionResult::logs` for
+//! debugging; the receipt only carries their Blake3 commitment, so log
+//! contents never leave the node inside federation events.
+
+use serde::{Deserialize, Serialize};
+
+/// Domain separation prefix of the log commitment
+const LOG_DOMAIN: &[u8] = b"TENZIK_GUEST_LOG_V1";
+
+/// Default number of message bytes a capsule may log per run
+pub const DEFAULT_MAX_LOG_BYTES: usize = 16 * 1024;
+
+/// Severity of a log line, as passed to `log_write`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogLevel {
+    /// `0`
+    Error,
+    /// `1`
+    Warn,
+    /// `2`
+    Info,
+    /// `3`
+    Debug,
+}
+
+impl LogLevel {
+    /// Decode the level argument of `log_write`
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(LogLevel::Error),
+            1 => Some(LogLevel::Warn),
+            2 => Some(LogLevel::Info),
+            3 => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    /// Numeric code of the level
+    pub fn code(&self) -> u8 {
+        match self {
+            LogLevel::Error => 0,
+            LogLevel::Warn => 1,
+            LogLevel::Info => 2,
+            LogLevel::Debug => 3,
+        }
+    }
+}
+
+/// One line written by a capsule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogLine {
+    /// Severity
+    pub level: LogLevel,
+    /// Message, with invalid UTF-8 replaced
+    pub message: String,
+}
+
+/// Hex Blake3 commitment over the canonical encoding of `lines`
+pub fn log_commitment(lines: &[LogLine]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(LOG_DOMAIN);
+    hasher.update(&(lines.len() as u64).to_le_bytes());
+    for line in lines {
+        hasher.update(&[line.level.code()]);
+        hasher.update(&(line.message.len() as u64).to_le_bytes());
+        hasher.update(line.message.as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Log lines of one execution, bounded by a byte budget
+#[derive(Debug)]
+pub(crate) struct GuestLog {
+    lines: Vec<LogLine>,
+    remaining_bytes: usize,
+}
+
+impl GuestLog {
+    /// Create a log that accepts up to `max_bytes` of raw message bytes
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        Self {
+            lines: Vec::new(),
+            remaining_bytes: max_bytes,
+        }
+    }
+
+    /// Append a line, or return `false` if it does not fit the budget
+    pub(crate) fn write(&mut self, level: LogLevel, message: &[u8]) -> bool {
+        if message.len() > self.remaining_bytes {
+            return false;
+        }
+        self.remaining_bytes -= message.len();
+        self.lines.push(LogLine {
+            level,
+            message: String::from_utf8_lossy(message).into_owned(),
+        });
+        true
+    }
+
+    /// Take the collected lines
+    pub(crate) fn into_lines(self) -> Vec<LogLine> {
+        self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_rejects_lines_that_do_not_fit() {
+        let mut log = GuestLog::new(8);
+        assert!(log.write(LogLevel::Info, b"hello"));
+        assert!(!log.write(LogLevel::Info, b"world"));
+        assert!(log.write(LogLevel::Debug, b"!!!"));
+        assert!(!log.write(LogLevel::Debug, b"!"));
+
+        let lines = log.into_lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].message, "!!!");
+    }
+
+    #[test]
+    fn test_commitment_covers_levels_and_messages() {
+        let line = |level, message: &str| LogLine {
+            level,
+            message: message.to_string(),
+        };
+        let lines = vec![line(LogLevel::Info, "a"), line(LogLevel::Warn, "b")];
+
+        assert_eq!(log_commitment(&lines), log_commitment(&lines.clone()));
+        assert_ne!(
+            log_commitment(&lines),
+            log_commitment(&[line(LogLevel::Info, "a"), line(LogLevel::Error, "b")])
+        );
+        assert_ne!(
+            log_commitment(&lines),
+            log_commitment(&[line(LogLevel::Info, "ab")])
+        );
+    }
+}
@@ -0,0 +1,2040 @@
+//! Host Functions Module
+//!
+//! This module implements the host-provided primitives that capsules import
+//! from the `env` namespace. Host functions operate on the capsule's exported
+//! `memory` and report failures through negative status codes instead of
+//! trapping, so a bad pointer from the guest never aborts the execution.
+//! The one exception is a replayed run that departs from its transcript
+//! (see `transcript.rs`), which traps at the first divergent call.
+
+use crate::chunked::ChunkedIo;
+use crate::execution::ExecutionError;
+use crate::guest_log::{GuestLog, LogLevel, DEFAULT_MAX_LOG_BYTES};
+use crate::http::{HttpClient, HttpError};
+use crate::json_path::JsonPath;
+use crate::limiter::ExecutionLimiter;
+use crate::sandbox::{Capability, SecuritySandbox};
+use crate::secrets::{self, GrantedSecrets, HMAC_SHA256_LEN};
+use crate::signing::{CapsuleSigner, SIGNATURE_LEN};
+use crate::state::{CapsuleState, PutError};
+use crate::transcript::TranscriptMode;
+
+use base64::engine::general_purpose::{GeneralPurpose, STANDARD, URL_SAFE_NO_PAD};
+use base64::engine::{DecodePaddingMode, GeneralPurposeConfig};
+use base64::{alphabet, Engine};
+use ed25519_dalek::{Signature, Verifier};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use wasmtime::{Caller, Extern, Linker, Memory};
+
+/// Host call completed successfully
+pub const HOST_OK: i32 = 0;
+/// A guest pointer/length pair falls outside linear memory
+pub const HOST_ERR_OUT_OF_BOUNDS: i32 = -1;
+/// The capsule does not export a `memory`
+pub const HOST_ERR_NO_MEMORY: i32 = -2;
+/// The input document is not valid UTF-8 JSON
+pub const HOST_ERR_INVALID_JSON: i32 = -3;
+/// The JSONPath expression could not be parsed
+pub const HOST_ERR_INVALID_PATH: i32 = -4;
+/// The JSONPath expression matched nothing
+pub const HOST_ERR_PATH_NOT_FOUND: i32 = -5;
+/// The guest output buffer cannot hold the result
+pub const HOST_ERR_BUFFER_TOO_SMALL: i32 = -6;
+/// The JSONPath expression matched more than one value
+pub const HOST_ERR_AMBIGUOUS_PATH: i32 = -7;
+/// The input is not valid Base64 for the requested alphabet
+pub const HOST_ERR_INVALID_BASE64: i32 = -8;
+/// An argument selects an unknown mode or variant
+pub const HOST_ERR_INVALID_ARGUMENT: i32 = -9;
+/// The run's log byte budget cannot hold the message
+pub const HOST_ERR_LOG_BUDGET_EXCEEDED: i32 = -10;
+/// The state key does not exist
+pub const HOST_ERR_STATE_NOT_FOUND: i32 = -11;
+/// The capsule's state namespace is full
+pub const HOST_ERR_STATE_FULL: i32 = -12;
+/// The HTTP policy does not allow the method for the host, or the run may
+/// not send a secret header to it
+pub const HOST_ERR_HTTP_DENIED: i32 = -13;
+/// The HTTP request or response body exceeds the policy limit
+pub const HOST_ERR_HTTP_TOO_LARGE: i32 = -14;
+/// The HTTP exchange did not finish within the policy timeout
+pub const HOST_ERR_HTTP_TIMEOUT: i32 = -15;
+/// The HTTP connection failed or the response was malformed
+pub const HOST_ERR_HTTP_FAILED: i32 = -16;
+/// The secret is not granted to the capsule or not held by the node
+pub const HOST_ERR_SECRET_UNAVAILABLE: i32 = -17;
+/// No signing key was derived for the run
+pub const HOST_ERR_SIGN_UNAVAILABLE: i32 = -18;
+/// The input has no chunk with the requested index
+pub const HOST_ERR_CHUNK_NOT_FOUND: i32 = -19;
+/// The run's output limit cannot hold the chunk
+pub const HOST_ERR_OUTPUT_FULL: i32 = -20;
+
+/// Standard Base64 alphabet (`+`, `/`) with `=` padding
+pub const BASE64_STANDARD: i32 = 0;
+/// URL-safe Base64 alphabet (`-`, `_`) without padding
+pub const BASE64_URL_SAFE: i32 = 1;
+
+/// Length of a Blake3 digest in bytes
+pub const HASH_LEN: usize = 32;
+
+/// Per-execution state stored in the wasmtime `Store`
+pub struct HostState {
+    /// Memory and table limits, with peak usage
+    pub(crate) limiter: ExecutionLimiter,
+    /// Logical execution timestamp in Unix milliseconds, fixed for the run
+    pub(crate) logical_time_ms: u64,
+    /// Blake3 XOF stream keyed by the run's random seed
+    pub(crate) rng: blake3::OutputReader,
+    /// Number of calls made to each host function
+    pub(crate) host_calls: BTreeMap<&'static str, u32>,
+    /// Wall-clock deadline of the run, armed with the epoch deadline and
+    /// bounding host calls that wait on the outside world
+    pub(crate) deadline: Option<Instant>,
+    /// Set when the run is stopped at its deadline, by the epoch callback
+    /// or a host call
+    pub(crate) deadline_exceeded: bool,
+    /// Fuel left at the last host call or epoch tick, shared with the async
+    /// backstop timeout, which cannot read the store once it fires
+    pub(crate) fuel_remaining: Arc<AtomicU64>,
+    /// Host call transcript being recorded or replayed
+    pub(crate) transcript: TranscriptMode,
+    /// Lines written with `log_write`
+    pub(crate) log: GuestLog,
+    /// Working copy of the capsule's state namespace
+    pub(crate) state: CapsuleState,
+    /// Client for `http_request`, bound to the run's policy
+    pub(crate) http: Option<HttpClient>,
+    /// Node secrets granted to the run
+    pub(crate) secrets: GrantedSecrets,
+    /// Key for `sign_ed25519`, derived for the capsule
+    pub(crate) signer: Option<CapsuleSigner>,
+    /// Input and output of a chunked I/O run
+    pub(crate) chunked_io: Option<ChunkedIo>,
+}
+
+impl HostState {
+    /// Create host state with the given resource limiter, logical clock and
+    /// random seed
+    pub(crate) fn new(
+        limiter: ExecutionLimiter,
+        logical_time_ms: u64,
+        random_seed: [u8; 32],
+    ) -> Self {
+        Self {
+            limiter,
+            logical_time_ms,
+            rng: blake3::Hasher::new_keyed(&random_seed).finalize_xof(),
+            host_calls: BTreeMap::new(),
+            deadline: None,
+            deadline_exceeded: false,
+            fuel_remaining: Arc::new(AtomicU64::new(0)),
+            transcript: TranscriptMode::Off,
+            log: GuestLog::new(DEFAULT_MAX_LOG_BYTES),
+            state: CapsuleState::default(),
+            http: None,
+            secrets: GrantedSecrets::default(),
+            signer: None,
+            chunked_io: None,
+        }
+    }
+
+    /// Record or replay host calls
+    pub(crate) fn with_transcript(mut self, transcript: TranscriptMode) -> Self {
+        self.transcript = transcript;
+        self
+    }
+
+    /// Give the capsule its state namespace
+    pub(crate) fn with_state(mut self, state: CapsuleState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Let the capsule send HTTP requests through `http`
+    pub(crate) fn with_http(mut self, http: HttpClient) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Let the capsule use the granted node secrets
+    pub(crate) fn with_secrets(mut self, secrets: GrantedSecrets) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Let the capsule sign with `signer`
+    pub(crate) fn with_signer(mut self, signer: CapsuleSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Serve the input and collect the output through chunked I/O
+    pub(crate) fn with_chunked_io(mut self, chunked_io: ChunkedIo) -> Self {
+        self.chunked_io = Some(chunked_io);
+        self
+    }
+
+    /// Limit the message bytes the capsule may log
+    pub(crate) fn with_log_budget(mut self, max_bytes: usize) -> Self {
+        self.log = GuestLog::new(max_bytes);
+        self
+    }
+
+    /// Record a call to a host function
+    pub(crate) fn record_call(&mut self, function: &'static str) {
+        *self.host_calls.entry(function).or_insert(0) += 1;
+    }
+
+    /// Total number of host function calls made so far
+    pub(crate) fn total_host_calls(&self) -> u32 {
+        self.host_calls.values().sum()
+    }
+
+    /// Host function calls keyed by function name
+    pub(crate) fn host_call_breakdown(&self) -> BTreeMap<String, u32> {
+        self.host_calls
+            .iter()
+            .map(|(name, count)| (name.to_string(), *count))
+            .collect()
+    }
+}
+
+/// Link a host function through [`host_call`], which counts the call and
+/// records or replays it. Every argument is an `i32`; `reads` lists the
+/// `(ptr, len)` guest ranges the function reads, and `replay` how a replayed
+/// call is answered (recomputed unless given).
+macro_rules! link_host_call {
+    (@replay) => {
+        Replay::Recompute
+    };
+    (@replay $replay:ident) => {
+        Replay::$replay
+    };
+    (
+        $linker:expr,
+        $function:ident($($arg:ident),*)
+        $(, reads = [$(($ptr:expr, $len:expr)),+ $(,)?])?
+        $(, replay = $replay:ident)?
+    ) => {
+        $linker
+            .func_wrap(
+                "env",
+                stringify!($function),
+                |mut caller: Caller<'_, HostState>, $($arg: i32),*| {
+                    host_call(
+                        &mut caller,
+                        stringify!($function),
+                        &[$($arg),*],
+                        &[$($(($ptr, $len)),+)?],
+                        link_host_call!(@replay $($replay)?),
+                        |caller| $function(caller, $($arg),*),
+                    )
+                },
+            )
+            .map_err(|e| link_error(stringify!($function), e))?
+    };
+}
+
+/// Link every host function granted by the sandbox into the linker
+pub(crate) fn link_host_functions(
+    linker: &mut Linker<HostState>,
+    sandbox: &SecuritySandbox,
+) -> Result<(), ExecutionError> {
+    if sandbox.has_capability(Capability::Hash) {
+        link_host_call!(linker, hash_commit(ptr, len, out_ptr), reads = [(ptr, len)]);
+        link_host_call!(
+            linker,
+            hash_verify(ptr, len, hash_ptr),
+            reads = [(ptr, len), (hash_ptr, HASH_LEN as i32)]
+        );
+    }
+
+    if sandbox.has_capability(Capability::Json) {
+        link_host_call!(
+            linker,
+            json_path(data_ptr, data_len, path_ptr, path_len, out_ptr, out_cap),
+            reads = [(data_ptr, data_len), (path_ptr, path_len)]
+        );
+        link_host_call!(
+            linker,
+            json_extract(data_ptr, data_len, path_ptr, path_len, out_ptr, out_cap),
+            reads = [(data_ptr, data_len), (path_ptr, path_len)]
+        );
+    }
+
+    if sandbox.has_capability(Capability::Base64) {
+        link_host_call!(
+            linker,
+            base64_encode(ptr, len, variant, out_ptr, out_cap),
+            reads = [(ptr, len)]
+        );
+        link_host_call!(
+            linker,
+            base64_decode(ptr, len, variant, out_ptr, out_cap),
+            reads = [(ptr, len)]
+        );
+    }
+
+    if sandbox.has_capability(Capability::Time) {
+        link_host_call!(linker, time_now_ms());
+        link_host_call!(linker, time_iso8601(out_ptr, out_cap));
+    }
+
+    if sandbox.has_capability(Capability::Random) {
+        link_host_call!(linker, random_bytes(out_ptr, len));
+        link_host_call!(linker, random_u32());
+    }
+
+    if sandbox.has_capability(Capability::State) {
+        link_host_call!(
+            linker,
+            state_get(key_ptr, key_len, out_ptr, out_cap),
+            reads = [(key_ptr, key_len)]
+        );
+        link_host_call!(
+            linker,
+            state_put(key_ptr, key_len, value_ptr, value_len),
+            reads = [(key_ptr, key_len), (value_ptr, value_len)]
+        );
+        link_host_call!(
+            linker,
+            state_delete(key_ptr, key_len),
+            reads = [(key_ptr, key_len)]
+        );
+    }
+
+    if sandbox.has_capability(Capability::Secrets) {
+        link_host_call!(
+            linker,
+            secret_hmac_sha256(id_ptr, id_len, data_ptr, data_len, out_ptr),
+            reads = [(id_ptr, id_len), (data_ptr, data_len)],
+            replay = Recorded
+        );
+        link_host_call!(
+            linker,
+            secret_hmac_verify(id_ptr, id_len, data_ptr, data_len, tag_ptr),
+            reads = [
+                (id_ptr, id_len),
+                (data_ptr, data_len),
+                (tag_ptr, HMAC_SHA256_LEN as i32)
+            ],
+            replay = Recorded
+        );
+    }
+
+    if sandbox.has_capability(Capability::Sign) {
+        // A verifier cannot sign under the recording node's key, so replay
+        // answers signatures from the transcript and checks them against the
+        // capsule's public key
+        linker
+            .func_wrap(
+                "env",
+                "sign_ed25519",
+                |mut caller: Caller<'_, HostState>, data_ptr: i32, data_len: i32, out_ptr: i32| {
+                    let result = host_call(
+                        &mut caller,
+                        "sign_ed25519",
+                        &[data_ptr, data_len, out_ptr],
+                        &[(data_ptr, data_len)],
+                        Replay::Recorded,
+                        |caller| sign_ed25519(caller, data_ptr, data_len, out_ptr),
+                    )?;
+                    if caller.data().transcript.is_replay() {
+                        check_replayed_signature(&mut caller, data_ptr, data_len, out_ptr, result)?;
+                    }
+                    Ok(result)
+                },
+            )
+            .map_err(|e| link_error("sign_ed25519", e))?;
+        link_host_call!(linker, sign_public_key(out_ptr));
+    }
+
+    if sandbox.has_capability(Capability::Log) {
+        // Logging only affects the guest through its status code, so it is
+        // run for real on replay instead of going through the transcript
+        linker
+            .func_wrap(
+                "env",
+                "log_write",
+                |mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| {
+                    count_call(&mut caller, "log_write");
+                    log_write(&mut caller, level, ptr, len)
+                },
+            )
+            .map_err(|e| link_error("log_write", e))?;
+    }
+
+    if sandbox.has_capability(Capability::Http) {
+        linker
+            .func_wrap_async(
+                "env",
+                "http_request",
+                |mut caller: Caller<'_, HostState>,
+                 (req_ptr, req_len, out_ptr, out_cap): (i32, i32, i32, i32)| {
+                    Box::new(async move {
+                        http_request(&mut caller, req_ptr, req_len, out_ptr, out_cap).await
+                    })
+                },
+            )
+            .map_err(|e| link_error("http_request", e))?;
+    }
+
+    Ok(())
+}
+
+/// Link the chunked I/O functions, used instead of passing the input to
+/// `run` when the run is in chunked I/O mode.
+///
+/// Chunks only depend on the run's input, so these functions run for real
+/// on replay instead of going through the transcript.
+pub(crate) fn link_chunked_io(linker: &mut Linker<HostState>) -> Result<(), ExecutionError> {
+    linker
+        .func_wrap(
+            "env",
+            "io_input_chunks",
+            |mut caller: Caller<'_, HostState>| {
+                count_call(&mut caller, "io_input_chunks");
+                io_input_chunks(&mut caller)
+            },
+        )
+        .map_err(|e| link_error("io_input_chunks", e))?;
+    linker
+        .func_wrap(
+            "env",
+            "io_read_chunk",
+            |mut caller: Caller<'_, HostState>, index: i32, out_ptr: i32, out_cap: i32| {
+                count_call(&mut caller, "io_read_chunk");
+                io_read_chunk(&mut caller, index, out_ptr, out_cap)
+            },
+        )
+        .map_err(|e| link_error("io_read_chunk", e))?;
+    linker
+        .func_wrap(
+            "env",
+            "io_write_chunk",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+                count_call(&mut caller, "io_write_chunk");
+                io_write_chunk(&mut caller, ptr, len)
+            },
+        )
+        .map_err(|e| link_error("io_write_chunk", e))?;
+    Ok(())
+}
+
+/// Value a host function returns to the guest
+trait HostValue: Copy {
+    /// Widen to the transcript representation
+    fn to_i64(self) -> i64;
+    /// Narrow a transcript value back to the function's return type
+    fn from_i64(value: i64) -> Self;
+}
+
+impl HostValue for i32 {
+    fn to_i64(self) -> i64 {
+        self as i64
+    }
+
+    fn from_i64(value: i64) -> Self {
+        value as i32
+    }
+}
+
+impl HostValue for i64 {
+    fn to_i64(self) -> i64 {
+        self
+    }
+
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+}
+
+/// How a host call is answered when a transcript is replayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Replay {
+    /// Run the function again; it must reproduce the recorded writes and
+    /// result. Used for calls that only depend on the guest, the signed
+    /// clock and seed, or the recorded pre-state.
+    Recompute,
+    /// Apply the recorded writes and return the recorded result, for calls
+    /// that need the recording node's secrets
+    Recorded,
+}
+
+/// Run a host function, counting it and applying the transcript mode.
+///
+/// When recording, the call, the hash of the guest ranges in `reads`, its
+/// guest writes and its result are appended to the transcript. When
+/// replaying, the next recorded call must match `function`, `args` and the
+/// bytes read, and is then answered as `replay` says. A mismatch traps and
+/// is reported as `ExecutionError::ReplayDiverged`.
+fn host_call<R: HostValue>(
+    caller: &mut Caller<'_, HostState>,
+    function: &'static str,
+    args: &[i32],
+    reads: &[(i32, i32)],
+    replay: Replay,
+    call: impl FnOnce(&mut Caller<'_, HostState>) -> R,
+) -> anyhow::Result<R> {
+    let args: Vec<i64> = args.iter().map(|&arg| arg as i64).collect();
+    count_call(caller, function);
+    if caller.data().transcript.is_off() {
+        return Ok(call(caller));
+    }
+
+    let read_hash = read_hash(caller, reads);
+    if caller.data().transcript.is_replay() {
+        return match replay {
+            Replay::Recompute => recompute_call(caller, function, &args, &read_hash, call),
+            Replay::Recorded => {
+                replay_call(caller, function, &args, &read_hash, &[]).map(R::from_i64)
+            }
+        };
+    }
+
+    caller
+        .data_mut()
+        .transcript
+        .begin_record(function, &args, &read_hash, &[]);
+    let result = call(caller);
+    caller.data_mut().transcript.finish_record(result.to_i64());
+    Ok(result)
+}
+
+/// Hex Blake3 hash of the guest ranges a call reads, each prefixed with its
+/// length. An out-of-bounds range hashes as a marker, so the call still
+/// matches its record when it fails the same way on replay. Empty when the
+/// call reads nothing.
+fn read_hash(caller: &mut Caller<'_, HostState>, reads: &[(i32, i32)]) -> String {
+    if reads.is_empty() {
+        return String::new();
+    }
+    let memory = guest_memory(caller);
+    let mut hasher = blake3::Hasher::new();
+    for &(ptr, len) in reads {
+        match memory.and_then(|memory| guest_slice(caller, memory, ptr, len)) {
+            Some(bytes) => {
+                hasher.update(&(bytes.len() as u64).to_le_bytes());
+                hasher.update(bytes);
+            }
+            None => {
+                hasher.update(&u64::MAX.to_le_bytes());
+            }
+        }
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Run a replayed call again and check it against its record
+fn recompute_call<R: HostValue>(
+    caller: &mut Caller<'_, HostState>,
+    function: &'static str,
+    args: &[i64],
+    read_hash: &str,
+    call: impl FnOnce(&mut Caller<'_, HostState>) -> R,
+) -> anyhow::Result<R> {
+    caller
+        .data_mut()
+        .transcript
+        .next_replayed(function, args, read_hash, &[])
+        .map_err(|divergence| anyhow!("replay diverged at {}", divergence))?;
+    let result = call(caller);
+    caller
+        .data_mut()
+        .transcript
+        .check_recomputed(result.to_i64())
+        .map_err(|divergence| anyhow!("replay diverged at {}", divergence))?;
+    Ok(result)
+}
+
+/// Check a signature answered from the transcript: when the data and
+/// output buffer are in bounds, the recorded call must have succeeded and
+/// written a signature that verifies under the capsule's public key
+fn check_replayed_signature(
+    caller: &mut Caller<'_, HostState>,
+    data_ptr: i32,
+    data_len: i32,
+    out_ptr: i32,
+    result: i32,
+) -> anyhow::Result<()> {
+    let Some(memory) = guest_memory(caller) else {
+        return Ok(());
+    };
+    let data = guest_slice(caller, memory, data_ptr, data_len);
+    let signature = guest_slice(caller, memory, out_ptr, SIGNATURE_LEN as i32);
+    let (Some(data), Some(signature), Some(signer)) = (data, signature, &caller.data().signer)
+    else {
+        return Ok(());
+    };
+
+    let signature = Signature::from_bytes(signature.try_into().expect("signature length"));
+    if result == HOST_OK && signer.verifying_key().verify(data, &signature).is_ok() {
+        return Ok(());
+    }
+    let divergence = caller.data_mut().transcript.diverge(
+        "with a signature under the capsule key".to_string(),
+        "with an invalid signature".to_string(),
+    );
+    Err(anyhow!("replay diverged at {}", divergence))
+}
+
+/// Answer a call from the transcript being replayed: apply its recorded
+/// writes and return its recorded result
+fn replay_call(
+    caller: &mut Caller<'_, HostState>,
+    function: &'static str,
+    args: &[i64],
+    read_hash: &str,
+    request: &[u8],
+) -> anyhow::Result<i64> {
+    let record = caller
+        .data_mut()
+        .transcript
+        .next_replayed(function, args, read_hash, request)
+        .map_err(|divergence| anyhow!("replay diverged at {}", divergence))?;
+    if !record.writes.is_empty() {
+        let memory = guest_memory(caller)
+            .ok_or_else(|| anyhow!("replayed {} writes to missing memory", function))?;
+        for write in &record.writes {
+            write_guest(caller, memory, write.ptr as i32, &write.data)
+                .map_err(|_| anyhow!("replayed {} write out of bounds", function))?;
+        }
+    }
+    Ok(record.result)
+}
+
+/// Compute the Blake3 hash of `[ptr, ptr + len)` and write the 32-byte
+/// digest to `out_ptr`.
+///
+/// Returns `HOST_OK` on success or a negative error code.
+fn hash_commit(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32, out_ptr: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let digest = match guest_slice(caller, memory, ptr, len) {
+        Some(data) => blake3::hash(data),
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    match write_guest(caller, memory, out_ptr, digest.as_bytes()) {
+        Ok(()) => HOST_OK,
+        Err(code) => code,
+    }
+}
+
+/// Check whether the Blake3 hash of `[ptr, ptr + len)` equals the 32-byte
+/// digest stored at `hash_ptr`.
+///
+/// Returns 1 on match, 0 on mismatch, or a negative error code.
+fn hash_verify(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32, hash_ptr: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let digest = match guest_slice(caller, memory, ptr, len) {
+        Some(data) => blake3::hash(data),
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    let expected = match guest_slice(caller, memory, hash_ptr, HASH_LEN as i32) {
+        Some(bytes) => {
+            let mut buf = [0u8; HASH_LEN];
+            buf.copy_from_slice(bytes);
+            blake3::Hash::from(buf)
+        }
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    // blake3::Hash equality is constant-time
+    (digest == expected) as i32
+}
+
+/// Evaluate a JSONPath expression and write the matches as JSON text to
+/// `[out_ptr, out_ptr + out_cap)`.
+///
+/// A path without wildcards yields the single matching value; a wildcard
+/// path yields a JSON array of every match (possibly empty). Returns the
+/// number of bytes written or a negative error code.
+fn json_path(
+    caller: &mut Caller<'_, HostState>,
+    data_ptr: i32,
+    data_len: i32,
+    path_ptr: i32,
+    path_len: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let result = match eval_json_path(caller, memory, data_ptr, data_len, path_ptr, path_len) {
+        Ok((path, matches)) => {
+            if path.has_wildcard() {
+                Value::Array(matches)
+            } else {
+                match matches.into_iter().next() {
+                    Some(value) => value,
+                    None => return HOST_ERR_PATH_NOT_FOUND,
+                }
+            }
+        }
+        Err(code) => return code,
+    };
+
+    write_output(
+        caller,
+        memory,
+        out_ptr,
+        out_cap,
+        result.to_string().as_bytes(),
+    )
+}
+
+/// Extract the single value matched by a JSONPath expression into
+/// `[out_ptr, out_ptr + out_cap)`.
+///
+/// Strings are written as raw UTF-8 without quotes or escapes; every other
+/// value is written as JSON text. Returns the number of bytes written or a
+/// negative error code.
+fn json_extract(
+    caller: &mut Caller<'_, HostState>,
+    data_ptr: i32,
+    data_len: i32,
+    path_ptr: i32,
+    path_len: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let value = match eval_json_path(caller, memory, data_ptr, data_len, path_ptr, path_len) {
+        Ok((_, matches)) if matches.len() > 1 => return HOST_ERR_AMBIGUOUS_PATH,
+        Ok((_, matches)) => match matches.into_iter().next() {
+            Some(value) => value,
+            None => return HOST_ERR_PATH_NOT_FOUND,
+        },
+        Err(code) => return code,
+    };
+
+    let bytes = match value {
+        Value::String(text) => text.into_bytes(),
+        other => other.to_string().into_bytes(),
+    };
+
+    write_output(caller, memory, out_ptr, out_cap, &bytes)
+}
+
+/// Parse the guest document and path, returning owned copies of every match
+fn eval_json_path(
+    caller: &Caller<'_, HostState>,
+    memory: Memory,
+    data_ptr: i32,
+    data_len: i32,
+    path_ptr: i32,
+    path_len: i32,
+) -> Result<(JsonPath, Vec<Value>), i32> {
+    let data = guest_slice(caller, memory, data_ptr, data_len).ok_or(HOST_ERR_OUT_OF_BOUNDS)?;
+    let path = guest_slice(caller, memory, path_ptr, path_len).ok_or(HOST_ERR_OUT_OF_BOUNDS)?;
+
+    let document: Value = serde_json::from_slice(data).map_err(|_| HOST_ERR_INVALID_JSON)?;
+    let path = std::str::from_utf8(path).map_err(|_| HOST_ERR_INVALID_PATH)?;
+    let path = JsonPath::parse(path).map_err(|_| HOST_ERR_INVALID_PATH)?;
+
+    let matches = path.evaluate(&document).into_iter().cloned().collect();
+    Ok((path, matches))
+}
+
+/// Base64-encode `[ptr, ptr + len)` with the given alphabet and write the
+/// text to `[out_ptr, out_ptr + out_cap)`.
+///
+/// Returns the number of bytes written or a negative error code.
+fn base64_encode(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+    variant: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+    let (encoder, _) = match base64_engines(variant) {
+        Some(engines) => engines,
+        None => return HOST_ERR_INVALID_ARGUMENT,
+    };
+
+    let encoded = match guest_slice(caller, memory, ptr, len) {
+        Some(data) => encoder.encode(data),
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    write_output(caller, memory, out_ptr, out_cap, encoded.as_bytes())
+}
+
+/// Decode the Base64 text at `[ptr, ptr + len)` with the given alphabet and
+/// write the raw bytes to `[out_ptr, out_ptr + out_cap)`.
+///
+/// Padding is optional for both alphabets. Returns the number of bytes
+/// written or a negative error code.
+fn base64_decode(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+    variant: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+    let (_, decoder) = match base64_engines(variant) {
+        Some(engines) => engines,
+        None => return HOST_ERR_INVALID_ARGUMENT,
+    };
+
+    let decoded = match guest_slice(caller, memory, ptr, len) {
+        Some(text) => match decoder.decode(text) {
+            Ok(bytes) => bytes,
+            Err(_) => return HOST_ERR_INVALID_BASE64,
+        },
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    write_output(caller, memory, out_ptr, out_cap, &decoded)
+}
+
+/// Lenient decoders that accept input with or without padding
+const STANDARD_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+const URL_SAFE_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Resolve a Base64 variant to its `(encoder, decoder)` engines
+fn base64_engines(variant: i32) -> Option<(&'static GeneralPurpose, &'static GeneralPurpose)> {
+    match variant {
+        BASE64_STANDARD => Some((&STANDARD, &STANDARD_LENIENT)),
+        BASE64_URL_SAFE => Some((&URL_SAFE_NO_PAD, &URL_SAFE_LENIENT)),
+        _ => None,
+    }
+}
+
+/// Return the logical execution timestamp in Unix milliseconds.
+///
+/// The value is fixed before the capsule starts and recorded in the receipt,
+/// so repeated calls and re-executions observe the same time.
+fn time_now_ms(caller: &mut Caller<'_, HostState>) -> i64 {
+    caller.data().logical_time_ms as i64
+}
+
+/// Write the logical execution timestamp as RFC 3339 text with millisecond
+/// precision (e.g. `2024-01-01T00:00:00.000Z`) to `[out_ptr, out_ptr + out_cap)`.
+///
+/// Returns the number of bytes written or a negative error code.
+fn time_iso8601(caller: &mut Caller<'_, HostState>, out_ptr: i32, out_cap: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let timestamp = match format_iso8601(caller.data().logical_time_ms) {
+        Some(timestamp) => timestamp,
+        None => return HOST_ERR_INVALID_ARGUMENT,
+    };
+
+    write_output(caller, memory, out_ptr, out_cap, timestamp.as_bytes())
+}
+
+/// Format Unix milliseconds as an RFC 3339 UTC timestamp
+pub(crate) fn format_iso8601(unix_ms: u64) -> Option<String> {
+    let millis = i64::try_from(unix_ms).ok()?;
+    let time = chrono::DateTime::from_timestamp_millis(millis)?;
+    Some(time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+/// Fill `[out_ptr, out_ptr + len)` with the next bytes of the seeded
+/// random stream.
+///
+/// Returns `HOST_OK` on success or a negative error code. Nothing is drawn
+/// from the stream when the buffer is out of bounds.
+fn random_bytes(caller: &mut Caller<'_, HostState>, out_ptr: i32, len: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+    if guest_range(memory.data_size(&*caller), out_ptr, len).is_none() {
+        return HOST_ERR_OUT_OF_BOUNDS;
+    }
+
+    let mut bytes = vec![0u8; len as u32 as usize];
+    caller.data_mut().rng.fill(&mut bytes);
+    match write_guest(caller, memory, out_ptr, &bytes) {
+        Ok(()) => HOST_OK,
+        Err(code) => code,
+    }
+}
+
+/// Return the next 32-bit value of the seeded random stream
+fn random_u32(caller: &mut Caller<'_, HostState>) -> i32 {
+    let mut buf = [0u8; 4];
+    caller.data_mut().rng.fill(&mut buf);
+    i32::from_le_bytes(buf)
+}
+
+/// Copy the value stored under the key at `[key_ptr, key_ptr + key_len)` to
+/// `out_ptr`.
+///
+/// Returns the number of bytes written, `HOST_ERR_STATE_NOT_FOUND` if the
+/// key is absent, or another negative error code.
+fn state_get(
+    caller: &mut Caller<'_, HostState>,
+    key_ptr: i32,
+    key_len: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let key = match guest_range(data.len(), key_ptr, key_len) {
+        Some(range) => &data[range],
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+    let value = match state.state.get(key) {
+        Some(value) => value.to_vec(),
+        None => return HOST_ERR_STATE_NOT_FOUND,
+    };
+
+    write_output(caller, memory, out_ptr, out_cap, &value)
+}
+
+/// Store the bytes at `[value_ptr, value_ptr + value_len)` under the key at
+/// `[key_ptr, key_ptr + key_len)`.
+///
+/// Returns `HOST_OK` on success, `HOST_ERR_INVALID_ARGUMENT` if the key is
+/// longer than `MAX_STATE_KEY_LEN`, `HOST_ERR_STATE_FULL` if the namespace
+/// would exceed its byte limit, or another negative error code.
+fn state_put(
+    caller: &mut Caller<'_, HostState>,
+    key_ptr: i32,
+    key_len: i32,
+    value_ptr: i32,
+    value_len: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let (key, value) = match (
+        guest_range(data.len(), key_ptr, key_len),
+        guest_range(data.len(), value_ptr, value_len),
+    ) {
+        (Some(key), Some(value)) => (&data[key], &data[value]),
+        _ => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    match state.state.put(key, value) {
+        Ok(()) => HOST_OK,
+        Err(PutError::KeyTooLong) => HOST_ERR_INVALID_ARGUMENT,
+        Err(PutError::Full) => HOST_ERR_STATE_FULL,
+    }
+}
+
+/// Remove the key at `[key_ptr, key_ptr + key_len)`.
+///
+/// Returns `1` if the key was removed, `0` if it was absent, or a negative
+/// error code.
+fn state_delete(caller: &mut Caller<'_, HostState>, key_ptr: i32, key_len: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    match guest_range(data.len(), key_ptr, key_len) {
+        Some(range) => state.state.delete(&data[range]) as i32,
+        None => HOST_ERR_OUT_OF_BOUNDS,
+    }
+}
+
+/// Compute the HMAC-SHA256 of `[data_ptr, data_ptr + data_len)` under the
+/// secret named by `[id_ptr, id_ptr + id_len)` and write the 32-byte tag to
+/// `out_ptr`.
+///
+/// Returns `HOST_OK` on success, `HOST_ERR_SECRET_UNAVAILABLE` if the secret
+/// is not granted, or another negative error code.
+fn secret_hmac_sha256(
+    caller: &mut Caller<'_, HostState>,
+    id_ptr: i32,
+    id_len: i32,
+    data_ptr: i32,
+    data_len: i32,
+    out_ptr: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let tag = match secret_and_data(caller, memory, id_ptr, id_len, data_ptr, data_len) {
+        Ok((key, data)) => secrets::hmac_sha256(key, data),
+        Err(code) => return code,
+    };
+
+    match write_guest(caller, memory, out_ptr, &tag) {
+        Ok(()) => HOST_OK,
+        Err(code) => code,
+    }
+}
+
+/// Check the 32-byte tag at `tag_ptr` against the HMAC-SHA256 of
+/// `[data_ptr, data_ptr + data_len)` under the secret named by
+/// `[id_ptr, id_ptr + id_len)`, in constant time.
+///
+/// Returns 1 on match, 0 on mismatch, or a negative error code.
+fn secret_hmac_verify(
+    caller: &mut Caller<'_, HostState>,
+    id_ptr: i32,
+    id_len: i32,
+    data_ptr: i32,
+    data_len: i32,
+    tag_ptr: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+    let tag = match guest_slice(caller, memory, tag_ptr, HMAC_SHA256_LEN as i32) {
+        Some(tag) => tag.to_vec(),
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+
+    match secret_and_data(caller, memory, id_ptr, id_len, data_ptr, data_len) {
+        Ok((key, data)) => secrets::hmac_sha256_verify(key, data, &tag) as i32,
+        Err(code) => code,
+    }
+}
+
+/// Borrow the secret named by `[id_ptr, id_ptr + id_len)` and the guest
+/// bytes `[data_ptr, data_ptr + data_len)`, recording the secret as used
+fn secret_and_data<'a>(
+    caller: &'a mut Caller<'_, HostState>,
+    memory: Memory,
+    id_ptr: i32,
+    id_len: i32,
+    data_ptr: i32,
+    data_len: i32,
+) -> Result<(&'a [u8], &'a [u8]), i32> {
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let (id, data) = match (
+        guest_range(data.len(), id_ptr, id_len),
+        guest_range(data.len(), data_ptr, data_len),
+    ) {
+        (Some(id), Some(range)) => (&data[id], &data[range]),
+        _ => return Err(HOST_ERR_OUT_OF_BOUNDS),
+    };
+    let id = std::str::from_utf8(id).map_err(|_| HOST_ERR_SECRET_UNAVAILABLE)?;
+    let key = state
+        .secrets
+        .use_secret(id)
+        .ok_or(HOST_ERR_SECRET_UNAVAILABLE)?;
+    Ok((key, data))
+}
+
+/// Sign `[data_ptr, data_ptr + data_len)` with the capsule's derived
+/// Ed25519 key and write the 64-byte signature to `out_ptr`.
+///
+/// Returns `HOST_OK` on success or a negative error code.
+fn sign_ed25519(
+    caller: &mut Caller<'_, HostState>,
+    data_ptr: i32,
+    data_len: i32,
+    out_ptr: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let data = match guest_range(data.len(), data_ptr, data_len) {
+        Some(range) => &data[range],
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+    let signature = match state.signer.as_ref().and_then(|signer| signer.sign(data)) {
+        Some(signature) => signature,
+        None => return HOST_ERR_SIGN_UNAVAILABLE,
+    };
+
+    match write_guest(caller, memory, out_ptr, &signature) {
+        Ok(()) => HOST_OK,
+        Err(code) => code,
+    }
+}
+
+/// Write the 32-byte public key that verifies `sign_ed25519` signatures to
+/// `out_ptr`.
+///
+/// Returns `HOST_OK` on success or a negative error code.
+fn sign_public_key(caller: &mut Caller<'_, HostState>, out_ptr: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+    let public_key = match &caller.data().signer {
+        Some(signer) => signer.verifying_key().to_bytes(),
+        None => return HOST_ERR_SIGN_UNAVAILABLE,
+    };
+
+    match write_guest(caller, memory, out_ptr, &public_key) {
+        Ok(()) => HOST_OK,
+        Err(code) => code,
+    }
+}
+
+/// Number of input chunks of a chunked I/O run
+fn io_input_chunks(caller: &mut Caller<'_, HostState>) -> i32 {
+    match &caller.data().chunked_io {
+        Some(io) => io.input_chunk_count().min(i32::MAX as usize) as i32,
+        None => 0,
+    }
+}
+
+/// Copy input chunk `index` into the guest buffer at `out_ptr` of capacity
+/// `out_cap`.
+///
+/// Returns the number of bytes written, `HOST_ERR_CHUNK_NOT_FOUND` past the
+/// last chunk, or another negative error code.
+fn io_read_chunk(
+    caller: &mut Caller<'_, HostState>,
+    index: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let out = match guest_range(data.len(), out_ptr, out_cap) {
+        Some(range) => &mut data[range],
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+    let chunk = match usize::try_from(index)
+        .ok()
+        .and_then(|index| state.chunked_io.as_ref()?.input_chunk(index))
+    {
+        Some(chunk) => chunk,
+        None => return HOST_ERR_CHUNK_NOT_FOUND,
+    };
+    if chunk.len() > out.len() {
+        return HOST_ERR_BUFFER_TOO_SMALL;
+    }
+
+    out[..chunk.len()].copy_from_slice(chunk);
+    chunk.len() as i32
+}
+
+/// Append `[ptr, ptr + len)` to the output of a chunked I/O run.
+///
+/// Returns `HOST_OK` on success, `HOST_ERR_OUTPUT_FULL` if the output limit
+/// cannot hold it, or another negative error code.
+fn io_write_chunk(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> i32 {
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let chunk = match guest_range(data.len(), ptr, len) {
+        Some(range) => &data[range],
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+    let written = state
+        .chunked_io
+        .as_mut()
+        .is_some_and(|io| io.write_output(chunk));
+    if written {
+        HOST_OK
+    } else {
+        HOST_ERR_OUTPUT_FULL
+    }
+}
+
+/// Append `[ptr, ptr + len)` to the run's log at `level` (0 = error,
+/// 1 = warn, 2 = info, 3 = debug).
+///
+/// Returns `HOST_OK` on success or a negative error code. A message that
+/// does not fit the remaining log budget is dropped.
+fn log_write(caller: &mut Caller<'_, HostState>, level: i32, ptr: i32, len: i32) -> i32 {
+    let level = match LogLevel::from_code(level) {
+        Some(level) => level,
+        None => return HOST_ERR_INVALID_ARGUMENT,
+    };
+    let memory = match guest_memory(caller) {
+        Some(memory) => memory,
+        None => return HOST_ERR_NO_MEMORY,
+    };
+
+    let (data, state) = memory.data_and_store_mut(&mut *caller);
+    let message = match guest_range(data.len(), ptr, len) {
+        Some(range) => &data[range],
+        None => return HOST_ERR_OUT_OF_BOUNDS,
+    };
+    if state.log.write(level, message) {
+        HOST_OK
+    } else {
+        HOST_ERR_LOG_BUDGET_EXCEEDED
+    }
+}
+
+/// Send the JSON request at `[req_ptr, req_ptr + req_len)` and write the
+/// JSON response (`status`, `headers`, `body`) to `[out_ptr, out_ptr + out_cap)`.
+///
+/// The request has a `method`, an absolute `url` and optional `headers` and
+/// `body`. Returns the number of bytes written or a negative error code.
+/// The request bytes are recorded with the call, so a replayed run must send
+/// the same request to receive the recorded response.
+async fn http_request(
+    caller: &mut Caller<'_, HostState>,
+    req_ptr: i32,
+    req_len: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> anyhow::Result<i32> {
+    const FUNCTION: &str = "http_request";
+    let args: Vec<i64> = [req_ptr, req_len, out_ptr, out_cap]
+        .iter()
+        .map(|&arg| arg as i64)
+        .collect();
+    count_call(caller, FUNCTION);
+
+    let memory = guest_memory(caller);
+    let request = memory
+        .and_then(|memory| guest_slice(caller, memory, req_ptr, req_len))
+        .map(<[u8]>::to_vec);
+    let recorded_request = request.as_deref().unwrap_or_default();
+    if caller.data().transcript.is_replay() {
+        return replay_call(caller, FUNCTION, &args, "", recorded_request).map(|r| r as i32);
+    }
+    caller
+        .data_mut()
+        .transcript
+        .begin_record(FUNCTION, &args, "", recorded_request);
+
+    let result = match (memory, request, caller.data().http.clone()) {
+        (None, _, _) => HOST_ERR_NO_MEMORY,
+        (_, None, _) => HOST_ERR_OUT_OF_BOUNDS,
+        (_, _, None) => HOST_ERR_HTTP_DENIED,
+        (Some(memory), Some(request), Some(http)) => {
+            // The request may not outlive the run: stopping here keeps the
+            // timeout on the epoch path, which reports the fuel used
+            let deadline = caller.data().deadline;
+            let send = http.send(&request, &mut caller.data_mut().secrets);
+            let sent = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), send).await,
+                None => Ok(send.await),
+            };
+            let Ok(sent) = sent else {
+                caller.data_mut().deadline_exceeded = true;
+                return Err(anyhow!("execution deadline exceeded"));
+            };
+            match sent {
+                Ok(response) => write_output(caller, memory, out_ptr, out_cap, &response),
+                Err(HttpError::InvalidJson) => HOST_ERR_INVALID_JSON,
+                Err(HttpError::InvalidRequest) => HOST_ERR_INVALID_ARGUMENT,
+                Err(HttpError::Denied) => HOST_ERR_HTTP_DENIED,
+                Err(HttpError::TooLarge) => HOST_ERR_HTTP_TOO_LARGE,
+                Err(HttpError::Timeout) => HOST_ERR_HTTP_TIMEOUT,
+                Err(HttpError::Failed) => HOST_ERR_HTTP_FAILED,
+                Err(HttpError::SecretUnavailable) => HOST_ERR_SECRET_UNAVAILABLE,
+            }
+        }
+    };
+    caller.data_mut().transcript.finish_record(result as i64);
+    Ok(result)
+}
+
+/// Count a call to `function` and note the fuel left when it started
+fn count_call(caller: &mut Caller<'_, HostState>, function: &'static str) {
+    if let Ok(remaining) = caller.get_fuel() {
+        caller
+            .data()
+            .fuel_remaining
+            .store(remaining, Ordering::Relaxed);
+    }
+    caller.data_mut().record_call(function);
+}
+
+/// Look up the capsule's exported linear memory
+pub(crate) fn guest_memory(caller: &mut Caller<'_, HostState>) -> Option<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Some(memory),
+        _ => None,
+    }
+}
+
+/// Resolve a guest `(ptr, len)` pair to a host byte range, if it is in bounds
+pub(crate) fn guest_range(
+    memory_size: usize,
+    ptr: i32,
+    len: i32,
+) -> Option<std::ops::Range<usize>> {
+    // Guest pointers are unsigned 32-bit offsets
+    let start = ptr as u32 as usize;
+    let len = len as u32 as usize;
+    let end = start.checked_add(len)?;
+
+    if end > memory_size {
+        return None;
+    }
+
+    Some(start..end)
+}
+
+/// Borrow a slice of guest memory, or `None` if it is out of bounds
+pub(crate) fn guest_slice<'a>(
+    caller: &'a Caller<'_, HostState>,
+    memory: Memory,
+    ptr: i32,
+    len: i32,
+) -> Option<&'a [u8]> {
+    let data = memory.data(caller);
+    let range = guest_range(data.len(), ptr, len)?;
+    Some(&data[range])
+}
+
+/// Copy bytes into guest memory at `ptr`
+pub(crate) fn write_guest(
+    caller: &mut Caller<'_, HostState>,
+    memory: Memory,
+    ptr: i32,
+    bytes: &[u8],
+) -> Result<(), i32> {
+    let data = memory.data_mut(&mut *caller);
+    let range = guest_range(data.len(), ptr, bytes.len() as i32).ok_or(HOST_ERR_OUT_OF_BOUNDS)?;
+    data[range].copy_from_slice(bytes);
+    caller.data_mut().transcript.record_write(ptr, bytes);
+    Ok(())
+}
+
+/// Write a result into a guest output buffer of capacity `out_cap`.
+///
+/// Returns the number of bytes written, `HOST_ERR_BUFFER_TOO_SMALL` if the
+/// result does not fit, or `HOST_ERR_OUT_OF_BOUNDS` if the buffer itself is
+/// outside linear memory.
+pub(crate) fn write_output(
+    caller: &mut Caller<'_, HostState>,
+    memory: Memory,
+    out_ptr: i32,
+    out_cap: i32,
+    bytes: &[u8],
+) -> i32 {
+    if guest_range(memory.data_size(&*caller), out_ptr, out_cap).is_none() {
+        return HOST_ERR_OUT_OF_BOUNDS;
+    }
+    if bytes.len() > out_cap as u32 as usize || bytes.len() > i32::MAX as usize {
+        return HOST_ERR_BUFFER_TOO_SMALL;
+    }
+
+    match write_guest(caller, memory, out_ptr, bytes) {
+        Ok(()) => bytes.len() as i32,
+        Err(code) => code,
+    }
+}
+
+/// Build the error returned when a host function cannot be linked
+fn link_error(function: &str, error: anyhow::Error) -> ExecutionError {
+    ExecutionError::ExecutionFailed {
+        reason: format!("Failed to link {}: {}", function, error),
+    }
+}
+
+/// Helpers for tests that run capsules against the host functions, shared
+/// with the tests of each capability's module
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::execution::{ExecutionResult, WasmRuntime};
+    use crate::receipts::generate_test_signing_key;
+    use crate::sandbox::{Capability, ResourceLimits};
+
+    /// Default limits plus `capabilities`
+    pub(crate) fn limits_with(capabilities: &[Capability]) -> ResourceLimits {
+        let mut limits = ResourceLimits::default();
+        for &capability in capabilities {
+            limits.add_capability(capability);
+        }
+        limits
+    }
+
+    /// Compile `wat` and run it on `input` with a fresh runtime
+    pub(crate) async fn run_wat(
+        wat: &str,
+        input: &[u8],
+        limits: ResourceLimits,
+    ) -> ExecutionResult {
+        let capsule = wat::parse_str(wat).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        runtime.execute(&capsule, input, limits).await.unwrap()
+    }
+
+    /// End of a `run` body that returns the status code in local `$n`
+    /// followed by the bytes written at `out_ptr`. The status is stored in
+    /// the four bytes before `out_ptr`; read it back with [`split_status`].
+    pub(crate) fn return_status_and_output(out_ptr: u32) -> String {
+        format!(
+            r#"
+            (i32.store (i32.const {status_ptr}) (local.get $n))
+            (i32.or
+              (i32.shl
+                (i32.add (i32.const 4)
+                  (select (local.get $n) (i32.const 0) (i32.gt_s (local.get $n) (i32.const 0))))
+                (i32.const 16))
+              (i32.const {status_ptr}))"#,
+            status_ptr = out_ptr - 4,
+        )
+    }
+
+    /// Split output produced by [`return_status_and_output`] into the
+    /// status code and the written bytes
+    pub(crate) fn split_status(output: &[u8]) -> (i32, &[u8]) {
+        let (status, bytes) = output.split_at(4);
+        (i32::from_le_bytes(status.try_into().unwrap()), bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{limits_with, return_status_and_output, run_wat, split_status};
+    use super::*;
+    use crate::execution::{ExecutionOptions, WasmRuntime};
+    use crate::receipts::{generate_test_signing_key, ReplayContext};
+    use crate::sandbox::ResourceLimits;
+
+    /// Capsule that hashes its input with `hash_commit` and returns the digest.
+    /// Setting the first input byte to `!` passes an out-of-bounds pointer and
+    /// returns the status code instead.
+    const HASH_COMMIT_WAT: &str = r#"
+        (module
+          (import "env" "hash_commit" (func $hash_commit (param i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (local $status i32)
+            (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 33))
+              (then
+                (local.set $status
+                  (call $hash_commit (local.get $ptr) (i32.const 100000) (i32.const 4096)))
+                (i32.store (i32.const 4096) (local.get $status))
+                (return (i32.or (i32.shl (i32.const 4) (i32.const 16)) (i32.const 4096)))))
+            (drop (call $hash_commit (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (i32.or (i32.shl (i32.const 32) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    /// Capsule that commits to its input, then verifies it against both the
+    /// real digest and a corrupted one, returning the two results.
+    const HASH_VERIFY_WAT: &str = r#"
+        (module
+          (import "env" "hash_commit" (func $hash_commit (param i32 i32 i32) (result i32)))
+          (import "env" "hash_verify" (func $hash_verify (param i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (drop (call $hash_commit (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (i32.store8 (i32.const 8192)
+              (call $hash_verify (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (i32.store8 (i32.const 4096) (i32.xor (i32.load8_u (i32.const 4096)) (i32.const 1)))
+            (i32.store8 (i32.const 8193)
+              (call $hash_verify (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (i32.store8 (i32.const 8194)
+              (call $hash_verify (local.get $ptr) (local.get $len) (i32.const 65530)))
+            (i32.or (i32.shl (i32.const 3) (i32.const 16)) (i32.const 8192))))
+    "#;
+
+    async fn run(wat: &str, input: &[u8]) -> Vec<u8> {
+        run_wat(wat, input, ResourceLimits::default()).await.output
+    }
+
+    /// Run `json_path` or `json_extract` over the input with a fixed path and
+    /// output capacity, returning the status code and the written bytes
+    async fn run_json(function: &str, path: &str, out_cap: i32, input: &[u8]) -> (i32, Vec<u8>) {
+        let wat = format!(
+            r#"
+            (module
+              (import "env" "{function}" (func $f (param i32 i32 i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 16) "{path}")
+              (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                (local $n i32)
+                (local.set $n
+                  (call $f (local.get $ptr) (local.get $len)
+                    (i32.const 16) (i32.const {path_len})
+                    (i32.const 4096) (i32.const {out_cap})))
+                {result}))
+            "#,
+            path_len = path.len(),
+            result = return_status_and_output(4096),
+        );
+        let output = run(&wat, input).await;
+        let (status, out) = split_status(&output);
+        (status, out.to_vec())
+    }
+
+    /// Run `base64_encode` or `base64_decode` over the input with the given
+    /// variant and output capacity, returning the status code and the written bytes
+    async fn run_base64(
+        function: &str,
+        variant: i32,
+        out_cap: i32,
+        input: &[u8],
+    ) -> (i32, Vec<u8>) {
+        let wat = format!(
+            r#"
+            (module
+              (import "env" "{function}" (func $f (param i32 i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                (local $n i32)
+                (local.set $n
+                  (call $f (local.get $ptr) (local.get $len) (i32.const {variant})
+                    (i32.const 4096) (i32.const {out_cap})))
+                {result}))
+            "#,
+            result = return_status_and_output(4096),
+        );
+        let limits = limits_with(&[Capability::Base64]);
+        let output = run_wat(&wat, input, limits).await.output;
+        let (status, out) = split_status(&output);
+        (status, out.to_vec())
+    }
+
+    #[test]
+    fn test_guest_range_bounds() {
+        assert_eq!(guest_range(100, 10, 20), Some(10..30));
+        assert_eq!(guest_range(100, 0, 100), Some(0..100));
+        assert_eq!(guest_range(100, 90, 11), None);
+        assert_eq!(guest_range(100, -1, 1), None);
+        assert_eq!(guest_range(100, 10, -1), None);
+    }
+
+    #[tokio::test]
+    async fn test_hash_commit_writes_digest() {
+        let input = b"{\"hello\": \"tenzik\"}";
+        let output = run(HASH_COMMIT_WAT, input).await;
+        assert_eq!(output, blake3::hash(input).as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_hash_commit_out_of_bounds() {
+        let output = run(HASH_COMMIT_WAT, b"!oob").await;
+        let status = i32::from_le_bytes(output.try_into().unwrap());
+        assert_eq!(status, HOST_ERR_OUT_OF_BOUNDS);
+    }
+
+    #[tokio::test]
+    async fn test_hash_verify() {
+        let output = run(HASH_VERIFY_WAT, b"verify me").await;
+        assert_eq!(output[0], 1);
+        assert_eq!(output[1], 0);
+        assert_eq!(output[2] as i8 as i32, HOST_ERR_OUT_OF_BOUNDS);
+    }
+
+    #[tokio::test]
+    async fn test_json_path_single_and_wildcard() {
+        let doc = br#"{"user":{"name":"ada","age":36},"items":[{"sku":"a1"},{"sku":"b2"}]}"#;
+
+        let (status, out) = run_json("json_path", "$.user.name", 256, doc).await;
+        assert_eq!(status, 5);
+        assert_eq!(out, br#""ada""#);
+
+        let (status, out) = run_json("json_path", "$.items[*].sku", 256, doc).await;
+        assert_eq!(status as usize, out.len());
+        assert_eq!(out, br#"["a1","b2"]"#);
+
+        let (_, out) = run_json("json_path", "$.items[-1]", 256, doc).await;
+        assert_eq!(out, br#"{"sku":"b2"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_json_extract_unwraps_strings() {
+        let doc = br#"{"user":{"name":"a \"quoted\" name","age":36}}"#;
+
+        let (_, out) = run_json("json_extract", "$.user.name", 256, doc).await;
+        assert_eq!(out, br#"a "quoted" name"#);
+
+        let (_, out) = run_json("json_extract", "user.age", 256, doc).await;
+        assert_eq!(out, b"36");
+
+        let (status, _) = run_json("json_extract", "$.user.*", 256, doc).await;
+        assert_eq!(status, HOST_ERR_AMBIGUOUS_PATH);
+    }
+
+    #[tokio::test]
+    async fn test_json_error_codes() {
+        let doc = br#"{"user":{"name":"ada"}}"#;
+
+        let (status, _) = run_json("json_path", "$.user.email", 256, doc).await;
+        assert_eq!(status, HOST_ERR_PATH_NOT_FOUND);
+
+        let (status, _) = run_json("json_extract", "$.user.email", 256, doc).await;
+        assert_eq!(status, HOST_ERR_PATH_NOT_FOUND);
+
+        let (status, _) = run_json("json_path", "$.user", 256, b"{not json").await;
+        assert_eq!(status, HOST_ERR_INVALID_JSON);
+
+        let (status, _) = run_json("json_path", "$.user[", 256, doc).await;
+        assert_eq!(status, HOST_ERR_INVALID_PATH);
+
+        let (status, _) = run_json("json_path", "$.user", 4, doc).await;
+        assert_eq!(status, HOST_ERR_BUFFER_TOO_SMALL);
+
+        let (status, _) = run_json("json_path", "$.user", 70000, doc).await;
+        assert_eq!(status, HOST_ERR_OUT_OF_BOUNDS);
+    }
+
+    #[tokio::test]
+    async fn test_base64_round_trip() {
+        let data = b"tenzik\xfb\xff capsule";
+
+        let (status, out) = run_base64("base64_encode", BASE64_STANDARD, 256, data).await;
+        assert_eq!(status as usize, out.len());
+        assert_eq!(out, STANDARD.encode(data).as_bytes());
+        assert!(out.contains(&b'/') && out.ends_with(b"="));
+
+        let (_, decoded) = run_base64("base64_decode", BASE64_STANDARD, 256, &out).await;
+        assert_eq!(decoded, data);
+
+        let (_, out) = run_base64("base64_encode", BASE64_URL_SAFE, 256, data).await;
+        assert_eq!(out, URL_SAFE_NO_PAD.encode(data).as_bytes());
+        assert!(out.contains(&b'_') && !out.ends_with(b"="));
+
+        let (_, decoded) = run_base64("base64_decode", BASE64_URL_SAFE, 256, &out).await;
+        assert_eq!(decoded, data);
+
+        // Padding is accepted but not required when decoding
+        let (_, decoded) = run_base64("base64_decode", BASE64_STANDARD, 256, b"aGk").await;
+        assert_eq!(decoded, b"hi");
+    }
+
+    #[tokio::test]
+    async fn test_base64_error_codes() {
+        let (status, _) = run_base64("base64_decode", BASE64_STANDARD, 256, b"a*b=").await;
+        assert_eq!(status, HOST_ERR_INVALID_BASE64);
+
+        let (status, _) = run_base64("base64_decode", BASE64_URL_SAFE, 256, b"+/+/").await;
+        assert_eq!(status, HOST_ERR_INVALID_BASE64);
+
+        let (status, _) = run_base64("base64_encode", 7, 256, b"data").await;
+        assert_eq!(status, HOST_ERR_INVALID_ARGUMENT);
+
+        let (status, _) = run_base64("base64_encode", BASE64_STANDARD, 4, b"data").await;
+        assert_eq!(status, HOST_ERR_BUFFER_TOO_SMALL);
+
+        let (status, _) = run_base64("base64_encode", BASE64_STANDARD, 70000, b"data").await;
+        assert_eq!(status, HOST_ERR_OUT_OF_BOUNDS);
+    }
+
+    /// Capsule that calls `time_now_ms` twice and `time_iso8601` once,
+    /// returning both millisecond values followed by the ISO 8601 text.
+    const TIME_WAT: &str = r#"
+        (module
+          (import "env" "time_now_ms" (func $now (result i64)))
+          (import "env" "time_iso8601" (func $iso (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (local $n i32)
+            (i64.store (i32.const 4096) (call $now))
+            (i64.store (i32.const 4104) (call $now))
+            (local.set $n (call $iso (i32.const 4112) (i32.const 64)))
+            (i32.or
+              (i32.shl (i32.add (i32.const 16) (local.get $n)) (i32.const 16))
+              (i32.const 4096))))
+    "#;
+
+    #[tokio::test]
+    async fn test_time_is_fixed_per_run_and_receipted() {
+        let capsule = wat::parse_str(TIME_WAT).unwrap();
+        let limits = limits_with(&[Capability::Time]);
+        let options = ExecutionOptions::default().with_logical_time_ms(1_700_000_000_123);
+
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let first = runtime
+            .execute_with_options(&capsule, b"", limits.clone(), options.clone())
+            .await
+            .unwrap();
+        let second = runtime
+            .execute_with_options(&capsule, b"", limits, options)
+            .await
+            .unwrap();
+
+        let out = &first.output;
+        assert_eq!(
+            u64::from_le_bytes(out[0..8].try_into().unwrap()),
+            1_700_000_000_123
+        );
+        assert_eq!(out[0..8], out[8..16]);
+        assert_eq!(&out[16..], b"2023-11-14T22:13:20.123Z");
+
+        assert_eq!(first.output, second.output);
+        assert_eq!(first.receipt.replay.logical_time_ms, 1_700_000_000_123);
+        assert!(first.receipt.verify_node_signature().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_time_defaults_to_wall_clock() {
+        let capsule = wat::parse_str(TIME_WAT).unwrap();
+        let limits = limits_with(&[Capability::Time]);
+
+        let before = chrono::Utc::now().timestamp_millis() as u64;
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let result = runtime.execute(&capsule, b"", limits).await.unwrap();
+        let after = chrono::Utc::now().timestamp_millis() as u64;
+
+        let served = u64::from_le_bytes(result.output[0..8].try_into().unwrap());
+        assert!(before <= served && served <= after);
+        assert_eq!(result.receipt.replay.logical_time_ms, served);
+    }
+
+    /// Capsule that draws 16 random bytes followed by one `random_u32`
+    const RANDOM_WAT: &str = r#"
+        (module
+          (import "env" "random_bytes" (func $bytes (param i32 i32) (result i32)))
+          (import "env" "random_u32" (func $u32 (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (drop (call $bytes (i32.const 4096) (i32.const 16)))
+            (i32.store (i32.const 4112) (call $u32))
+            (i32.store (i32.const 4116) (call $bytes (i32.const 65530) (i32.const 16)))
+            (i32.or (i32.shl (i32.const 24) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    #[tokio::test]
+    async fn test_random_is_replayable_from_seed() {
+        let capsule = wat::parse_str(RANDOM_WAT).unwrap();
+        let options = ExecutionOptions::default().with_random_seed([42; 32]);
+
+        let first = WasmRuntime::new(generate_test_signing_key())
+            .unwrap()
+            .execute_with_options(
+                &capsule,
+                b"",
+                limits_with(&[Capability::Random]),
+                options.clone(),
+            )
+            .await
+            .unwrap();
+        let second = WasmRuntime::new(generate_test_signing_key())
+            .unwrap()
+            .execute_with_options(&capsule, b"", limits_with(&[Capability::Random]), options)
+            .await
+            .unwrap();
+
+        let mut expected = [0u8; 20];
+        blake3::Hasher::new_keyed(&[42; 32])
+            .finalize_xof()
+            .fill(&mut expected);
+
+        assert_eq!(first.output[..20], expected);
+        assert_eq!(first.output, second.output);
+        assert_eq!(
+            i32::from_le_bytes(first.output[20..24].try_into().unwrap()),
+            HOST_ERR_OUT_OF_BOUNDS
+        );
+        assert_eq!(first.receipt.replay.random_seed_bytes().unwrap(), [42; 32]);
+    }
+
+    #[tokio::test]
+    async fn test_random_default_seed_is_derived_and_receipted() {
+        let capsule = wat::parse_str(RANDOM_WAT).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+
+        let first = runtime
+            .execute(&capsule, b"in", limits_with(&[Capability::Random]))
+            .await
+            .unwrap();
+        let second = runtime
+            .execute(&capsule, b"in", limits_with(&[Capability::Random]))
+            .await
+            .unwrap();
+
+        let receipt = &first.receipt;
+        let derived = ReplayContext::derive_random_seed(
+            &receipt.capsule_id,
+            &receipt.input_commit,
+            receipt.nonce,
+        );
+        assert_eq!(receipt.replay.random_seed_bytes().unwrap(), derived);
+        assert!(receipt.verify_node_signature().unwrap());
+
+        // A new nonce yields a new seed, but replaying the recorded seed
+        // reproduces the original bytes
+        assert_ne!(first.output, second.output);
+        let replayed = runtime
+            .execute_with_options(
+                &capsule,
+                b"in",
+                limits_with(&[Capability::Random]),
+                ExecutionOptions::default().with_random_seed(derived),
+            )
+            .await
+            .unwrap();
+        assert_eq!(first.output, replayed.output);
+    }
+
+    #[tokio::test]
+    async fn test_host_calls_are_counted_per_function() {
+        let capsule = wat::parse_str(HASH_VERIFY_WAT).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let result = runtime
+            .execute(&capsule, b"count me", ResourceLimits::default())
+            .await
+            .unwrap();
+
+        assert_eq!(result.metrics.host_function_calls, 4);
+        assert_eq!(
+            result.metrics.host_call_breakdown,
+            BTreeMap::from([
+                ("hash_commit".to_string(), 1),
+                ("hash_verify".to_string(), 3)
+            ])
+        );
+        assert_eq!(result.receipt.exec_metrics, result.metrics);
+        assert!(result.receipt.verify_node_signature().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_transcript_replay_recomputes_random_bytes() {
+        let capsule = wat::parse_str(RANDOM_WAT).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+
+        let recorded = runtime
+            .execute_with_options(
+                &capsule,
+                b"",
+                limits_with(&[Capability::Random]),
+                ExecutionOptions::default()
+                    .with_random_seed([1; 32])
+                    .with_transcript_recording(),
+            )
+            .await
+            .unwrap();
+        let transcript = recorded.transcript.clone().unwrap();
+        assert_eq!(transcript.len(), 3);
+        assert_eq!(transcript.calls[0].writes[0].data.len(), 16);
+        assert_eq!(
+            recorded.receipt.replay.transcript_commit,
+            transcript.commitment()
+        );
+        assert!(recorded.receipt.verify_node_signature().unwrap());
+
+        // The signed seed draws the recorded bytes again
+        let replayed = runtime
+            .execute_with_options(
+                &capsule,
+                b"",
+                limits_with(&[Capability::Random]),
+                ExecutionOptions::default()
+                    .with_random_seed([1; 32])
+                    .with_replay_transcript(transcript.clone()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(replayed.output, recorded.output);
+        assert_eq!(replayed.transcript, Some(transcript.clone()));
+        assert_eq!(
+            replayed.receipt.replay.transcript_commit,
+            recorded.receipt.replay.transcript_commit
+        );
+
+        // Bytes the seed does not produce are rejected, whether they come
+        // from another seed or from an edited transcript
+        let mut edited = transcript.clone();
+        edited.calls[0].writes[0].data[0] ^= 1;
+        for (seed, transcript) in [([2; 32], transcript), ([1; 32], edited)] {
+            let result = runtime
+                .execute_with_options(
+                    &capsule,
+                    b"",
+                    limits_with(&[Capability::Random]),
+                    ExecutionOptions::default()
+                        .with_random_seed(seed)
+                        .with_replay_transcript(transcript),
+                )
+                .await;
+            match result {
+                Err(ExecutionError::ReplayDiverged { divergence }) => {
+                    assert_eq!(divergence.index, 0);
+                    assert!(divergence.expected.starts_with("random_bytes("));
+                    assert_ne!(divergence.expected, divergence.actual);
+                }
+                other => panic!("expected divergence, got {:?}", other.map(|r| r.output)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_reports_first_divergence() {
+        let capsule = wat::parse_str(HASH_VERIFY_WAT).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+
+        let recorded = runtime
+            .execute_with_options(
+                &capsule,
+                b"abc",
+                ResourceLimits::default(),
+                ExecutionOptions::default().with_transcript_recording(),
+            )
+            .await
+            .unwrap();
+
+        // A longer input changes the length argument of the first call
+        let result = runtime
+            .execute_with_options(
+                &capsule,
+                b"abcd",
+                ResourceLimits::default(),
+                ExecutionOptions::default().with_replay_transcript(recorded.transcript.unwrap()),
+            )
+            .await;
+
+        match result {
+            Err(ExecutionError::ReplayDiverged { divergence }) => {
+                assert_eq!(divergence.index, 0);
+                assert!(divergence
+                    .expected
+                    .starts_with("hash_commit(1024, 3, 4096) reading "));
+                assert!(divergence
+                    .actual
+                    .starts_with("hash_commit(1024, 4, 4096) reading "));
+            }
+            other => panic!("expected divergence, got {:?}", other.map(|r| r.output)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_detects_changed_input_bytes() {
+        let capsule = wat::parse_str(HASH_VERIFY_WAT).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+
+        let recorded = runtime
+            .execute_with_options(
+                &capsule,
+                b"abc",
+                ResourceLimits::default(),
+                ExecutionOptions::default().with_transcript_recording(),
+            )
+            .await
+            .unwrap();
+
+        // Same length, so hash_commit gets the same arguments but reads
+        // different bytes
+        let result = runtime
+            .execute_with_options(
+                &capsule,
+                b"abd",
+                ResourceLimits::default(),
+                ExecutionOptions::default().with_replay_transcript(recorded.transcript.unwrap()),
+            )
+            .await;
+
+        match result {
+            Err(ExecutionError::ReplayDiverged { divergence }) => {
+                assert_eq!(divergence.index, 0);
+                assert_ne!(divergence.expected, divergence.actual);
+                assert!(divergence
+                    .actual
+                    .starts_with("hash_commit(1024, 3, 4096) reading "));
+            }
+            other => panic!("expected divergence, got {:?}", other.map(|r| r.output)),
+        }
+    }
+
+    /// Capsule that logs "hello" at info, then tries an unknown level, a
+    /// message larger than the budget and an out-of-bounds message. Returns
+    /// the four status codes.
+    const LOG_WAT: &str = r#"
+        (module
+          (import "env" "log_write" (func $log (param i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "hello")
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (i32.store (i32.const 4096) (call $log (i32.const 2) (i32.const 0) (i32.const 5)))
+            (i32.store (i32.const 4100) (call $log (i32.const 9) (i32.const 0) (i32.const 5)))
+            (i32.store (i32.const 4104) (call $log (i32.const 0) (i32.const 0) (i32.const 64)))
+            (i32.store (i32.const 4108) (call $log (i32.const 3) (i32.const 65530) (i32.const 16)))
+            (i32.or (i32.shl (i32.const 16) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    #[tokio::test]
+    async fn test_log_lines_are_returned_and_only_committed() {
+        use crate::execution::RuntimeConfig;
+        use crate::guest_log::{log_commitment, LogLevel};
+
+        let config = RuntimeConfig {
+            max_log_bytes: 32,
+            ..Default::default()
+        };
+        let runtime = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let capsule = wat::parse_str(LOG_WAT).unwrap();
+        let limits = limits_with(&[Capability::Log]);
+
+        let result = runtime.execute(&capsule, b"", limits).await.unwrap();
+        let codes: Vec<i32> = result
+            .output
+            .chunks(4)
+            .map(|c| i32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                HOST_OK,
+                HOST_ERR_INVALID_ARGUMENT,
+                HOST_ERR_LOG_BUDGET_EXCEEDED,
+                HOST_ERR_OUT_OF_BOUNDS
+            ]
+        );
+
+        assert_eq!(result.logs.len(), 1);
+        assert_eq!(result.logs[0].level, LogLevel::Info);
+        assert_eq!(result.logs[0].message, "hello");
+        assert_eq!(result.receipt.log_commit, log_commitment(&result.logs));
+        assert_eq!(result.metrics.host_call_breakdown["log_write"], 4);
+        assert!(result.receipt.verify_node_signature().unwrap());
+        assert!(!result.receipt.to_json().unwrap().contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_log_write_requires_capability() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = wat::parse_str(LOG_WAT).unwrap();
+        assert!(runtime
+            .execute(&capsule, b"", ResourceLimits::default())
+            .await
+            .is_err());
+    }
+}
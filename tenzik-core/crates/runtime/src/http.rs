@@ -0,0 +1,642 @@
+//! Outbound HTTP
+//!
+//! This module performs the requests a capsule makes with the `http_request`
+//! host function. The capsule passes a JSON request and gets a JSON response
+//! back; the host checks the method and host against the run's
+//! [`HttpPolicy`] and enforces its size and time limits. Redirects are not
+//! followed, so a response can never lead the host to a host outside the
+//! allowlist.
+//!
+//! Responses come from outside the run, so every exchange is recorded in the
+//! host call transcript (see `transcript.rs`) and a verifier replays them
+//! from there instead of repeating the requests.
+//!
+//! A request can send a granted node secret as a header value through
+//! `secret_headers`, which maps header names to secret identifiers. Each
+//! secret may only go to the hosts and header names its
+//! `ResourceLimits::secret_headers` rules name, since any host that receives
+//! it could echo it back into the response. Only the identifiers appear in
+//! the request and its transcript record.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use reqwest::header::{self, HeaderName, HeaderValue};
+use reqwest::{redirect, Client, Method, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox::{HttpPolicy, SecretHeaderRule};
+use crate::secrets::GrantedSecrets;
+
+/// Headers a capsule may not set: the target host and body framing come
+/// from the request itself, and hop-by-hop headers belong to the connection
+const RESERVED_HEADERS: [HeaderName; 10] = [
+    header::HOST,
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+    header::CONNECTION,
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+    header::PROXY_AUTHORIZATION,
+    header::PROXY_AUTHENTICATE,
+    HeaderName::from_static("keep-alive"),
+];
+
+/// Request a capsule passes to `http_request`
+#[derive(Debug, Deserialize)]
+struct GuestRequest {
+    /// HTTP method, case-insensitive
+    method: String,
+    /// Absolute `http` or `https` URL
+    url: String,
+    /// Request headers
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Headers whose value is the node secret with the given identifier
+    #[serde(default)]
+    secret_headers: BTreeMap<String, String>,
+    /// UTF-8 request body
+    #[serde(default)]
+    body: String,
+}
+
+/// Response `http_request` writes back to the capsule
+#[derive(Debug, Serialize)]
+struct GuestResponse {
+    /// HTTP status code
+    status: u16,
+    /// Response headers with lower-case names; repeated headers are joined
+    /// with `", "`
+    headers: BTreeMap<String, String>,
+    /// Response body, with invalid UTF-8 replaced
+    body: String,
+}
+
+/// Why an HTTP request was not answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HttpError {
+    /// The request is not valid JSON of the expected shape
+    InvalidJson,
+    /// The method, URL or a header is malformed, or a header is reserved
+    InvalidRequest,
+    /// The policy does not allow the method for the host, or a secret
+    /// header for the host and header name
+    Denied,
+    /// The request (body and headers) or response body exceeds the policy
+    /// limit
+    TooLarge,
+    /// The exchange did not finish within the policy timeout
+    Timeout,
+    /// A secret header names a secret the run was not granted
+    SecretUnavailable,
+    /// The connection failed or the response was malformed
+    Failed,
+}
+
+/// Client used for the requests of one run, bound to its policy
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    client: Client,
+    policy: HttpPolicy,
+    secret_headers: Vec<SecretHeaderRule>,
+}
+
+/// Build the client shared by every run of a runtime
+pub(crate) fn build_client() -> reqwest::Result<Client> {
+    Client::builder().redirect(redirect::Policy::none()).build()
+}
+
+impl HttpClient {
+    /// Send requests through `client` under `policy`
+    pub(crate) fn new(client: Client, policy: HttpPolicy) -> Self {
+        Self {
+            client,
+            policy,
+            secret_headers: Vec::new(),
+        }
+    }
+
+    /// Allow secrets to be sent as headers as `rules` say
+    pub(crate) fn with_secret_headers(mut self, rules: Vec<SecretHeaderRule>) -> Self {
+        self.secret_headers = rules;
+        self
+    }
+
+    /// Perform the JSON request and return the JSON response
+    pub(crate) async fn send(
+        &self,
+        request: &[u8],
+        secrets: &mut GrantedSecrets,
+    ) -> Result<Vec<u8>, HttpError> {
+        let request: GuestRequest =
+            serde_json::from_slice(request).map_err(|_| HttpError::InvalidJson)?;
+        let method = Method::from_bytes(request.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| HttpError::InvalidRequest)?;
+        let url = Url::parse(&request.url).map_err(|_| HttpError::InvalidRequest)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(HttpError::InvalidRequest);
+        }
+        let host = url.host_str().ok_or(HttpError::InvalidRequest)?;
+        let port = url
+            .port_or_known_default()
+            .ok_or(HttpError::InvalidRequest)?;
+        if !self.policy.allows(method.as_str(), host, port) {
+            return Err(HttpError::Denied);
+        }
+        // Headers count toward the request limit along with the body
+        let mut request_bytes = request.body.len();
+        let mut headers = Vec::new();
+        for (name, value) in &request.headers {
+            let value = HeaderValue::from_str(value).map_err(|_| HttpError::InvalidRequest)?;
+            request_bytes += name.len() + value.len();
+            headers.push((guest_header_name(name)?, value));
+        }
+        for (name, id) in &request.secret_headers {
+            let allowed = self
+                .secret_headers
+                .iter()
+                .any(|rule| rule.allows(id, name, host, port));
+            if !allowed {
+                return Err(HttpError::Denied);
+            }
+            let secret = secrets.use_secret(id).ok_or(HttpError::SecretUnavailable)?;
+            let mut value =
+                HeaderValue::from_bytes(secret).map_err(|_| HttpError::InvalidRequest)?;
+            value.set_sensitive(true);
+            request_bytes += name.len() + value.len();
+            headers.push((guest_header_name(name)?, value));
+        }
+        if request_bytes > self.policy.max_request_bytes {
+            return Err(HttpError::TooLarge);
+        }
+
+        let mut builder = self
+            .client
+            .request(method, url)
+            .timeout(Duration::from_millis(self.policy.timeout_ms))
+            .body(request.body);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+
+        let mut response = builder.send().await.map_err(classify)?;
+        let max_body = self.policy.max_response_bytes;
+        if response
+            .content_length()
+            .is_some_and(|len| len > max_body as u64)
+        {
+            return Err(HttpError::TooLarge);
+        }
+
+        let mut headers: BTreeMap<String, String> = BTreeMap::new();
+        for (name, value) in response.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(classify)? {
+            if body.len() + chunk.len() > max_body {
+                return Err(HttpError::TooLarge);
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let response = GuestResponse {
+            status: response.status().as_u16(),
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        };
+        serde_json::to_vec(&response).map_err(|_| HttpError::Failed)
+    }
+}
+
+/// Parse a header name set by the guest, rejecting the headers the client
+/// derives from the request itself and hop-by-hop headers
+fn guest_header_name(name: &str) -> Result<HeaderName, HttpError> {
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| HttpError::InvalidRequest)?;
+    if RESERVED_HEADERS.contains(&name) {
+        return Err(HttpError::InvalidRequest);
+    }
+    Ok(name)
+}
+
+/// Map a client error to the status reported to the guest
+fn classify(error: reqwest::Error) -> HttpError {
+    if error.is_timeout() {
+        HttpError::Timeout
+    } else if error.is_builder() {
+        HttpError::InvalidRequest
+    } else {
+        HttpError::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::{ExecutionError, ExecutionOptions, WasmRuntime};
+    use crate::host::test_support::{limits_with, return_status_and_output, split_status};
+    use crate::host::{
+        HOST_ERR_HTTP_DENIED, HOST_ERR_HTTP_FAILED, HOST_ERR_HTTP_TIMEOUT, HOST_ERR_HTTP_TOO_LARGE,
+        HOST_ERR_INVALID_ARGUMENT, HOST_ERR_INVALID_JSON, HOST_ERR_SECRET_UNAVAILABLE,
+    };
+    use crate::receipts::generate_test_signing_key;
+    use crate::sandbox::{Capability, ResourceLimits};
+    use crate::secrets::MemorySecretStore;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    fn client() -> HttpClient {
+        let policy = HttpPolicy {
+            max_request_bytes: 64,
+            ..HttpPolicy::default().allow("127.0.0.1", &["GET", "POST"])
+        };
+        HttpClient::new(build_client().unwrap(), policy)
+    }
+
+    async fn send(request: serde_json::Value) -> Result<Vec<u8>, HttpError> {
+        let request = serde_json::to_vec(&request).unwrap();
+        client()
+            .send(&request, &mut GrantedSecrets::default())
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_reserved_headers_are_rejected() {
+        for name in [
+            "Host",
+            "content-length",
+            "Transfer-Encoding",
+            "connection",
+            "Keep-Alive",
+        ] {
+            let request = serde_json::json!({
+                "method": "get",
+                "url": "http://127.0.0.1:1/",
+                "headers": {name: "x"},
+            });
+            assert_eq!(
+                send(request).await,
+                Err(HttpError::InvalidRequest),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_headers_count_toward_request_limit() {
+        let request = serde_json::json!({
+            "method": "post",
+            "url": "http://127.0.0.1:1/",
+            "headers": {"x-padding": "a".repeat(48)},
+            "body": "0123456789",
+        });
+        assert_eq!(send(request).await, Err(HttpError::TooLarge));
+    }
+
+    /// Capsule that sends its input as the `http_request` request and returns
+    /// the status code followed by the response
+    fn http_capsule() -> Vec<u8> {
+        wat::parse_str(format!(
+            r#"
+            (module
+              (import "env" "http_request" (func $http (param i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                (local $n i32)
+                (local.set $n
+                  (call $http (local.get $ptr) (local.get $len) (i32.const 8192) (i32.const 4096)))
+                {}))
+            "#,
+            return_status_and_output(8192)
+        ))
+        .unwrap()
+    }
+
+    /// Serve on a local port: `/big` returns a 4 KiB body, `/slow` answers
+    /// after two seconds, `/redirect` redirects to another host, `/auth`
+    /// answers 200 only with the `authorization` header `Bearer t0k3n` and
+    /// every other path echoes the method and request body
+    async fn spawn_mock_server() -> std::net::SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    let header_end = loop {
+                        let n = socket.read(&mut chunk).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break pos + 4;
+                        }
+                    };
+                    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+                    let content_length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    while buf.len() < header_end + content_length {
+                        let n = socket.read(&mut chunk).await.unwrap();
+                        buf.extend_from_slice(&chunk[..n]);
+                    }
+                    let request_body = String::from_utf8_lossy(&buf[header_end..]);
+                    let mut request_line = head.split_whitespace();
+                    let method = request_line.next().unwrap();
+                    let path = request_line.next().unwrap();
+
+                    let (status, extra_headers, body) = match path {
+                        "/big" => ("200 OK", "", "x".repeat(4096)),
+                        "/slow" => {
+                            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                            ("200 OK", "", String::new())
+                        }
+                        "/redirect" => (
+                            "302 Found",
+                            "location: http://example.com/\r\n",
+                            String::new(),
+                        ),
+                        "/auth" => {
+                            let authorization = head.lines().find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("authorization")
+                                    .then(|| value.trim().to_string())
+                            });
+                            match authorization.as_deref() {
+                                Some("Bearer t0k3n") => ("200 OK", "", "authorized".to_string()),
+                                _ => ("401 Unauthorized", "", String::new()),
+                            }
+                        }
+                        _ => ("200 OK", "", format!("{} {}", method, request_body)),
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n{}\r\n{}",
+                        status,
+                        body.len(),
+                        extra_headers,
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        addr
+    }
+
+    fn http_limits(policy: HttpPolicy) -> ResourceLimits {
+        let mut limits = limits_with(&[Capability::Http]);
+        limits.http = policy;
+        limits
+    }
+
+    /// Send `request` through `HTTP_WAT`, returning the status code and the
+    /// parsed response (`Null` on error)
+    async fn run_http(
+        runtime: &WasmRuntime,
+        limits: ResourceLimits,
+        request: &str,
+    ) -> (i32, Value) {
+        let output = runtime
+            .execute(&http_capsule(), request.as_bytes(), limits)
+            .await
+            .unwrap()
+            .output;
+        let (status, response) = split_status(&output);
+        (
+            status,
+            serde_json::from_slice(response).unwrap_or(Value::Null),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_http_exchanges_are_recorded_and_replayed() {
+        let addr = spawn_mock_server().await;
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = http_capsule();
+        let policy = HttpPolicy::default().allow("127.0.0.1", &["POST"]);
+        let request = format!(
+            r#"{{"method":"post","url":"http://{}/echo","body":"ping"}}"#,
+            addr
+        );
+
+        let recorded = runtime
+            .execute(&capsule, request.as_bytes(), http_limits(policy))
+            .await
+            .unwrap();
+        let response: Value = serde_json::from_slice(&recorded.output[4..]).unwrap();
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"], "POST ping");
+        assert_eq!(response["headers"]["content-length"], "9");
+
+        // The exchange is recorded and committed without being requested
+        let transcript = recorded.transcript.clone().unwrap();
+        assert_eq!(transcript.calls[0].request, request.as_bytes());
+        assert_eq!(
+            recorded.receipt.replay.transcript_commit,
+            transcript.commitment()
+        );
+        assert!(recorded
+            .receipt
+            .replay
+            .resource_limits_string()
+            .contains("http_allow=127.0.0.1=POST"));
+        assert!(recorded.receipt.verify_node_signature().unwrap());
+
+        // Replay answers from the transcript, even with every host denied
+        let replayed = runtime
+            .execute_with_options(
+                &capsule,
+                request.as_bytes(),
+                http_limits(HttpPolicy::default()),
+                ExecutionOptions::default().with_replay_transcript(transcript.clone()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(replayed.output, recorded.output);
+
+        // A different request with the same arguments diverges
+        let forged = request.replace("ping", "pong");
+        let result = runtime
+            .execute_with_options(
+                &capsule,
+                forged.as_bytes(),
+                http_limits(HttpPolicy::default()),
+                ExecutionOptions::default().with_replay_transcript(transcript),
+            )
+            .await;
+        assert!(matches!(result, Err(ExecutionError::ReplayDiverged { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_http_policy_is_enforced() {
+        let addr = spawn_mock_server().await;
+        let closed_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let limits = http_limits(
+            HttpPolicy {
+                max_request_bytes: 8,
+                max_response_bytes: 1024,
+                timeout_ms: 100,
+                ..Default::default()
+            }
+            .allow("127.0.0.1", &["GET", "POST"]),
+        );
+        let request = |method: &str, host: String, path: &str, body: &str| {
+            format!(
+                r#"{{"method":"{}","url":"http://{}{}","body":"{}"}}"#,
+                method, host, path, body
+            )
+        };
+        let local = addr.to_string();
+
+        let cases = [
+            (
+                request("DELETE", local.clone(), "/echo", ""),
+                HOST_ERR_HTTP_DENIED,
+            ),
+            (
+                request("GET", format!("localhost:{}", addr.port()), "/echo", ""),
+                HOST_ERR_HTTP_DENIED,
+            ),
+            (
+                request("POST", local.clone(), "/echo", "far too long"),
+                HOST_ERR_HTTP_TOO_LARGE,
+            ),
+            (
+                request("GET", local.clone(), "/big", ""),
+                HOST_ERR_HTTP_TOO_LARGE,
+            ),
+            (
+                request("GET", local.clone(), "/slow", ""),
+                HOST_ERR_HTTP_TIMEOUT,
+            ),
+            (
+                request("GET", format!("127.0.0.1:{}", closed_port), "/", ""),
+                HOST_ERR_HTTP_FAILED,
+            ),
+            ("not json".to_string(), HOST_ERR_INVALID_JSON),
+            (
+                r#"{"method":"GET","url":"ftp://127.0.0.1/"}"#.to_string(),
+                HOST_ERR_INVALID_ARGUMENT,
+            ),
+        ];
+        for (request, expected) in cases {
+            let (status, _) = run_http(&runtime, limits.clone(), &request).await;
+            assert_eq!(status, expected, "{}", request);
+        }
+
+        // Redirects are handed to the capsule instead of being followed
+        let (_, response) =
+            run_http(&runtime, limits, &request("GET", local, "/redirect", "")).await;
+        assert_eq!(response["status"], 302);
+        assert_eq!(response["headers"]["location"], "http://example.com/");
+    }
+
+    #[tokio::test]
+    async fn test_http_request_is_bounded_by_execution_deadline() {
+        let addr = spawn_mock_server().await;
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let capsule = http_capsule();
+        let mut limits = http_limits(
+            HttpPolicy {
+                timeout_ms: 5_000,
+                ..Default::default()
+            }
+            .allow("127.0.0.1", &["GET"]),
+        );
+        limits.execution_time_ms = 100;
+        let request = format!(r#"{{"method":"GET","url":"http://{}/slow"}}"#, addr);
+
+        // The run stops at its own deadline, not the request's, and still
+        // reports the fuel spent before the call
+        match runtime.execute(&capsule, request.as_bytes(), limits).await {
+            Err(ExecutionError::Timeout {
+                timeout_ms,
+                fuel_used,
+            }) => {
+                assert_eq!(timeout_ms, 100);
+                assert!(fuel_used > 0);
+            }
+            other => panic!("expected timeout, got {:?}", other.map(|r| r.output)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_secret_headers() {
+        let addr = spawn_mock_server().await;
+        let store = MemorySecretStore::new();
+        store.insert("api-token", "Bearer t0k3n");
+        store.insert("webhook", "not granted");
+        let runtime = WasmRuntime::new(generate_test_signing_key())
+            .unwrap()
+            .with_secret_store(Arc::new(store));
+        let mut limits = http_limits(
+            HttpPolicy::default()
+                .allow("127.0.0.1", &["GET"])
+                .allow("localhost", &["GET"]),
+        );
+        limits.add_capability(Capability::Secrets);
+        limits.secrets = vec!["api-token".to_string()];
+        limits.allow_secret_header("api-token", "127.0.0.1", &["Authorization"]);
+        limits.allow_secret_header("webhook", "127.0.0.1", &["Authorization"]);
+        let request = serde_json::json!({
+            "method": "GET",
+            "url": format!("http://{}/auth", addr),
+            "secret_headers": {"authorization": "api-token"},
+        })
+        .to_string();
+
+        // The server sees the secret; the capsule and transcript never do
+        let capsule = http_capsule();
+        let result = runtime
+            .execute(&capsule, request.as_bytes(), limits.clone())
+            .await
+            .unwrap();
+        let response: Value = serde_json::from_slice(&result.output[4..]).unwrap();
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"], "authorized");
+        assert_eq!(result.receipt.secrets_used[0].id, "api-token");
+        let recorded = &result.transcript.unwrap().calls[0].request;
+        assert!(!String::from_utf8_lossy(recorded).contains("t0k3n"));
+
+        // A secret only goes to its own hosts and header names
+        let other_header = request.replace(r#""authorization":"#, r#""x-echo":"#);
+        let other_host = request.replace("127.0.0.1", "localhost");
+        for request in [other_header, other_host] {
+            let (status, _) = run_http(&runtime, limits.clone(), &request).await;
+            assert_eq!(status, HOST_ERR_HTTP_DENIED);
+        }
+
+        // A header rule does not grant the secret itself
+        let (status, _) = run_http(
+            &runtime,
+            limits,
+            &request.replace(r#":"api-token""#, r#":"webhook""#),
+        )
+        .await;
+        assert_eq!(status, HOST_ERR_SECRET_UNAVAILABLE);
+    }
+}
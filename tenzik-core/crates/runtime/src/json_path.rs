@@ -0,0 +1,271 @@
+//! JSONPath Module
+//!
+//! This module implements the JSONPath subset evaluated by the `json_path` and
+//! `json_extract` host functions. Capsules are too small to carry a JSON
+//! parser, so path evaluation happens on the host.
+//!
+//! Supported syntax:
+//!
+//! | Syntax           | Meaning                                   |
+//! |------------------|-------------------------------------------|
+//! | `$`              | Root of the document (optional prefix)    |
+//! | `.key`           | Object member by name                     |
+//! | `['key']`        | Object member by name (quoted, any chars) |
+//! | `[n]`            | Array element by index, negative from end |
+//! | `.*` / `[*]`     | Every member of an object or array        |
+//!
+//! Examples: `$.user.name`, `items[0].id`, `$['content-type']`, `items[*].sku`.
+//! Filters, slices, unions and recursive descent are not supported.
+
+use serde_json::Value;
+use thiserror::Error;
+
+/// JSONPath parse errors
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum JsonPathError {
+    #[error("Empty path segment at offset {offset}")]
+    EmptySegment { offset: usize },
+
+    #[error("Unexpected character '{found}' at offset {offset}")]
+    UnexpectedChar { found: char, offset: usize },
+
+    #[error("Unterminated bracket starting at offset {offset}")]
+    UnterminatedBracket { offset: usize },
+
+    #[error("Invalid array index '{index}'")]
+    InvalidIndex { index: String },
+}
+
+/// A single step in a JSONPath expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Object member lookup
+    Key(String),
+    /// Array element lookup (negative counts from the end)
+    Index(i64),
+    /// Every member of an object or array
+    Wildcard,
+}
+
+/// A parsed JSONPath expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    segments: Vec<PathSegment>,
+}
+
+impl JsonPath {
+    /// Parse a JSONPath expression
+    pub fn parse(path: &str) -> Result<Self, JsonPathError> {
+        let chars: Vec<char> = path.chars().collect();
+        let mut segments = Vec::new();
+        let mut pos = 0;
+
+        if chars.first() == Some(&'$') {
+            pos = 1;
+        }
+
+        while pos < chars.len() {
+            match chars[pos] {
+                '.' => {
+                    pos += 1;
+                    let start = pos;
+                    while pos < chars.len() && chars[pos] != '.' && chars[pos] != '[' {
+                        pos += 1;
+                    }
+                    let key: String = chars[start..pos].iter().collect();
+                    if key.is_empty() {
+                        return Err(JsonPathError::EmptySegment { offset: start });
+                    }
+                    segments.push(if key == "*" {
+                        PathSegment::Wildcard
+                    } else {
+                        PathSegment::Key(key)
+                    });
+                }
+                '[' => {
+                    let (segment, next) = Self::parse_bracket(&chars, pos)?;
+                    segments.push(segment);
+                    pos = next;
+                }
+                // A bare leading key, e.g. `user.name`
+                c if pos == 0 && c != ']' => {
+                    let start = pos;
+                    while pos < chars.len() && chars[pos] != '.' && chars[pos] != '[' {
+                        pos += 1;
+                    }
+                    let key: String = chars[start..pos].iter().collect();
+                    segments.push(if key == "*" {
+                        PathSegment::Wildcard
+                    } else {
+                        PathSegment::Key(key)
+                    });
+                }
+                c => {
+                    return Err(JsonPathError::UnexpectedChar {
+                        found: c,
+                        offset: pos,
+                    })
+                }
+            }
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Parse a bracketed segment starting at `start` (which holds `[`)
+    fn parse_bracket(chars: &[char], start: usize) -> Result<(PathSegment, usize), JsonPathError> {
+        let mut pos = start + 1;
+
+        match chars.get(pos) {
+            Some(&quote) if quote == '\'' || quote == '"' => {
+                pos += 1;
+                let key_start = pos;
+                while pos < chars.len() && chars[pos] != quote {
+                    pos += 1;
+                }
+                if pos + 1 >= chars.len() || chars[pos + 1] != ']' {
+                    return Err(JsonPathError::UnterminatedBracket { offset: start });
+                }
+                let key: String = chars[key_start..pos].iter().collect();
+                Ok((PathSegment::Key(key), pos + 2))
+            }
+            _ => {
+                let inner_start = pos;
+                while pos < chars.len() && chars[pos] != ']' {
+                    pos += 1;
+                }
+                if pos >= chars.len() {
+                    return Err(JsonPathError::UnterminatedBracket { offset: start });
+                }
+                let inner: String = chars[inner_start..pos].iter().collect();
+                let inner = inner.trim();
+                if inner.is_empty() {
+                    return Err(JsonPathError::EmptySegment {
+                        offset: inner_start,
+                    });
+                }
+                if inner == "*" {
+                    return Ok((PathSegment::Wildcard, pos + 1));
+                }
+                let index = inner
+                    .parse::<i64>()
+                    .map_err(|_| JsonPathError::InvalidIndex {
+                        index: inner.to_string(),
+                    })?;
+                Ok((PathSegment::Index(index), pos + 1))
+            }
+        }
+    }
+
+    /// Get the parsed segments
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Whether the path can match more than one value
+    pub fn has_wildcard(&self) -> bool {
+        self.segments.contains(&PathSegment::Wildcard)
+    }
+
+    /// Evaluate the path against a document, returning every matching value.
+    ///
+    /// Array wildcards preserve element order; object wildcards follow key order.
+    pub fn evaluate<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![root];
+
+        for segment in &self.segments {
+            let mut next = Vec::new();
+            for value in current {
+                match (segment, value) {
+                    (PathSegment::Key(key), Value::Object(map)) => {
+                        if let Some(child) = map.get(key) {
+                            next.push(child);
+                        }
+                    }
+                    (PathSegment::Index(index), Value::Array(items)) => {
+                        let resolved = if *index < 0 {
+                            items.len() as i64 + index
+                        } else {
+                            *index
+                        };
+                        if resolved >= 0 {
+                            if let Some(child) = items.get(resolved as usize) {
+                                next.push(child);
+                            }
+                        }
+                    }
+                    (PathSegment::Wildcard, Value::Object(map)) => next.extend(map.values()),
+                    (PathSegment::Wildcard, Value::Array(items)) => next.extend(items.iter()),
+                    _ => {}
+                }
+            }
+            current = next;
+        }
+
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_segments() {
+        let path = JsonPath::parse("$.items[0]['content-type'].*[-1]").unwrap();
+        assert_eq!(
+            path.segments(),
+            &[
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(0),
+                PathSegment::Key("content-type".to_string()),
+                PathSegment::Wildcard,
+                PathSegment::Index(-1),
+            ]
+        );
+        assert!(path.has_wildcard());
+
+        let bare = JsonPath::parse("user.name").unwrap();
+        assert_eq!(bare.segments().len(), 2);
+        assert!(JsonPath::parse("$").unwrap().segments().is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            JsonPath::parse("$..a"),
+            Err(JsonPathError::EmptySegment { .. })
+        ));
+        assert!(matches!(
+            JsonPath::parse("$.a[1"),
+            Err(JsonPathError::UnterminatedBracket { .. })
+        ));
+        assert!(matches!(
+            JsonPath::parse("$.a[x]"),
+            Err(JsonPathError::InvalidIndex { .. })
+        ));
+        assert!(matches!(
+            JsonPath::parse("$]"),
+            Err(JsonPathError::UnexpectedChar { .. })
+        ));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let doc = json!({
+            "user": { "name": "ada", "tags": ["a", "b", "c"] },
+            "items": [{ "sku": "x1" }, { "sku": "x2" }, { "id": 3 }]
+        });
+
+        let eval = |p: &str| JsonPath::parse(p).unwrap().evaluate(&doc);
+
+        assert_eq!(eval("$.user.name"), vec![&json!("ada")]);
+        assert_eq!(eval("$.user.tags[-1]"), vec![&json!("c")]);
+        assert_eq!(eval("$.items[*].sku"), vec![&json!("x1"), &json!("x2")]);
+        assert_eq!(eval("$").len(), 1);
+        assert!(eval("$.user.missing").is_empty());
+        assert!(eval("$.user.tags[10]").is_empty());
+        assert!(eval("$.user.name[0]").is_empty());
+    }
+}
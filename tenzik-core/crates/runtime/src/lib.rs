@@ -4,15 +4,57 @@
 //! capsules (3-5KB WASM modules) with strict resource limits and capability controls.
 
 pub mod validation;
+pub mod bytecode;
 pub mod sandbox;
 pub mod execution;
+pub mod executor;
+pub mod pipeline;
+pub mod host;
+mod http;
+pub mod abi;
+pub mod cache;
+mod epoch;
+pub mod features;
+mod limiter;
+pub mod transcript;
+pub mod guest_log;
+pub mod state;
+pub mod secrets;
+pub mod signing;
+pub mod merkle;
+pub mod chunked;
+pub mod json_path;
+pub mod metrics;
 pub mod receipts;
 
 // Re-export key types for easy access
 pub use validation::{WasmValidator, ValidationResult, ValidationError, ValidatorConfig};
-pub use sandbox::{Capability, ResourceLimits, SecuritySandbox, SandboxError};
-pub use execution::{WasmRuntime, ExecutionResult, ExecutionError, RuntimeConfig};
-pub use receipts::{ExecutionReceipt, ExecMetrics, ReceiptError, ReceiptVerifier};
+pub use sandbox::{
+    Capability, HttpAllowRule, HttpPolicy, ResourceLimits, SecretHeaderRule, SecuritySandbox,
+    SandboxError,
+};
+pub use abi::CapsuleAbi;
+pub use bytecode::BytecodeLimits;
+pub use features::WasmFeaturePolicy;
+pub use cache::{CacheStats, ModuleCache};
+pub use execution::{
+    BatchItem, BatchResult, ExecutionError, ExecutionMetrics, ExecutionOptions, ExecutionResult,
+    RuntimeConfig, WasmRuntime,
+};
+pub use executor::{CapsuleExecutor, ExecutionJob, ExecutorStats};
+pub use pipeline::{Pipeline, PipelineResult, PipelineStep};
+pub use json_path::{JsonPath, JsonPathError};
+pub use guest_log::{LogLevel, LogLine};
+pub use state::{FileStateStore, MemoryStateStore, StateError, StateStore};
+pub use secrets::{MemorySecretStore, SecretRef, SecretStore, SecretValue};
+pub use signing::capsule_verifying_key;
+pub use merkle::MerkleProof;
+pub use metrics::{CapsuleMetrics, Histogram, MetricsSnapshot};
+pub use transcript::{GuestWrite, HostCallRecord, ReplayDivergence, Transcript};
+pub use receipts::{
+    BatchItemProof, BatchReceipt, ExecutionReceipt, ExecutionReceiptBuilder, ExecMetrics,
+    PipelineReceipt, ReceiptError, ReceiptVerifier, ReexecutionVerdict, ReplayContext,
+};
 
 // Re-export crypto types for convenience
 pub use ed25519_dalek::{SigningKey, VerifyingKey};
@@ -30,8 +72,6 @@ pub fn generate_test_signing_key() -> SigningKey {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
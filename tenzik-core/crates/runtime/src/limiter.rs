@@ -0,0 +1,169 @@
+//! Resource Limiter Module
+//!
+//! This module enforces a capsule's memory and table limits from inside the
+//! wasmtime `Store` and records the high-water mark of each, so execution
+//! metrics report peak usage rather than the size left at the end of a run.
+//!
+//! Growth past a module's own declared maximum is still handled by wasmtime
+//! (`memory.grow` returns `-1`). Growth past the sandbox limits traps instead,
+//! and the limit that was hit is kept on the limiter so the runtime can report
+//! it as `ExecutionError::ResourceLimitExceeded`.
+
+use anyhow::{anyhow, Result};
+use wasmtime::ResourceLimiter;
+
+/// Maximum number of table elements per store
+pub(crate) const MAX_TABLE_ELEMENTS: usize = 1000;
+/// Maximum number of instances per store
+const MAX_INSTANCES: usize = 10;
+/// Maximum number of tables per store
+const MAX_TABLES: usize = 1000;
+/// Maximum number of linear memories per store
+const MAX_MEMORIES: usize = 1000;
+
+/// Store limiter that tracks peak memory and table usage
+#[derive(Debug)]
+pub(crate) struct ExecutionLimiter {
+    /// Maximum total linear memory in bytes
+    memory_limit_bytes: usize,
+    /// Maximum total table elements
+    table_limit_elements: usize,
+    /// Current linear memory across all memories in bytes
+    memory_bytes: usize,
+    /// Current table elements across all tables
+    table_elements: usize,
+    /// Highest value `memory_bytes` has reached
+    peak_memory_bytes: usize,
+    /// Highest value `table_elements` has reached
+    peak_table_elements: usize,
+    /// Limit that denied growth, if any
+    exceeded: Option<String>,
+}
+
+impl ExecutionLimiter {
+    /// Create a limiter allowing `memory_limit_mb` of linear memory
+    pub(crate) fn new(memory_limit_mb: u32) -> Self {
+        Self {
+            memory_limit_bytes: memory_limit_mb as usize * 1024 * 1024,
+            table_limit_elements: MAX_TABLE_ELEMENTS,
+            memory_bytes: 0,
+            table_elements: 0,
+            peak_memory_bytes: 0,
+            peak_table_elements: 0,
+            exceeded: None,
+        }
+    }
+
+    /// Peak linear memory in bytes
+    pub(crate) fn peak_memory_bytes(&self) -> usize {
+        self.peak_memory_bytes
+    }
+
+    /// Peak table elements
+    pub(crate) fn peak_table_elements(&self) -> usize {
+        self.peak_table_elements
+    }
+
+    /// Description of the limit that denied growth, if any
+    pub(crate) fn exceeded(&self) -> Option<&str> {
+        self.exceeded.as_deref()
+    }
+
+    /// Record a denied growth request and build the trap returned to wasmtime
+    fn deny(&mut self, limit_type: String) -> anyhow::Error {
+        let error = anyhow!("resource limit exceeded: {}", limit_type);
+        self.exceeded = Some(limit_type);
+        error
+    }
+}
+
+impl ResourceLimiter for ExecutionLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        let total = self.memory_bytes - current + desired;
+        if total > self.memory_limit_bytes {
+            return Err(self.deny(format!(
+                "memory: {} bytes requested (limit: {} bytes)",
+                total, self.memory_limit_bytes
+            )));
+        }
+
+        self.memory_bytes = total;
+        self.peak_memory_bytes = self.peak_memory_bytes.max(total);
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        let total = self.table_elements - current + desired;
+        if total > self.table_limit_elements {
+            return Err(self.deny(format!(
+                "table: {} elements requested (limit: {} elements)",
+                total, self.table_limit_elements
+            )));
+        }
+
+        self.table_elements = total;
+        self.peak_table_elements = self.peak_table_elements.max(total);
+        Ok(true)
+    }
+
+    fn instances(&self) -> usize {
+        MAX_INSTANCES
+    }
+
+    fn tables(&self) -> usize {
+        MAX_TABLES
+    }
+
+    fn memories(&self) -> usize {
+        MAX_MEMORIES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    #[test]
+    fn test_tracks_peak_memory() {
+        let mut limiter = ExecutionLimiter::new(4);
+        assert!(limiter.memory_growing(0, MB, None).unwrap());
+        assert!(limiter.memory_growing(MB, 3 * MB, None).unwrap());
+
+        assert_eq!(limiter.peak_memory_bytes(), 3 * MB);
+        assert!(limiter.exceeded().is_none());
+    }
+
+    #[test]
+    fn test_denies_growth_past_limit() {
+        let mut limiter = ExecutionLimiter::new(2);
+        assert!(limiter.memory_growing(0, MB, None).unwrap());
+        assert!(limiter.memory_growing(MB, 3 * MB, None).is_err());
+
+        assert_eq!(limiter.peak_memory_bytes(), MB);
+        assert!(limiter.exceeded().unwrap().starts_with("memory"));
+    }
+
+    #[test]
+    fn test_denies_table_growth_past_limit() {
+        let mut limiter = ExecutionLimiter::new(1);
+        assert!(limiter.table_growing(0, 10, None).unwrap());
+        assert!(limiter
+            .table_growing(10, MAX_TABLE_ELEMENTS + 1, None)
+            .is_err());
+
+        assert_eq!(limiter.peak_table_elements(), 10);
+        assert!(limiter.exceeded().unwrap().starts_with("table"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Merkle Trees
+//!
+//! This module builds binary Blake3 Merkle trees over leaf hashes and the
+//! inclusion proofs that tie one leaf to a root. Callers (state roots,
+//! chunked I/O, batch receipts) hash their leaves with their own domain
+//! prefix; interior nodes hash their two children and an odd node is
+//! promoted to the next level unchanged.
+//!
+//! The shape of the tree only depends on the number of leaves, so a proof
+//! carries the leaf index and count plus the sibling hashes that are present
+//! on the path to the root.
+
+use serde::{Deserialize, Serialize};
+
+/// Domain separation prefix of interior nodes
+const NODE_DOMAIN: &[u8] = b"TENZIK_MERKLE_NODE_V1";
+
+/// Hash of an interior node
+fn node_hash(left: &blake3::Hash, right: &blake3::Hash) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(NODE_DOMAIN);
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    hasher.finalize()
+}
+
+/// Nodes of the level above `level`
+fn next_level(level: &[blake3::Hash]) -> Vec<blake3::Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks(2) yields one or two nodes"),
+        })
+        .collect()
+}
+
+/// Root of the tree over `leaves`, or `None` if there are none
+pub fn merkle_root(leaves: &[blake3::Hash]) -> Option<blake3::Hash> {
+    MerkleTree::new(leaves.to_vec()).root()
+}
+
+/// Every level of a Merkle tree, for building many proofs from one tree
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Leaves first, root last
+    levels: Vec<Vec<blake3::Hash>>,
+}
+
+impl MerkleTree {
+    /// Build the tree over `leaves`
+    pub fn new(leaves: Vec<blake3::Hash>) -> Self {
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = next_level(level);
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Root of the tree, or `None` if it has no leaves
+    pub fn root(&self) -> Option<blake3::Hash> {
+        self.levels.last()?.first().copied()
+    }
+
+    /// Number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Proof for the leaf at `index`
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut position = index;
+        let mut siblings = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(sibling.to_hex().to_string());
+            }
+            position /= 2;
+        }
+
+        Some(MerkleProof {
+            index: index as u64,
+            leaf_count: self.leaf_count() as u64,
+            siblings,
+        })
+    }
+}
+
+/// Proof that a leaf is part of a Merkle tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Position of the leaf
+    pub index: u64,
+    /// Number of leaves in the tree
+    pub leaf_count: u64,
+    /// Hex sibling hashes from the leaf up to the root, skipping levels
+    /// where the node is promoted without a sibling
+    pub siblings: Vec<String>,
+}
+
+impl MerkleProof {
+    /// Build the proof for the leaf at `index`. Use [`MerkleTree`] to build
+    /// proofs for many leaves of the same tree.
+    pub fn build(leaves: &[blake3::Hash], index: usize) -> Option<Self> {
+        MerkleTree::new(leaves.to_vec()).proof(index)
+    }
+
+    /// Root implied by `leaf` and this proof, or `None` if the proof is
+    /// malformed
+    pub fn root(&self, leaf: blake3::Hash) -> Option<blake3::Hash> {
+        if self.index >= self.leaf_count {
+            return None;
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut node = leaf;
+        let mut position = self.index;
+        let mut width = self.leaf_count;
+        while width > 1 {
+            if position ^ 1 < width {
+                let sibling = blake3::Hash::from_hex(siblings.next()?).ok()?;
+                node = if position & 1 == 0 {
+                    node_hash(&node, &sibling)
+                } else {
+                    node_hash(&sibling, &node)
+                };
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+
+        match siblings.next() {
+            Some(_) => None,
+            None => Some(node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<blake3::Hash> {
+        (0..count as u64)
+            .map(|i| blake3::hash(&i.to_le_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_proofs_for_every_tree_shape() {
+        assert_eq!(merkle_root(&[]), None);
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = merkle_root(&leaves).unwrap();
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::build(&leaves, index).unwrap();
+                assert_eq!(proof.root(*leaf), Some(root), "{} of {}", index, count);
+                assert_ne!(proof.root(blake3::hash(b"other")), Some(root));
+            }
+            assert!(MerkleProof::build(&leaves, count).is_none());
+        }
+    }
+
+    #[test]
+    fn test_malformed_proofs_are_rejected() {
+        let leaves = leaves(5);
+        let root = merkle_root(&leaves).unwrap();
+        let proof = MerkleProof::build(&leaves, 2).unwrap();
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert_ne!(moved.root(leaves[2]), Some(root));
+
+        let mut extra = proof.clone();
+        extra.siblings.push(blake3::hash(b"x").to_hex().to_string());
+        assert_eq!(extra.root(leaves[2]), None);
+
+        let mut out_of_range = proof;
+        out_of_range.index = 5;
+        assert_eq!(out_of_range.root(leaves[2]), None);
+    }
+}
@@ -0,0 +1,379 @@
+//! Runtime Metrics
+//!
+//! This module aggregates the usage of every execution a `WasmRuntime` runs:
+//! runtime-wide totals in [`ExecutionMetrics`], and per capsule the number of
+//! runs, histograms of duration and fuel, and failures by error kind
+//! (`ExecutionError::kind`). [`WasmRuntime::metrics`] returns a
+//! [`MetricsSnapshot`], which renders itself in the Prometheus text
+//! exposition format with [`MetricsSnapshot::to_prometheus`].
+//!
+//! Capsules are labelled by `capsule_id`, so the number of series grows with
+//! the number of distinct capsules the node runs.
+//!
+//! [`WasmRuntime::metrics`]: crate::execution::WasmRuntime::metrics
+
+use crate::execution::{ExecutionError, ExecutionMetrics};
+use crate::receipts::ExecMetrics;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Upper bounds of the duration histogram buckets, in milliseconds
+pub const DURATION_BUCKETS_MS: &[f64] = &[
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+/// Upper bounds of the fuel histogram buckets
+pub const FUEL_BUCKETS: &[f64] = &[1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+/// Histogram with fixed bucket bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Upper bound of every bucket, ascending
+    pub bounds: &'static [f64],
+    /// Observations per bucket, plus a last bucket for values above every
+    /// bound. Counts are not cumulative.
+    pub counts: Vec<u64>,
+    /// Number of observations
+    pub count: u64,
+    /// Sum of all observations
+    pub sum: f64,
+}
+
+impl Histogram {
+    /// Empty histogram over `bounds`
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Record one observation
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|&bound| bound < value);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Cumulative count of every bucket up to and including its bound,
+    /// ending with the `+Inf` bucket
+    pub fn cumulative_counts(&self) -> Vec<u64> {
+        self.counts
+            .iter()
+            .scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            })
+            .collect()
+    }
+}
+
+/// Usage of one capsule
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapsuleMetrics {
+    /// Successful executions
+    pub executions: u64,
+    /// Duration of successful executions in milliseconds
+    pub duration_ms: Histogram,
+    /// Fuel consumed by successful executions
+    pub fuel_used: Histogram,
+    /// Failed executions by `ExecutionError::kind`
+    pub failures: BTreeMap<String, u64>,
+}
+
+impl Default for CapsuleMetrics {
+    fn default() -> Self {
+        Self {
+            executions: 0,
+            duration_ms: Histogram::new(DURATION_BUCKETS_MS),
+            fuel_used: Histogram::new(FUEL_BUCKETS),
+            failures: BTreeMap::new(),
+        }
+    }
+}
+
+/// Point-in-time copy of the metrics of a runtime
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Totals over every execution
+    pub runtime: ExecutionMetrics,
+    /// Usage keyed by `capsule_id`
+    pub capsules: BTreeMap<String, CapsuleMetrics>,
+}
+
+impl MetricsSnapshot {
+    /// Render the snapshot in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let runtime = &self.runtime;
+
+        metric_header(
+            &mut out,
+            "tenzik_executions_total",
+            "counter",
+            "Executions run, successful or not",
+        );
+        let _ = writeln!(out, "tenzik_executions_total {}", runtime.total_executions);
+        metric_header(
+            &mut out,
+            "tenzik_failed_executions_total",
+            "counter",
+            "Executions that returned an error",
+        );
+        let _ = writeln!(
+            out,
+            "tenzik_failed_executions_total {}",
+            runtime.failed_executions
+        );
+        metric_header(
+            &mut out,
+            "tenzik_execution_time_avg_ms",
+            "gauge",
+            "Average duration of successful executions in milliseconds",
+        );
+        let _ = writeln!(
+            out,
+            "tenzik_execution_time_avg_ms {}",
+            runtime.avg_execution_time_ms
+        );
+        metric_header(
+            &mut out,
+            "tenzik_peak_memory_mb",
+            "gauge",
+            "Peak linear memory of any execution in MB",
+        );
+        let _ = writeln!(out, "tenzik_peak_memory_mb {}", runtime.peak_memory_mb);
+        metric_header(
+            &mut out,
+            "tenzik_fuel_used_total",
+            "counter",
+            "Fuel consumed by every execution",
+        );
+        let _ = writeln!(out, "tenzik_fuel_used_total {}", runtime.total_fuel_used);
+
+        metric_header(
+            &mut out,
+            "tenzik_capsule_executions_total",
+            "counter",
+            "Successful executions per capsule",
+        );
+        for (capsule_id, capsule) in &self.capsules {
+            let _ = writeln!(
+                out,
+                "tenzik_capsule_executions_total{{capsule_id=\"{}\"}} {}",
+                escape_label(capsule_id),
+                capsule.executions
+            );
+        }
+        metric_header(
+            &mut out,
+            "tenzik_capsule_failures_total",
+            "counter",
+            "Failed executions per capsule and error kind",
+        );
+        for (capsule_id, capsule) in &self.capsules {
+            for (kind, count) in &capsule.failures {
+                let _ = writeln!(
+                    out,
+                    "tenzik_capsule_failures_total{{capsule_id=\"{}\",kind=\"{}\"}} {}",
+                    escape_label(capsule_id),
+                    escape_label(kind),
+                    count
+                );
+            }
+        }
+        write_histograms(
+            &mut out,
+            "tenzik_capsule_duration_ms",
+            "Duration of successful executions per capsule in milliseconds",
+            self.capsules
+                .iter()
+                .map(|(id, capsule)| (id, &capsule.duration_ms)),
+        );
+        write_histograms(
+            &mut out,
+            "tenzik_capsule_fuel_used",
+            "Fuel consumed by successful executions per capsule",
+            self.capsules
+                .iter()
+                .map(|(id, capsule)| (id, &capsule.fuel_used)),
+        );
+        out
+    }
+}
+
+/// Write the `# HELP` and `# TYPE` lines of a metric
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Write one histogram per capsule under the metric `name`
+fn write_histograms<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    histograms: impl Iterator<Item = (&'a String, &'a Histogram)>,
+) {
+    metric_header(out, name, "histogram", help);
+    for (capsule_id, histogram) in histograms {
+        let capsule_id = escape_label(capsule_id);
+        let cumulative = histogram.cumulative_counts();
+        for (bound, count) in histogram.bounds.iter().zip(&cumulative) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{capsule_id=\"{}\",le=\"{}\"}} {}",
+                name, capsule_id, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{capsule_id=\"{}\",le=\"+Inf\"}} {}",
+            name, capsule_id, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "{}_sum{{capsule_id=\"{}\"}} {}",
+            name, capsule_id, histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{capsule_id=\"{}\"}} {}",
+            name, capsule_id, histogram.count
+        );
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Metrics shared by every execution of a runtime
+#[derive(Debug, Default)]
+pub(crate) struct MetricsRegistry {
+    snapshot: Mutex<MetricsSnapshot>,
+}
+
+impl MetricsRegistry {
+    /// Record a successful execution of `capsule_id`
+    pub(crate) fn record_success(&self, capsule_id: &str, metrics: &ExecMetrics) {
+        let mut snapshot = self.lock();
+        let runtime = &mut snapshot.runtime;
+        let successes = runtime.total_executions - runtime.failed_executions + 1;
+        runtime.avg_execution_time_ms +=
+            (metrics.duration_ms as f64 - runtime.avg_execution_time_ms) / successes as f64;
+        runtime.total_executions += 1;
+        runtime.peak_memory_mb = runtime.peak_memory_mb.max(metrics.memory_mb);
+        runtime.total_fuel_used += metrics.fuel_used;
+
+        let capsule = snapshot.capsules.entry(capsule_id.to_string()).or_default();
+        capsule.executions += 1;
+        capsule.duration_ms.observe(metrics.duration_ms as f64);
+        capsule.fuel_used.observe(metrics.fuel_used as f64);
+    }
+
+    /// Record a failed execution of `capsule_id`
+    pub(crate) fn record_failure(&self, capsule_id: &str, error: &ExecutionError) {
+        let mut snapshot = self.lock();
+        let runtime = &mut snapshot.runtime;
+        runtime.total_executions += 1;
+        runtime.failed_executions += 1;
+        runtime.total_fuel_used += error.fuel_used().unwrap_or(0);
+
+        let capsule = snapshot.capsules.entry(capsule_id.to_string()).or_default();
+        *capsule
+            .failures
+            .entry(error.kind().to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// Copy of the current metrics
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricsSnapshot> {
+        // The snapshot is updated in one step under the lock, so it is
+        // consistent even if a holder panicked
+        self.snapshot
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(duration_ms: u64, fuel_used: u64, memory_mb: f64) -> ExecMetrics {
+        ExecMetrics {
+            fuel_used,
+            memory_mb,
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::new(&[1.0, 10.0]);
+        for value in [0.5, 1.0, 5.0, 50.0] {
+            histogram.observe(value);
+        }
+        assert_eq!(histogram.counts, vec![2, 1, 1]);
+        assert_eq!(histogram.cumulative_counts(), vec![2, 3, 4]);
+        assert_eq!(histogram.sum, 56.5);
+    }
+
+    #[test]
+    fn test_registry_aggregates_runs_and_failures() {
+        let registry = MetricsRegistry::default();
+        registry.record_success("a", &run(10, 100, 1.0));
+        registry.record_success("a", &run(30, 300, 2.0));
+        registry.record_failure(
+            "a",
+            &ExecutionError::OutOfFuel {
+                fuel_used: 1000,
+                backtrace: None,
+            },
+        );
+        registry.record_failure(
+            "b",
+            &ExecutionError::IOError {
+                reason: "too large".to_string(),
+            },
+        );
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.runtime.total_executions, 4);
+        assert_eq!(snapshot.runtime.failed_executions, 2);
+        assert_eq!(snapshot.runtime.avg_execution_time_ms, 20.0);
+        assert_eq!(snapshot.runtime.peak_memory_mb, 2.0);
+        assert_eq!(snapshot.runtime.total_fuel_used, 1400);
+
+        let a = &snapshot.capsules["a"];
+        assert_eq!(a.executions, 2);
+        assert_eq!(a.duration_ms.count, 2);
+        assert_eq!(a.failures["out_of_fuel"], 1);
+        assert_eq!(snapshot.capsules["b"].failures["io"], 1);
+
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("tenzik_executions_total 4\n"));
+        assert!(text
+            .contains("tenzik_capsule_failures_total{capsule_id=\"a\",kind=\"out_of_fuel\"} 1\n"));
+        assert!(text.contains("tenzik_capsule_duration_ms_bucket{capsule_id=\"a\",le=\"10\"} 1\n"));
+        assert!(
+            text.contains("tenzik_capsule_duration_ms_bucket{capsule_id=\"a\",le=\"+Inf\"} 2\n")
+        );
+        assert!(text.contains("tenzik_capsule_fuel_used_sum{capsule_id=\"a\"} 400\n"));
+        assert!(text.contains("# TYPE tenzik_capsule_fuel_used histogram\n"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Capsule Pipelines
+//!
+//! This module chains capsules so each step's output becomes the next
+//! step's input, e.g. validate -> transform -> redact. Every step produces a
+//! normal `ExecutionReceipt` whose `parent_receipt_id` names the previous
+//! step's receipt, and the run as a whole produces a signed
+//! `PipelineReceipt` committing to the ordered capsule IDs, the step
+//! receipts and the final output.
+
+use std::sync::Arc;
+
+use crate::execution::{ExecutionError, ExecutionOptions, ExecutionResult, WasmRuntime};
+use crate::receipts::PipelineReceipt;
+use crate::sandbox::ResourceLimits;
+
+/// One capsule in a pipeline
+#[derive(Debug, Clone)]
+pub struct PipelineStep {
+    /// WASM capsule bytes
+    pub capsule: Arc<[u8]>,
+    /// Resource limits for this step
+    pub resource_limits: ResourceLimits,
+}
+
+/// Ordered list of capsules to run on a single input
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<PipelineStep>,
+}
+
+/// Result of a completed pipeline run
+#[derive(Debug, Clone)]
+pub struct PipelineResult {
+    /// Output of the last step
+    pub output: Vec<u8>,
+    /// Result of every step in execution order
+    pub steps: Vec<ExecutionResult>,
+    /// Signed receipt for the whole pipeline
+    pub receipt: PipelineReceipt,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a step that runs `capsule` on the previous step's output
+    pub fn then(mut self, capsule: impl Into<Arc<[u8]>>, resource_limits: ResourceLimits) -> Self {
+        self.steps.push(PipelineStep {
+            capsule: capsule.into(),
+            resource_limits,
+        });
+        self
+    }
+
+    /// Steps in execution order
+    pub fn steps(&self) -> &[PipelineStep] {
+        &self.steps
+    }
+
+    /// Number of steps
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether the pipeline has no steps
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Run every step on `runtime`, feeding each output into the next step.
+    ///
+    /// Stops at the first failing step and reports it as
+    /// `ExecutionError::PipelineStepFailed` with the zero-based step index.
+    pub async fn execute(
+        &self,
+        runtime: &WasmRuntime,
+        input: &[u8],
+    ) -> Result<PipelineResult, ExecutionError> {
+        if self.steps.is_empty() {
+            return Err(ExecutionError::ExecutionFailed {
+                reason: "Pipeline has no steps".to_string(),
+            });
+        }
+
+        let mut results: Vec<ExecutionResult> = Vec::with_capacity(self.steps.len());
+        for (i, step) in self.steps.iter().enumerate() {
+            let mut options = ExecutionOptions::default();
+            if let Some(previous) = results.last() {
+                options = options.with_parent_receipt_id(previous.receipt.receipt_id());
+            }
+            let step_input = results.last().map_or(input, |previous| &previous.output);
+
+            let result = runtime
+                .execute_with_options(
+                    &step.capsule,
+                    step_input,
+                    step.resource_limits.clone(),
+                    options,
+                )
+                .await
+                .map_err(|e| ExecutionError::PipelineStepFailed {
+                    step: i,
+                    source: Box::new(e),
+                })?;
+            results.push(result);
+        }
+
+        let output = results.last().map(|r| r.output.clone()).unwrap_or_default();
+        let step_receipts: Vec<_> = results.iter().map(|r| r.receipt.clone()).collect();
+        let receipt = PipelineReceipt::new(
+            &step_receipts,
+            input,
+            &output,
+            runtime.signing_key(),
+            runtime.take_nonce(),
+        )
+        .map_err(|e| ExecutionError::ReceiptError { source: e })?;
+
+        Ok(PipelineResult {
+            output,
+            steps: results,
+            receipt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receipts::generate_test_signing_key;
+
+    /// Legacy-ABI capsule that adds `delta` to every input byte
+    fn add_capsule(delta: u8) -> Vec<u8> {
+        wat::parse_str(format!(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                (local $i i32)
+                (block $done
+                  (loop $next
+                    (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+                    (i32.store8
+                      (i32.add (local.get $ptr) (local.get $i))
+                      (i32.add
+                        (i32.load8_u (i32.add (local.get $ptr) (local.get $i)))
+                        (i32.const {})))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $next)))
+                (i32.or (i32.shl (local.get $len) (i32.const 16)) (local.get $ptr))))
+            "#,
+            delta
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_chains_outputs_and_receipts() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let pipeline = Pipeline::new()
+            .then(add_capsule(1), ResourceLimits::default())
+            .then(add_capsule(2), ResourceLimits::default())
+            .then(add_capsule(3), ResourceLimits::default());
+
+        let result = pipeline.execute(&runtime, b"abc").await.unwrap();
+        assert_eq!(result.output, b"ghi");
+        assert_eq!(result.steps.len(), 3);
+
+        let steps: Vec<_> = result.steps.iter().map(|s| s.receipt.clone()).collect();
+        assert!(steps[0].parent_receipt_id.is_none());
+        assert_eq!(steps[1].parent_receipt_id, Some(steps[0].receipt_id()));
+        assert_eq!(steps[2].parent_receipt_id, Some(steps[1].receipt_id()));
+        assert!(steps.iter().all(|s| s.verify_node_signature().unwrap()));
+
+        let receipt = &result.receipt;
+        assert!(receipt.verify(&runtime.public_key()).unwrap());
+        assert!(receipt.verify_lineage(&steps));
+        assert_eq!(receipt.capsule_ids.len(), 3);
+        assert_eq!(
+            receipt.output_commit,
+            blake3::hash(b"ghi").to_hex().to_string()
+        );
+
+        // Reordered or missing steps break the lineage
+        assert!(!receipt.verify_lineage(&[steps[1].clone(), steps[0].clone(), steps[2].clone()]));
+        assert!(!receipt.verify_lineage(&steps[..2]));
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_reports_failing_step() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let pipeline = Pipeline::new()
+            .then(add_capsule(1), ResourceLimits::default())
+            .then(b"not wasm".to_vec(), ResourceLimits::default());
+
+        let result = pipeline.execute(&runtime, b"abc").await;
+        assert!(matches!(
+            result,
+            Err(ExecutionError::PipelineStepFailed { step: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_empty_pipeline_is_rejected() {
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        assert!(Pipeline::new().execute(&runtime, b"").await.is_err());
+    }
+}
@@ -2,14 +2,23 @@
 //!
 //! This module provides cryptographic receipts for WASM capsule executions.
 //! Receipts enable verification that an execution occurred with specific inputs/outputs
-//! without needing to re-execute the capsule.
+//! without needing to re-execute the capsule, and record enough context for a
+//! verifier to re-execute it when the signer is not trusted.
 
 use blake3;
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
+use crate::chunked;
+use crate::execution::{ExecutionError, ExecutionOptions, WasmRuntime};
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::sandbox::{Capability, ResourceLimits};
+use crate::secrets::SecretRef;
+use crate::signing;
+use crate::transcript::Transcript;
+
 /// Execution metrics collected during capsule execution
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ExecMetrics {
@@ -17,10 +26,16 @@ pub struct ExecMetrics {
     pub fuel_used: u64,
     /// Peak memory usage in MB
     pub memory_mb: f64,
+    /// Peak number of table elements
+    #[serde(default)]
+    pub table_elements: u32,
     /// Execution duration in milliseconds
     pub duration_ms: u64,
     /// Number of host function calls made
     pub host_function_calls: u32,
+    /// Host function calls broken down by function name
+    #[serde(default)]
+    pub host_call_breakdown: BTreeMap<String, u32>,
 }
 
 impl Default for ExecMetrics {
@@ -28,9 +43,141 @@ impl Default for ExecMetrics {
         Self {
             fuel_used: 0,
             memory_mb: 0.0,
+            table_elements: 0,
             duration_ms: 0,
             host_function_calls: 0,
+            host_call_breakdown: BTreeMap::new(),
+        }
+    }
+}
+
+impl ExecMetrics {
+    /// Canonical `name=count` list of host calls, sorted by name, as signed
+    /// in the receipt payload
+    pub fn host_call_breakdown_string(&self) -> String {
+        self.host_call_breakdown
+            .iter()
+            .map(|(name, count)| format!("{}={}", name, count))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Add the usage of another run: counters are summed and peaks keep the
+    /// larger value
+    pub fn accumulate(&mut self, other: &ExecMetrics) {
+        self.fuel_used += other.fuel_used;
+        self.memory_mb = self.memory_mb.max(other.memory_mb);
+        self.table_elements = self.table_elements.max(other.table_elements);
+        self.duration_ms += other.duration_ms;
+        self.host_function_calls += other.host_function_calls;
+        for (name, count) in &other.host_call_breakdown {
+            *self.host_call_breakdown.entry(name.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+/// Domain separation context for deriving capsule random seeds
+const RANDOM_SEED_CONTEXT: &str = "tenzik 2024-01-01 capsule random seed v1";
+
+/// Inputs a verifier needs, beyond the capsule and input bytes, to replay an
+/// execution and reproduce its output exactly
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReplayContext {
+    /// Logical execution timestamp (Unix milliseconds) served to the capsule
+    #[serde(default)]
+    pub logical_time_ms: u64,
+    /// Hex-encoded 32-byte seed of the `random_*` host function stream
+    #[serde(default)]
+    pub random_seed: String,
+    /// Blake3 commitment of the host call transcript, if one was recorded
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub transcript_commit: String,
+    /// Limits the capsule ran under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_limits: Option<ResourceLimits>,
+    /// Commitment of the `WasmFeaturePolicy` of the engine that ran the
+    /// capsule
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub feature_policy: String,
+}
+
+impl ReplayContext {
+    /// Create a replay context from a logical timestamp and random seed
+    pub fn new(logical_time_ms: u64, random_seed: [u8; 32]) -> Self {
+        Self {
+            logical_time_ms,
+            random_seed: hex::encode(random_seed),
+            transcript_commit: String::new(),
+            resource_limits: None,
+            feature_policy: String::new(),
+        }
+    }
+
+    /// Derive the default random seed for an execution from the capsule,
+    /// its input and the receipt nonce
+    pub fn derive_random_seed(capsule_id: &str, input_commit: &str, nonce: u64) -> [u8; 32] {
+        let material = format!("{}:{}:{}", capsule_id, input_commit, nonce);
+        blake3::derive_key(RANDOM_SEED_CONTEXT, material.as_bytes())
+    }
+
+    /// Decode the random seed
+    pub fn random_seed_bytes(&self) -> Result<[u8; 32], ReceiptError> {
+        hex::decode(&self.random_seed)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| ReceiptError::InvalidFormat {
+                reason: "Invalid random seed".to_string(),
+            })
+    }
+    
+    /// Canonical form of the resource limits as signed in the receipt
+    /// payload, empty if none were recorded
+    pub fn resource_limits_string(&self) -> String {
+        let limits = match &self.resource_limits {
+            Some(limits) => limits,
+            None => return String::new(),
+        };
+        let mut text = format!(
+            "memory_limit_mb={},execution_time_ms={},fuel_limit={},capabilities={}",
+            limits.memory_limit_mb,
+            limits.execution_time_ms,
+            limits.fuel_limit,
+            limits
+                .capabilities
+                .iter()
+                .map(|c| format!("{:?}", c))
+                .collect::<Vec<_>>()
+                .join("|")
+        );
+        if !limits.secrets.is_empty() {
+            text.push_str(&format!(",secrets={}", limits.secrets.join("|")));
         }
+        if !limits.secret_headers.is_empty() {
+            let rules: Vec<String> = limits
+                .secret_headers
+                .iter()
+                .map(|rule| format!("{}@{}={}", rule.secret, rule.host, rule.headers.join("|")))
+                .collect();
+            text.push_str(&format!(",secret_headers={}", rules.join(";")));
+        }
+        // The HTTP grant is only listed when it allows something, so
+        // receipts without one keep their payload
+        let http = &limits.http;
+        if !http.allow.is_empty() {
+            let rules: Vec<String> = http
+                .allow
+                .iter()
+                .map(|rule| format!("{}={}", rule.host, rule.methods.join("|")))
+                .collect();
+            text.push_str(&format!(
+                ",http_allow={},http_max_request_bytes={},http_max_response_bytes={}",
+                rules.join(";"),
+                http.max_request_bytes,
+                http.max_response_bytes
+            ));
+            text.push_str(&format!(",http_timeout_ms={}", http.timeout_ms));
+        }
+        text
     }
 }
 
@@ -51,6 +198,9 @@ pub enum ReceiptError {
     
     #[error("Serialization error: {source}")]
     SerializationError { source: serde_json::Error },
+    
+    #[error("Receipt of a run granted {capability:?} needs its transcript to be verified")]
+    TranscriptRequired { capability: Capability },
 }
 
 /// Cryptographic execution receipt
@@ -61,12 +211,43 @@ pub enum ReceiptError {
 pub struct ExecutionReceipt {
     /// Blake3 hash of the WASM capsule bytes
     pub capsule_id: String,
-    /// Blake3 hash of the input JSON
+    /// Blake3 hash of the input JSON, or its chunk Merkle root for chunked
+    /// I/O runs
     pub input_commit: String,
-    /// Blake3 hash of the output JSON  
+    /// Blake3 hash of the output JSON, or its chunk Merkle root for chunked
+    /// I/O runs
     pub output_commit: String,
+    /// Chunk size of a chunked I/O run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u64>,
+    /// Number of input chunks of a chunked I/O run, the leaf count of
+    /// `input_commit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_chunks: Option<u64>,
+    /// Number of output chunks of a chunked I/O run, the leaf count of
+    /// `output_commit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_chunks: Option<u64>,
     /// Execution metrics
     pub exec_metrics: ExecMetrics,
+    /// Deterministic inputs served to the capsule by the host
+    #[serde(flatten)]
+    pub replay: ReplayContext,
+    /// Blake3 commitment of the capsule's log lines, if it wrote any
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub log_commit: String,
+    /// Merkle root of the capsule's state namespace before the run
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pre_state_root: String,
+    /// Merkle root of the capsule's state namespace after the run
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub post_state_root: String,
+    /// Identifiers and versions of the node secrets the run used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets_used: Vec<SecretRef>,
+    /// Receipt ID of the pipeline step whose output was this step's input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_receipt_id: Option<String>,
     /// Ed25519 public key of the executing node
     pub node_id: String,
     /// Nonce for replay protection
@@ -80,66 +261,64 @@ pub struct ExecutionReceipt {
 }
 
 impl ExecutionReceipt {
-    /// Create a new execution receipt
+    /// Create a new execution receipt without optional fields, signed by
+    /// `ExecutionReceiptBuilder::finish`
     pub fn new(
         capsule_bytes: &[u8],
         input_bytes: &[u8],
         output_bytes: &[u8],
         metrics: ExecMetrics,
+        replay: ReplayContext,
         signing_key: &SigningKey,
         nonce: u64,
     ) -> Result<Self, ReceiptError> {
-        // Generate content commitments
-        let capsule_id = blake3::hash(capsule_bytes).to_hex().to_string();
-        let input_commit = blake3::hash(input_bytes).to_hex().to_string();
-        let output_commit = blake3::hash(output_bytes).to_hex().to_string();
-        
-        // Get node ID from signing key
-        let node_id = hex::encode(signing_key.verifying_key().as_bytes());
-        
-        // Generate timestamp
-        let timestamp = Self::current_timestamp_iso8601();
-        
-        // Create the payload to sign
-        let payload = Self::create_signature_payload(
-            &capsule_id,
-            &input_commit,
-            &output_commit,
-            &metrics,
-            &node_id,
-            nonce,
-            &timestamp,
-        );
-        
-        // Sign the payload
-        let signature_bytes = signing_key.sign(payload.as_bytes());
-        let signature = hex::encode(signature_bytes.to_bytes());
-        
-        Ok(ExecutionReceipt {
-            capsule_id,
-            input_commit,
-            output_commit,
+        Ok(Self::builder(capsule_bytes, input_bytes, output_bytes, metrics, replay, nonce)
+            .finish(signing_key))
+    }
+    
+    /// Start a receipt whose optional fields are set before it is signed
+    /// once by `ExecutionReceiptBuilder::finish`
+    pub fn builder(
+        capsule_bytes: &[u8],
+        input_bytes: &[u8],
+        output_bytes: &[u8],
+        metrics: ExecMetrics,
+        replay: ReplayContext,
+        nonce: u64,
+    ) -> ExecutionReceiptBuilder {
+        let receipt = ExecutionReceipt {
+            capsule_id: blake3::hash(capsule_bytes).to_hex().to_string(),
+            input_commit: blake3::hash(input_bytes).to_hex().to_string(),
+            output_commit: blake3::hash(output_bytes).to_hex().to_string(),
+            chunk_size: None,
+            input_chunks: None,
+            output_chunks: None,
             exec_metrics: metrics,
-            node_id,
+            replay,
+            log_commit: String::new(),
+            pre_state_root: String::new(),
+            post_state_root: String::new(),
+            secrets_used: Vec::new(),
+            parent_receipt_id: None,
+            node_id: String::new(),
             nonce,
-            signature,
-            timestamp,
+            signature: String::new(),
+            timestamp: Self::current_timestamp_iso8601(),
             version: "1.0.0".to_string(),
-        })
+        };
+        ExecutionReceiptBuilder { receipt }
+    }
+    
+    /// Sign the payload
+    fn sign(&mut self, signing_key: &SigningKey) {
+        let signature_bytes = signing_key.sign(self.signature_payload().as_bytes());
+        self.signature = hex::encode(signature_bytes.to_bytes());
     }
     
     /// Verify the receipt signature
     pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<bool, ReceiptError> {
         // Recreate the signature payload
-        let payload = Self::create_signature_payload(
-            &self.capsule_id,
-            &self.input_commit,
-            &self.output_commit,
-            &self.exec_metrics,
-            &self.node_id,
-            self.nonce,
-            &self.timestamp,
-        );
+        let payload = self.signature_payload();
         
         // Decode the signature
         let signature_bytes = hex::decode(&self.signature)
@@ -147,7 +326,7 @@ impl ExecutionReceipt {
                 reason: format!("Invalid signature hex: {}", e) 
             })?;
         
-        let signature = Signature::from_bytes(&signature_bytes)
+        let signature = Signature::from_slice(&signature_bytes)
             .map_err(|e| ReceiptError::CryptographicError { 
                 source: Box::new(e) 
             })?;
@@ -161,27 +340,92 @@ impl ExecutionReceipt {
     
     /// Verify that the receipt was signed by the claimed node
     pub fn verify_node_signature(&self) -> Result<bool, ReceiptError> {
-        // Decode the node public key
+        self.verify(&self.node_verifying_key()?)
+    }
+    
+    /// Public key that verifies the signatures the capsule made with
+    /// `sign_ed25519`, derived from `node_id` and `capsule_id`
+    pub fn capsule_verifying_key(&self) -> Result<VerifyingKey, ReceiptError> {
+        Ok(signing::capsule_verifying_key(&self.node_verifying_key()?, &self.capsule_id))
+    }
+    
+    /// Decode the node public key from `node_id`
+    fn node_verifying_key(&self) -> Result<VerifyingKey, ReceiptError> {
         let public_key_bytes = hex::decode(&self.node_id)
             .map_err(|e| ReceiptError::InvalidFormat { 
                 reason: format!("Invalid node_id hex: {}", e) 
             })?;
         
-        let verifying_key = VerifyingKey::from_bytes(&public_key_bytes
+        VerifyingKey::from_bytes(&public_key_bytes
             .try_into()
             .map_err(|_| ReceiptError::InvalidFormat { 
                 reason: "Invalid public key length".to_string() 
             })?)
             .map_err(|e| ReceiptError::CryptographicError { 
                 source: Box::new(e) 
-            })?;
-        
-        self.verify(&verifying_key)
+            })
+    }
+    
+    /// Commitment of `data` as it would appear in `input_commit` or
+    /// `output_commit`. Fails if the receipt records an unusable chunk size.
+    pub fn io_commitment(&self, data: &[u8]) -> Result<String, ReceiptError> {
+        Ok(match self.chunk_size_bytes()? {
+            Some(chunk_size) => chunked::chunk_root(data, chunk_size),
+            None => blake3::hash(data).to_hex().to_string(),
+        })
+    }
+    
+    /// Chunk size of a chunked I/O run, rejecting 0 and sizes this platform
+    /// cannot address since the value comes from the signed receipt
+    fn chunk_size_bytes(&self) -> Result<Option<usize>, ReceiptError> {
+        self.chunk_size
+            .map(|chunk_size| {
+                usize::try_from(chunk_size)
+                    .ok()
+                    .filter(|&chunk_size| chunk_size > 0)
+                    .ok_or_else(|| ReceiptError::InvalidFormat {
+                        reason: format!("Invalid chunk size: {}", chunk_size),
+                    })
+            })
+            .transpose()
+    }
+    
+    /// Check a proof that `chunk` is chunk `proof.index` of the input of a
+    /// chunked I/O run
+    pub fn verify_input_chunk(&self, chunk: &[u8], proof: &MerkleProof) -> bool {
+        self.verify_chunk(&self.input_commit, self.input_chunks, chunk, proof)
+    }
+    
+    /// Check a proof that `chunk` is chunk `proof.index` of the output of a
+    /// chunked I/O run
+    pub fn verify_output_chunk(&self, chunk: &[u8], proof: &MerkleProof) -> bool {
+        self.verify_chunk(&self.output_commit, self.output_chunks, chunk, proof)
+    }
+    
+    /// Check a chunk proof against a root whose signed leaf count is
+    /// `chunks`, so the proof cannot choose the shape of the tree
+    fn verify_chunk(
+        &self,
+        root: &str,
+        chunks: Option<u64>,
+        chunk: &[u8],
+        proof: &MerkleProof,
+    ) -> bool {
+        let (Some(chunk_size), Some(chunks)) = (self.chunk_size, chunks) else {
+            return false;
+        };
+        proof.leaf_count == chunks
+            && !chunk.is_empty()
+            && chunk.len() as u64 <= chunk_size
+            && chunked::verify_chunk(root, chunk, proof)
     }
     
     /// Get the receipt ID (hash of the receipt content)
+    ///
+    /// Pipeline steps also commit to their parent receipt, so a step's ID
+    /// pins the whole chain before it.
     pub fn receipt_id(&self) -> String {
-        let content = format!(
+        let mut content = format!(
             "{}:{}:{}:{}:{}",
             self.capsule_id,
             self.input_commit,
@@ -189,6 +433,10 @@ impl ExecutionReceipt {
             self.node_id,
             self.nonce
         );
+        if let Some(parent) = &self.parent_receipt_id {
+            content.push(':');
+            content.push_str(parent);
+        }
         blake3::hash(content.as_bytes()).to_hex().to_string()
     }
     
@@ -216,38 +464,64 @@ impl ExecutionReceipt {
     }
     
     /// Create the payload that gets signed
-    fn create_signature_payload(
-        capsule_id: &str,
-        input_commit: &str,
-        output_commit: &str,
-        metrics: &ExecMetrics,
-        node_id: &str,
-        nonce: u64,
-        timestamp: &str,
-    ) -> String {
+    fn signature_payload(&self) -> String {
         // Create a deterministic representation for signing
         format!(
             "TENZIK_RECEIPT_V1\n\
              capsule_id:{}\n\
              input_commit:{}\n\
              output_commit:{}\n\
+             chunk_size:{}\n\
+             input_chunks:{}\n\
+             output_chunks:{}\n\
              fuel_used:{}\n\
              memory_mb:{:.3}\n\
+             table_elements:{}\n\
              duration_ms:{}\n\
              host_calls:{}\n\
+             host_call_breakdown:{}\n\
+             logical_time_ms:{}\n\
+             random_seed:{}\n\
+             transcript_commit:{}\n\
+             resource_limits:{}\n\
+             feature_policy:{}\n\
+             log_commit:{}\n\
+             pre_state_root:{}\n\
+             post_state_root:{}\n\
+             secrets_used:{}\n\
+             parent_receipt_id:{}\n\
              node_id:{}\n\
              nonce:{}\n\
              timestamp:{}",
-            capsule_id,
-            input_commit,
-            output_commit,
-            metrics.fuel_used,
-            metrics.memory_mb,
-            metrics.duration_ms,
-            metrics.host_function_calls,
-            node_id,
-            nonce,
-            timestamp
+            self.capsule_id,
+            self.input_commit,
+            self.output_commit,
+            self.chunk_size.map(|size| size.to_string()).unwrap_or_default(),
+            self.input_chunks.map(|count| count.to_string()).unwrap_or_default(),
+            self.output_chunks.map(|count| count.to_string()).unwrap_or_default(),
+            self.exec_metrics.fuel_used,
+            self.exec_metrics.memory_mb,
+            self.exec_metrics.table_elements,
+            self.exec_metrics.duration_ms,
+            self.exec_metrics.host_function_calls,
+            self.exec_metrics.host_call_breakdown_string(),
+            self.replay.logical_time_ms,
+            self.replay.random_seed,
+            self.replay.transcript_commit,
+            self.replay.resource_limits_string(),
+            self.replay.feature_policy,
+            self.log_commit,
+            self.pre_state_root,
+            self.post_state_root,
+            self.secrets_used
+                .iter()
+                .map(|secret| format!("{}@{}", secret.id, secret.version))
+                .collect::<Vec<_>>()
+                .join(","),
+            self.parent_receipt_id.as_deref().unwrap_or_default(),
+            self.node_id,
+            self.nonce,
+            self.timestamp
         )
     }
     
@@ -257,6 +531,458 @@ impl ExecutionReceipt {
     }
 }
 
+/// Execution receipt being assembled; nothing is signed until `finish`
+#[derive(Debug, Clone)]
+pub struct ExecutionReceiptBuilder {
+    receipt: ExecutionReceipt,
+}
+
+impl ExecutionReceiptBuilder {
+    /// Commit to the input and output of a chunked I/O run as chunk Merkle
+    /// roots, along with their chunk counts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn chunked_io(mut self, chunk_size: usize, input: &[u8], output: &[u8]) -> Self {
+        self.receipt.chunk_size = Some(chunk_size as u64);
+        self.receipt.input_commit = chunked::chunk_root(input, chunk_size);
+        self.receipt.output_commit = chunked::chunk_root(output, chunk_size);
+        self.receipt.input_chunks = Some(chunked::chunk_count(input, chunk_size) as u64);
+        self.receipt.output_chunks = Some(chunked::chunk_count(output, chunk_size) as u64);
+        self
+    }
+    
+    /// Commit to the capsule's log lines
+    pub fn log_commit(mut self, log_commit: String) -> Self {
+        self.receipt.log_commit = log_commit;
+        self
+    }
+    
+    /// Commit to the state roots before and after the run
+    pub fn state_roots(mut self, pre_state_root: String, post_state_root: String) -> Self {
+        self.receipt.pre_state_root = pre_state_root;
+        self.receipt.post_state_root = post_state_root;
+        self
+    }
+    
+    /// Record the node secrets the run used
+    pub fn secrets_used(mut self, secrets_used: Vec<SecretRef>) -> Self {
+        self.receipt.secrets_used = secrets_used;
+        self
+    }
+    
+    /// Link the receipt to the receipt of the previous pipeline step
+    pub fn parent_receipt_id(mut self, parent_receipt_id: String) -> Self {
+        self.receipt.parent_receipt_id = Some(parent_receipt_id);
+        self
+    }
+    
+    /// Sign the receipt as the node holding `signing_key`
+    pub fn finish(self, signing_key: &SigningKey) -> ExecutionReceipt {
+        let mut receipt = self.receipt;
+        receipt.node_id = hex::encode(signing_key.verifying_key().as_bytes());
+        receipt.sign(signing_key);
+        receipt
+    }
+}
+
+/// Signed receipt for a capsule pipeline
+///
+/// Commits to the ordered capsules of the pipeline, the receipt of every
+/// step, the pipeline input and the final output. Each step receipt links to
+/// the previous one through `parent_receipt_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineReceipt {
+    /// Capsule IDs in execution order
+    pub capsule_ids: Vec<String>,
+    /// Receipt IDs of the steps in execution order
+    pub step_receipt_ids: Vec<String>,
+    /// Blake3 hash of the pipeline input
+    pub input_commit: String,
+    /// Blake3 hash of the final output
+    pub output_commit: String,
+    /// Ed25519 public key of the executing node
+    pub node_id: String,
+    /// Nonce for replay protection
+    pub nonce: u64,
+    /// Ed25519 signature of the receipt content
+    pub signature: String,
+    /// ISO 8601 timestamp of completion
+    pub timestamp: String,
+    /// Version of the receipt format
+    pub version: String,
+}
+
+impl PipelineReceipt {
+    /// Create a pipeline receipt over the step receipts of a completed run
+    pub fn new(
+        steps: &[ExecutionReceipt],
+        input_bytes: &[u8],
+        output_bytes: &[u8],
+        signing_key: &SigningKey,
+        nonce: u64,
+    ) -> Result<Self, ReceiptError> {
+        if steps.is_empty() {
+            return Err(ReceiptError::InvalidFormat {
+                reason: "Pipeline has no steps".to_string(),
+            });
+        }
+        
+        let mut receipt = PipelineReceipt {
+            capsule_ids: steps.iter().map(|step| step.capsule_id.clone()).collect(),
+            step_receipt_ids: steps.iter().map(|step| step.receipt_id()).collect(),
+            input_commit: blake3::hash(input_bytes).to_hex().to_string(),
+            output_commit: blake3::hash(output_bytes).to_hex().to_string(),
+            node_id: hex::encode(signing_key.verifying_key().as_bytes()),
+            nonce,
+            signature: String::new(),
+            timestamp: ExecutionReceipt::current_timestamp_iso8601(),
+            version: "1.0.0".to_string(),
+        };
+        
+        let signature_bytes = signing_key.sign(receipt.signature_payload().as_bytes());
+        receipt.signature = hex::encode(signature_bytes.to_bytes());
+        Ok(receipt)
+    }
+    
+    /// Verify the receipt signature
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<bool, ReceiptError> {
+        let signature_bytes = hex::decode(&self.signature)
+            .map_err(|e| ReceiptError::InvalidFormat { 
+                reason: format!("Invalid signature hex: {}", e) 
+            })?;
+        
+        let signature = Signature::from_slice(&signature_bytes)
+            .map_err(|e| ReceiptError::CryptographicError { 
+                source: Box::new(e) 
+            })?;
+        
+        Ok(verifying_key
+            .verify(self.signature_payload().as_bytes(), &signature)
+            .is_ok())
+    }
+    
+    /// Check that `steps` are the receipts this pipeline receipt commits to
+    /// and form an unbroken chain from the pipeline input to its output
+    pub fn verify_lineage(&self, steps: &[ExecutionReceipt]) -> bool {
+        if steps.len() != self.step_receipt_ids.len() || steps.is_empty() {
+            return false;
+        }
+        
+        let mut parent: Option<String> = None;
+        let mut input_commit = &self.input_commit;
+        for (i, step) in steps.iter().enumerate() {
+            let receipt_id = step.receipt_id();
+            if step.capsule_id != self.capsule_ids[i]
+                || receipt_id != self.step_receipt_ids[i]
+                || step.parent_receipt_id != parent
+                || &step.input_commit != input_commit
+            {
+                return false;
+            }
+            parent = Some(receipt_id);
+            input_commit = &step.output_commit;
+        }
+        
+        *input_commit == self.output_commit
+    }
+    
+    /// Get the receipt ID (hash of the receipt content)
+    pub fn receipt_id(&self) -> String {
+        blake3::hash(self.signature_payload().as_bytes()).to_hex().to_string()
+    }
+    
+    /// Serialize to JSON
+    pub fn to_json(&self) -> Result<String, ReceiptError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| ReceiptError::SerializationError { source: e })
+    }
+    
+    /// Deserialize from JSON
+    pub fn from_json(json: &str) -> Result<Self, ReceiptError> {
+        serde_json::from_str(json)
+            .map_err(|e| ReceiptError::SerializationError { source: e })
+    }
+    
+    /// Create the canonical payload for signing
+    fn signature_payload(&self) -> String {
+        format!(
+            "TENZIK_PIPELINE_RECEIPT_V1\n\
+             capsule_ids:{}\n\
+             step_receipt_ids:{}\n\
+             input_commit:{}\n\
+             output_commit:{}\n\
+             node_id:{}\n\
+             nonce:{}\n\
+             timestamp:{}",
+            self.capsule_ids.join(","),
+            self.step_receipt_ids.join(","),
+            self.input_commit,
+            self.output_commit,
+            self.node_id,
+            self.nonce,
+            self.timestamp
+        )
+    }
+}
+
+/// Domain separation prefix of batch input leaves
+const BATCH_INPUT_DOMAIN: &[u8] = b"TENZIK_BATCH_INPUT_V1";
+
+/// Domain separation prefix of batch output leaves
+const BATCH_OUTPUT_DOMAIN: &[u8] = b"TENZIK_BATCH_OUTPUT_V1";
+
+/// Leaf hash of one batch input or output
+fn batch_leaf(domain: &[u8], data: &[u8]) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(domain);
+    hasher.update(&(data.len() as u64).to_le_bytes());
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Proof that an (input, output) pair is item `index` of a batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchItemProof {
+    /// Inclusion proof of the input under `input_root`
+    pub input: MerkleProof,
+    /// Inclusion proof of the output under `output_root`
+    pub output: MerkleProof,
+}
+
+/// Signed receipt for a batch of runs of one capsule
+///
+/// Replaces one `ExecutionReceipt` per input with a single signature over
+/// Merkle roots of the inputs and outputs. A `BatchItemProof` ties one
+/// (input, output) pair to the roots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReceipt {
+    /// Blake3 hash of the WASM capsule bytes
+    pub capsule_id: String,
+    /// Number of inputs in the batch
+    pub item_count: u64,
+    /// Merkle root of the inputs in batch order
+    pub input_root: String,
+    /// Merkle root of the outputs in batch order
+    pub output_root: String,
+    /// Usage summed over every item, with peaks taken over all of them
+    pub exec_metrics: ExecMetrics,
+    /// Logical clock, batch random seed and limits shared by every item
+    #[serde(flatten)]
+    pub replay: ReplayContext,
+    /// Ed25519 public key of the executing node
+    pub node_id: String,
+    /// Nonce for replay protection
+    pub nonce: u64,
+    /// Ed25519 signature of the receipt content
+    pub signature: String,
+    /// ISO 8601 timestamp of completion
+    pub timestamp: String,
+    /// Version of the receipt format
+    pub version: String,
+}
+
+impl BatchReceipt {
+    /// Create a batch receipt over the inputs and outputs of a completed
+    /// batch, returning it with the proof of every item
+    pub fn new(
+        capsule_bytes: &[u8],
+        inputs: &[&[u8]],
+        outputs: &[&[u8]],
+        metrics: ExecMetrics,
+        replay: ReplayContext,
+        signing_key: &SigningKey,
+        nonce: u64,
+    ) -> Result<(Self, Vec<BatchItemProof>), ReceiptError> {
+        if inputs.is_empty() || inputs.len() != outputs.len() {
+            return Err(ReceiptError::InvalidFormat {
+                reason: format!(
+                    "Batch needs one output per input ({} inputs, {} outputs)",
+                    inputs.len(),
+                    outputs.len()
+                ),
+            });
+        }
+        
+        let input_tree = Self::tree(BATCH_INPUT_DOMAIN, inputs);
+        let output_tree = Self::tree(BATCH_OUTPUT_DOMAIN, outputs);
+        let root = |tree: &MerkleTree| {
+            tree.root().map(|root| root.to_hex().to_string()).unwrap_or_default()
+        };
+        let proofs = (0..inputs.len())
+            .filter_map(|i| {
+                Some(BatchItemProof {
+                    input: input_tree.proof(i)?,
+                    output: output_tree.proof(i)?,
+                })
+            })
+            .collect();
+        
+        let mut receipt = BatchReceipt {
+            capsule_id: blake3::hash(capsule_bytes).to_hex().to_string(),
+            item_count: inputs.len() as u64,
+            input_root: root(&input_tree),
+            output_root: root(&output_tree),
+            exec_metrics: metrics,
+            replay,
+            node_id: hex::encode(signing_key.verifying_key().as_bytes()),
+            nonce,
+            signature: String::new(),
+            timestamp: ExecutionReceipt::current_timestamp_iso8601(),
+            version: "1.0.0".to_string(),
+        };
+        
+        let signature_bytes = signing_key.sign(receipt.signature_payload().as_bytes());
+        receipt.signature = hex::encode(signature_bytes.to_bytes());
+        Ok((receipt, proofs))
+    }
+    
+    /// Merkle tree over the batch leaves of `items`
+    fn tree(domain: &[u8], items: &[&[u8]]) -> MerkleTree {
+        MerkleTree::new(items.iter().map(|item| batch_leaf(domain, item)).collect())
+    }
+    
+    /// Random seed of item `index`, derived from the batch seed
+    pub fn derive_item_seed(batch_seed: &[u8; 32], index: u64) -> [u8; 32] {
+        *blake3::keyed_hash(batch_seed, &index.to_le_bytes()).as_bytes()
+    }
+    
+    /// Random seed item `index` ran with, for re-executing it
+    pub fn item_random_seed(&self, index: u64) -> Result<[u8; 32], ReceiptError> {
+        Ok(Self::derive_item_seed(&self.replay.random_seed_bytes()?, index))
+    }
+    
+    /// Check that `input` and `output` are item `proof.input.index` of the
+    /// batch
+    pub fn verify_item(&self, input: &[u8], output: &[u8], proof: &BatchItemProof) -> bool {
+        let included = |proof: &MerkleProof, domain: &[u8], data: &[u8], root: &str| {
+            proof.leaf_count == self.item_count
+                && proof
+                    .root(batch_leaf(domain, data))
+                    .is_some_and(|computed| computed.to_hex().as_str() == root)
+        };
+        proof.input.index == proof.output.index
+            && included(&proof.input, BATCH_INPUT_DOMAIN, input, &self.input_root)
+            && included(&proof.output, BATCH_OUTPUT_DOMAIN, output, &self.output_root)
+    }
+    
+    /// Verify the receipt signature
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<bool, ReceiptError> {
+        let signature_bytes = hex::decode(&self.signature)
+            .map_err(|e| ReceiptError::InvalidFormat { 
+                reason: format!("Invalid signature hex: {}", e) 
+            })?;
+        
+        let signature = Signature::from_slice(&signature_bytes)
+            .map_err(|e| ReceiptError::CryptographicError { 
+                source: Box::new(e) 
+            })?;
+        
+        Ok(verifying_key
+            .verify(self.signature_payload().as_bytes(), &signature)
+            .is_ok())
+    }
+    
+    /// Get the receipt ID (hash of the receipt content)
+    pub fn receipt_id(&self) -> String {
+        blake3::hash(self.signature_payload().as_bytes()).to_hex().to_string()
+    }
+    
+    /// Serialize to JSON
+    pub fn to_json(&self) -> Result<String, ReceiptError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| ReceiptError::SerializationError { source: e })
+    }
+    
+    /// Deserialize from JSON
+    pub fn from_json(json: &str) -> Result<Self, ReceiptError> {
+        serde_json::from_str(json)
+            .map_err(|e| ReceiptError::SerializationError { source: e })
+    }
+    
+    /// Create the canonical payload for signing
+    fn signature_payload(&self) -> String {
+        format!(
+            "TENZIK_BATCH_RECEIPT_V1\n\
+             capsule_id:{}\n\
+             item_count:{}\n\
+             input_root:{}\n\
+             output_root:{}\n\
+             fuel_used:{}\n\
+             memory_mb:{:.3}\n\
+             table_elements:{}\n\
+             duration_ms:{}\n\
+             host_calls:{}\n\
+             host_call_breakdown:{}\n\
+             logical_time_ms:{}\n\
+             random_seed:{}\n\
+             resource_limits:{}\n\
+             feature_policy:{}\n\
+             node_id:{}\n\
+             nonce:{}\n\
+             timestamp:{}",
+            self.capsule_id,
+            self.item_count,
+            self.input_root,
+            self.output_root,
+            self.exec_metrics.fuel_used,
+            self.exec_metrics.memory_mb,
+            self.exec_metrics.table_elements,
+            self.exec_metrics.duration_ms,
+            self.exec_metrics.host_function_calls,
+            self.exec_metrics.host_call_breakdown_string(),
+            self.replay.logical_time_ms,
+            self.replay.random_seed,
+            self.replay.resource_limits_string(),
+            self.replay.feature_policy,
+            self.node_id,
+            self.nonce,
+            self.timestamp
+        )
+    }
+}
+
+/// Outcome of re-executing the capsule behind a receipt
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReexecutionVerdict {
+    /// The re-execution reproduced the receipt's output
+    Match,
+    /// The capsule bytes do not hash to the receipt's `capsule_id`
+    CapsuleMismatch { expected: String, actual: String },
+    /// The input bytes do not hash to the receipt's `input_commit`
+    InputMismatch { expected: String, actual: String },
+    /// The verifying runtime's `WasmFeaturePolicy` differs from the one the
+    /// receipt was produced under, so re-execution would not be meaningful
+    FeaturePolicyMismatch { expected: String, actual: String },
+    /// The re-execution produced a different output
+    OutputMismatch { expected: String, actual: String },
+    /// The re-execution started from or left a different capsule state
+    /// than the receipt's `pre_state_root` or `post_state_root`
+    StateMismatch { expected: String, actual: String },
+    /// The supplied transcript does not hash to the receipt's
+    /// `transcript_commit`
+    TranscriptMismatch { expected: String, actual: String },
+    /// The re-execution made different host calls than the transcript
+    /// records
+    TranscriptDiverged { reason: String },
+    /// The capsule trapped although the receipt records a result
+    NondeterministicTrap { reason: String },
+    /// The re-execution failed for a reason unrelated to the receipt, such
+    /// as a timeout or the verifying runtime's own limits, so the receipt
+    /// could not be verified
+    Inconclusive { reason: String },
+}
+
+/// Capabilities whose host calls depend on the executing node or the
+/// outside world, so re-execution must replay them from the transcript
+const TRANSCRIPT_CAPABILITIES: [Capability; 4] = [
+    Capability::State,
+    Capability::Http,
+    Capability::Secrets,
+    Capability::Sign,
+];
+
 /// Receipt verification utilities
 pub struct ReceiptVerifier {
     /// Maximum age for receipts to be considered valid (in seconds)
@@ -298,6 +1024,144 @@ impl ReceiptVerifier {
     pub fn verify_receipts(&self, receipts: &[ExecutionReceipt]) -> Vec<Result<bool, ReceiptError>> {
         receipts.iter().map(|r| self.verify_receipt(r)).collect()
     }
+    
+    /// Verify a receipt by running the capsule again on `runtime`
+    ///
+    /// The run uses the limits, logical time and random seed recorded in the
+    /// receipt. The receipt's age is not checked, so old receipts can still
+    /// be audited. Fails if the signature is invalid or the receipt does not
+    /// record its resource limits.
+    ///
+    /// Host calls are checked against `transcript` when one is given, after
+    /// checking it against the receipt's `transcript_commit`, so the run
+    /// never touches the network, the verifier's state store, secrets or
+    /// signing key. State calls are recomputed from the transcript's
+    /// pre-state and must reproduce the receipt's state roots; signatures
+    /// must verify under the receipt's capsule key. Receipts of runs granted
+    /// `State`, `Http`, `Secrets` or `Sign` cannot be verified without it.
+    pub async fn verify_by_reexecution(
+        &self,
+        runtime: &WasmRuntime,
+        capsule_bytes: &[u8],
+        input_bytes: &[u8],
+        receipt: &ExecutionReceipt,
+        transcript: Option<&Transcript>,
+    ) -> Result<ReexecutionVerdict, ReceiptError> {
+        if !receipt.verify_node_signature()? {
+            return Err(ReceiptError::SignatureVerificationFailed);
+        }
+        
+        let resource_limits = receipt.replay.resource_limits.clone()
+            .ok_or_else(|| ReceiptError::InvalidFormat {
+                reason: "Receipt does not record resource limits".to_string(),
+            })?;
+        if transcript.is_none() {
+            if let Some(capability) = TRANSCRIPT_CAPABILITIES
+                .into_iter()
+                .find(|&capability| resource_limits.has_capability(capability))
+            {
+                return Err(ReceiptError::TranscriptRequired { capability });
+            }
+        }
+        
+        let capsule_id = blake3::hash(capsule_bytes).to_hex().to_string();
+        if capsule_id != receipt.capsule_id {
+            return Ok(ReexecutionVerdict::CapsuleMismatch {
+                expected: receipt.capsule_id.clone(),
+                actual: capsule_id,
+            });
+        }
+        
+        let input_commit = receipt.io_commitment(input_bytes)?;
+        if input_commit != receipt.input_commit {
+            return Ok(ReexecutionVerdict::InputMismatch {
+                expected: receipt.input_commit.clone(),
+                actual: input_commit,
+            });
+        }
+        
+        let feature_policy = runtime.config().features.commitment();
+        if !receipt.replay.feature_policy.is_empty()
+            && receipt.replay.feature_policy != feature_policy
+        {
+            return Ok(ReexecutionVerdict::FeaturePolicyMismatch {
+                expected: receipt.replay.feature_policy.clone(),
+                actual: feature_policy,
+            });
+        }
+        
+        let mut options = ExecutionOptions::default()
+            .with_logical_time_ms(receipt.replay.logical_time_ms)
+            .with_random_seed(receipt.replay.random_seed_bytes()?);
+        if let Some(transcript) = transcript {
+            let transcript_commit = transcript.commitment();
+            if transcript_commit != receipt.replay.transcript_commit {
+                return Ok(ReexecutionVerdict::TranscriptMismatch {
+                    expected: receipt.replay.transcript_commit.clone(),
+                    actual: transcript_commit,
+                });
+            }
+            options = options
+                .with_replay_transcript(transcript.clone())
+                .with_replay_node_key(receipt.node_verifying_key()?);
+        }
+        if let Some(chunk_size) = receipt.chunk_size_bytes()? {
+            options = options.with_chunked_io(chunk_size);
+        }
+        let result = match runtime
+            .execute_with_options(capsule_bytes, input_bytes, resource_limits, options)
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => return Ok(Self::failure_verdict(e)),
+        };
+        
+        let output_commit = receipt.io_commitment(&result.output)?;
+        if output_commit != receipt.output_commit {
+            return Ok(ReexecutionVerdict::OutputMismatch {
+                expected: receipt.output_commit.clone(),
+                actual: output_commit,
+            });
+        }
+        
+        let state_roots = [
+            (&receipt.pre_state_root, &result.receipt.pre_state_root),
+            (&receipt.post_state_root, &result.receipt.post_state_root),
+        ];
+        for (expected, actual) in state_roots {
+            if expected != actual {
+                return Ok(ReexecutionVerdict::StateMismatch {
+                    expected: expected.clone(),
+                    actual: actual.clone(),
+                });
+            }
+        }
+        
+        Ok(ReexecutionVerdict::Match)
+    }
+    
+    /// Verdict for a re-execution that failed. Guest traps and replay
+    /// divergences follow from the capsule, input and transcript alone, so
+    /// they contradict the receipt; any other failure says nothing about it.
+    fn failure_verdict(error: ExecutionError) -> ReexecutionVerdict {
+        match error {
+            ExecutionError::OutOfFuel { .. }
+            | ExecutionError::MemoryOutOfBounds { .. }
+            | ExecutionError::Unreachable { .. }
+            | ExecutionError::StackOverflow { .. }
+            | ExecutionError::GuestTrap { .. } => ReexecutionVerdict::NondeterministicTrap {
+                reason: error.to_string(),
+            },
+            ExecutionError::ReplayDiverged { divergence } => {
+                ReexecutionVerdict::TranscriptDiverged {
+                    reason: divergence.to_string(),
+                }
+            }
+            error => ReexecutionVerdict::Inconclusive {
+                reason: error.to_string(),
+            },
+        }
+    }
 }
 
 /// Generate a new signing key for testing
@@ -310,6 +1174,7 @@ pub fn generate_test_signing_key() -> SigningKey {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::execution::RuntimeConfig;
     
     #[test]
     fn test_receipt_creation_and_verification() {
@@ -322,8 +1187,13 @@ mod tests {
         let metrics = ExecMetrics {
             fuel_used: 1000,
             memory_mb: 2.5,
+            table_elements: 0,
             duration_ms: 50,
             host_function_calls: 3,
+            host_call_breakdown: BTreeMap::from([
+                ("hash_commit".to_string(), 2),
+                ("json_path".to_string(), 1),
+            ]),
         };
         
         let receipt = ExecutionReceipt::new(
@@ -331,6 +1201,7 @@ mod tests {
             input_bytes,
             output_bytes,
             metrics,
+            ReplayContext::new(1_700_000_000_000, [7; 32]),
             &signing_key,
             12345,
         ).unwrap();
@@ -339,6 +1210,81 @@ mod tests {
         assert!(receipt.verify_node_signature().unwrap());
     }
     
+    #[test]
+    fn test_receipt_replay_context_is_signed() {
+        let signing_key = generate_test_signing_key();
+
+        let mut receipt = ExecutionReceipt::new(
+            b"test",
+            b"input",
+            b"output",
+            ExecMetrics::default(),
+            ReplayContext::new(1_700_000_000_000, [7; 32]),
+            &signing_key,
+            42,
+        ).unwrap();
+
+        assert_eq!(receipt.replay.logical_time_ms, 1_700_000_000_000);
+        assert_eq!(receipt.replay.random_seed_bytes().unwrap(), [7; 32]);
+        assert!(receipt.verify_node_signature().unwrap());
+
+        receipt.replay.logical_time_ms += 1;
+        assert!(!receipt.verify_node_signature().unwrap());
+
+        receipt.replay.logical_time_ms -= 1;
+        receipt.replay.random_seed = hex::encode([8; 32]);
+        assert!(!receipt.verify_node_signature().unwrap());
+    }
+
+    #[test]
+    fn test_builder_signs_optional_fields() {
+        let signing_key = generate_test_signing_key();
+        
+        let receipt = ExecutionReceipt::builder(
+            b"test",
+            b"input",
+            b"output",
+            ExecMetrics::default(),
+            ReplayContext::default(),
+            42,
+        )
+        .log_commit("logs".to_string())
+        .state_roots("pre".to_string(), "post".to_string())
+        .parent_receipt_id("parent".to_string())
+        .finish(&signing_key);
+        assert!(receipt.verify_node_signature().unwrap());
+        
+        for tamper in [
+            |r: &mut ExecutionReceipt| r.log_commit.clear(),
+            |r: &mut ExecutionReceipt| r.post_state_root = "pre".to_string(),
+            |r: &mut ExecutionReceipt| r.parent_receipt_id = None,
+        ] {
+            let mut tampered = receipt.clone();
+            tamper(&mut tampered);
+            assert!(!tampered.verify_node_signature().unwrap());
+        }
+    }
+    
+    #[test]
+    fn test_io_commitment_rejects_invalid_chunk_size() {
+        let signing_key = generate_test_signing_key();
+        let builder = ExecutionReceipt::builder(
+            b"test",
+            b"input",
+            b"output",
+            ExecMetrics::default(),
+            ReplayContext::default(),
+            42,
+        );
+        
+        let plain = builder.clone().finish(&signing_key);
+        assert_eq!(plain.io_commitment(b"input").unwrap(), plain.input_commit);
+        
+        let mut chunked = builder.finish(&signing_key);
+        chunked.chunk_size = Some(0);
+        assert!(chunked.io_commitment(b"input").is_err());
+    }
+    
     #[test]
     fn test_receipt_json_serialization() {
         let signing_key = generate_test_signing_key();
@@ -348,6 +1294,7 @@ mod tests {
             b"input",
             b"output",
             ExecMetrics::default(),
+            ReplayContext::default(),
             &signing_key,
             42,
         ).unwrap();
@@ -369,6 +1316,7 @@ mod tests {
             b"input",
             b"output",
             ExecMetrics::default(),
+            ReplayContext::default(),
             &signing_key,
             42,
         ).unwrap();
@@ -378,6 +1326,7 @@ mod tests {
             b"input",
             b"output",
             ExecMetrics::default(),
+            ReplayContext::default(),
             &signing_key,
             42,
         ).unwrap();
@@ -397,6 +1346,7 @@ mod tests {
             b"input",
             b"output",
             ExecMetrics::default(),
+            ReplayContext::default(),
             &signing_key1,
             42,
         ).unwrap();
@@ -414,6 +1364,7 @@ mod tests {
             b"input",
             b"output",
             ExecMetrics::default(),
+            ReplayContext::default(),
             &signing_key,
             42,
         ).unwrap();
@@ -435,6 +1386,7 @@ mod tests {
             b"input",
             b"output",
             ExecMetrics::default(),
+            ReplayContext::default(),
             &signing_key,
             42,
         ).unwrap();
@@ -442,13 +1394,53 @@ mod tests {
         assert!(verifier.verify_receipt(&receipt).unwrap());
     }
     
+    #[test]
+    fn test_host_call_breakdown_is_signed() {
+        let signing_key = generate_test_signing_key();
+        let metrics = ExecMetrics {
+            host_function_calls: 3,
+            host_call_breakdown: BTreeMap::from([
+                ("json_path".to_string(), 1),
+                ("hash_commit".to_string(), 2),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(metrics.host_call_breakdown_string(), "hash_commit=2,json_path=1");
+
+        let mut receipt = ExecutionReceipt::new(
+            b"test",
+            b"input",
+            b"output",
+            metrics,
+            ReplayContext::default(),
+            &signing_key,
+            42,
+        ).unwrap();
+        assert!(receipt.verify_node_signature().unwrap());
+
+        receipt.exec_metrics.host_call_breakdown.insert("hash_commit".to_string(), 1);
+        assert!(!receipt.verify_node_signature().unwrap());
+    }
+
+    #[test]
+    fn test_random_seed_derivation() {
+        let seed = ReplayContext::derive_random_seed("capsule", "input", 1);
+
+        assert_eq!(seed, ReplayContext::derive_random_seed("capsule", "input", 1));
+        assert_ne!(seed, ReplayContext::derive_random_seed("capsule", "input", 2));
+        assert_ne!(seed, ReplayContext::derive_random_seed("capsule", "other", 1));
+        assert!(ReplayContext::default().random_seed_bytes().is_err());
+    }
+
     #[test]
     fn test_exec_metrics() {
         let metrics = ExecMetrics {
             fuel_used: 5000,
             memory_mb: 16.75,
+            table_elements: 12,
             duration_ms: 125,
             host_function_calls: 7,
+            host_call_breakdown: BTreeMap::from([("random_u32".to_string(), 7)]),
         };
         
         // Test serialization
@@ -457,4 +1449,183 @@ mod tests {
         
         assert_eq!(metrics, deserialized);
     }
+
+    /// Capsule that returns 8 bytes from the `random_bytes` stream
+    const RANDOM_WAT: &str = r#"
+        (module
+          (import "env" "random_bytes" (func $bytes (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (drop (call $bytes (i32.const 4096) (i32.const 8)))
+            (i32.or (i32.shl (i32.const 8) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    fn random_limits() -> ResourceLimits {
+        let mut limits = ResourceLimits::default();
+        limits.add_capability(crate::sandbox::Capability::Random);
+        limits
+    }
+
+    #[tokio::test]
+    async fn test_reexecution_reproduces_receipt() {
+        let capsule = wat::parse_str(RANDOM_WAT).unwrap();
+        let signer = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let result = signer.execute(&capsule, b"in", random_limits()).await.unwrap();
+        let receipt = &result.receipt;
+        assert_eq!(receipt.replay.resource_limits, Some(random_limits()));
+
+        // A different node reproduces the run from the receipt alone
+        let verifier = ReceiptVerifier::default();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, &capsule, b"in", receipt, None)
+            .await
+            .unwrap();
+        assert_eq!(verdict, ReexecutionVerdict::Match);
+
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, b"other capsule", b"in", receipt, None)
+            .await
+            .unwrap();
+        assert!(matches!(verdict, ReexecutionVerdict::CapsuleMismatch { .. }));
+
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, &capsule, b"other", receipt, None)
+            .await
+            .unwrap();
+        assert!(matches!(verdict, ReexecutionVerdict::InputMismatch { .. }));
+
+        // Different engine semantics cannot reproduce the run
+        let config = RuntimeConfig {
+            features: crate::features::WasmFeaturePolicy {
+                nan_canonicalization: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let other_engine = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let verdict = verifier
+            .verify_by_reexecution(&other_engine, &capsule, b"in", receipt, None)
+            .await
+            .unwrap();
+        assert!(matches!(verdict, ReexecutionVerdict::FeaturePolicyMismatch { .. }));
+
+        // Recorded limits are signed
+        let mut tampered = receipt.clone();
+        tampered.replay.resource_limits = Some(ResourceLimits::development());
+        assert!(verifier
+            .verify_by_reexecution(&runtime, &capsule, b"in", &tampered, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reexecution_detects_forged_results() {
+        let signing_key = generate_test_signing_key();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let verifier = ReceiptVerifier::default();
+        let mut replay = ReplayContext::new(1_700_000_000_000, [7; 32]);
+        replay.resource_limits = Some(random_limits());
+
+        let capsule = wat::parse_str(RANDOM_WAT).unwrap();
+        let forged = ExecutionReceipt::new(
+            &capsule,
+            b"in",
+            b"not the output",
+            ExecMetrics::default(),
+            replay.clone(),
+            &signing_key,
+            1,
+        ).unwrap();
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, &capsule, b"in", &forged, None)
+            .await
+            .unwrap();
+        match verdict {
+            ReexecutionVerdict::OutputMismatch { expected, .. } => {
+                assert_eq!(expected, forged.output_commit)
+            }
+            other => panic!("unexpected verdict: {:?}", other),
+        }
+
+        let trapping = wat::parse_str(
+            r#"(module
+                 (memory (export "memory") 1)
+                 (func (export "run") (param i32 i32) (result i32) unreachable))"#,
+        ).unwrap();
+        let forged = ExecutionReceipt::new(
+            &trapping,
+            b"in",
+            b"out",
+            ExecMetrics::default(),
+            replay,
+            &signing_key,
+            2,
+        ).unwrap();
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, &trapping, b"in", &forged, None)
+            .await
+            .unwrap();
+        assert!(matches!(verdict, ReexecutionVerdict::NondeterministicTrap { .. }));
+    }
+
+    /// Capsule that returns its `sign_public_key`, which differs per node
+    const PUBLIC_KEY_WAT: &str = r#"
+        (module
+          (import "env" "sign_public_key" (func $public_key (param i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (drop (call $public_key (i32.const 4096)))
+            (i32.or (i32.shl (i32.const 32) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    #[tokio::test]
+    async fn test_reexecution_replays_transcript() {
+        let capsule = wat::parse_str(PUBLIC_KEY_WAT).unwrap();
+        let mut limits = ResourceLimits::default();
+        limits.add_capability(crate::sandbox::Capability::Sign);
+        let signer = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let result = signer.execute(&capsule, b"in", limits).await.unwrap();
+        let transcript = result.transcript.unwrap();
+        let receipt = &result.receipt;
+
+        // The verifier's own key would give a different output, so the run
+        // can only be verified from its transcript
+        let verifier = ReceiptVerifier::default();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let err = verifier
+            .verify_by_reexecution(&runtime, &capsule, b"in", receipt, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ReceiptError::TranscriptRequired {
+                capability: crate::sandbox::Capability::Sign
+            }
+        ));
+
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, &capsule, b"in", receipt, Some(&transcript))
+            .await
+            .unwrap();
+        assert_eq!(verdict, ReexecutionVerdict::Match);
+
+        let verdict = verifier
+            .verify_by_reexecution(&runtime, &capsule, b"in", receipt, Some(&Transcript::default()))
+            .await
+            .unwrap();
+        assert!(matches!(verdict, ReexecutionVerdict::TranscriptMismatch { .. }));
+
+        // A verifier that cannot grant the recorded limits cannot tell
+        let config = RuntimeConfig {
+            max_memory_mb: 8,
+            ..Default::default()
+        };
+        let small = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let verdict = verifier
+            .verify_by_reexecution(&small, &capsule, b"in", receipt, Some(&transcript))
+            .await
+            .unwrap();
+        assert!(matches!(verdict, ReexecutionVerdict::Inconclusive { .. }));
+    }
 }
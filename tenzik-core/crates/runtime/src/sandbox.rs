@@ -21,6 +21,16 @@ pub enum Capability {
     Time,
     /// Access to deterministic random number generation
     Random,
+    /// Access to guest debug logging
+    Log,
+    /// Access to the capsule's persistent key-value state
+    State,
+    /// Outbound HTTP to the hosts allowed by `ResourceLimits::http`
+    Http,
+    /// Use of the node secrets listed in `ResourceLimits::secrets`
+    Secrets,
+    /// Ed25519 signatures under a key derived for the capsule
+    Sign,
 }
 
 impl Capability {
@@ -32,6 +42,11 @@ impl Capability {
             Capability::Base64 => "base64_",
             Capability::Time => "time_",
             Capability::Random => "random_",
+            Capability::Log => "log_",
+            Capability::State => "state_",
+            Capability::Http => "http_",
+            Capability::Secrets => "secret_",
+            Capability::Sign => "sign_",
         }
     }
     
@@ -43,6 +58,11 @@ impl Capability {
             Capability::Base64,
             Capability::Time,
             Capability::Random,
+            Capability::Log,
+            Capability::State,
+            Capability::Http,
+            Capability::Secrets,
+            Capability::Sign,
         ]
     }
     
@@ -54,12 +74,17 @@ impl Capability {
             Capability::Base64 => "Base64 encoding and decoding",
             Capability::Time => "Deterministic timestamp access",
             Capability::Random => "Deterministic random number generation",
+            Capability::Log => "Debug logging returned to the caller",
+            Capability::State => "Persistent per-capsule key-value state",
+            Capability::Http => "Outbound HTTP to allowlisted hosts",
+            Capability::Secrets => "Host-held secrets used without exposing them",
+            Capability::Sign => "Signatures under a per-capsule derived key",
         }
     }
 }
 
 /// Resource limits for WASM execution
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResourceLimits {
     /// Maximum memory allocation in MB
     pub memory_limit_mb: u32,
@@ -69,6 +94,16 @@ pub struct ResourceLimits {
     pub fuel_limit: u64,
     /// Allowed capabilities
     pub capabilities: Vec<Capability>,
+    /// Hosts and limits for `Capability::Http`
+    #[serde(default)]
+    pub http: HttpPolicy,
+    /// Identifiers of the node secrets the capsule may use
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// Hosts and header names each secret may be sent to with
+    /// `http_request`; a secret without a rule is only usable for HMACs
+    #[serde(default)]
+    pub secret_headers: Vec<SecretHeaderRule>,
 }
 
 impl Default for ResourceLimits {
@@ -78,6 +113,9 @@ impl Default for ResourceLimits {
             execution_time_ms: 1000,
             fuel_limit: 1_000_000, // 1M fuel units
             capabilities: vec![Capability::Hash, Capability::Json], // Minimal default set
+            http: HttpPolicy::default(),
+            secrets: Vec::new(),
+            secret_headers: Vec::new(),
         }
     }
 }
@@ -90,6 +128,9 @@ impl ResourceLimits {
             execution_time_ms: 5000,
             fuel_limit: 10_000_000,
             capabilities: Capability::all(),
+            http: HttpPolicy::default(),
+            secrets: Vec::new(),
+            secret_headers: Vec::new(),
         }
     }
     
@@ -100,6 +141,9 @@ impl ResourceLimits {
             execution_time_ms: 500,
             fuel_limit: 500_000,
             capabilities: vec![Capability::Hash], // Only hashing in production
+            http: HttpPolicy::default(),
+            secrets: Vec::new(),
+            secret_headers: Vec::new(),
         }
     }
     
@@ -119,6 +163,112 @@ impl ResourceLimits {
     pub fn remove_capability(&mut self, capability: Capability) {
         self.capabilities.retain(|&c| c != capability);
     }
+    
+    /// Allow `secret` to be sent to `host` (`host` or `host:port`) under
+    /// any of `headers`
+    pub fn allow_secret_header(
+        &mut self,
+        secret: impl Into<String>,
+        host: impl Into<String>,
+        headers: &[&str],
+    ) {
+        self.secret_headers.push(SecretHeaderRule {
+            secret: secret.into(),
+            host: host.into(),
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+        });
+    }
+    
+    /// Check whether `secret` may be sent under the header `header` to
+    /// `host` on `port`
+    pub fn allows_secret_header(&self, secret: &str, header: &str, host: &str, port: u16) -> bool {
+        self.secret_headers.iter().any(|rule| rule.allows(secret, header, host, port))
+    }
+}
+
+/// Destination a node secret may be sent to as an HTTP header. Binding each
+/// secret to the hosts it is meant for keeps a capsule from sending it to
+/// another allowed host that would echo it back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretHeaderRule {
+    /// Secret identifier; the secret must also be granted in
+    /// `ResourceLimits::secrets`
+    pub secret: String,
+    /// Host name, or `host:port` to allow a single port
+    pub host: String,
+    /// Header names the secret may be sent under, case-insensitive
+    pub headers: Vec<String>,
+}
+
+impl SecretHeaderRule {
+    /// Check whether the rule lets `secret` go to `host` on `port` under
+    /// the header `header`
+    pub fn allows(&self, secret: &str, header: &str, host: &str, port: u16) -> bool {
+        self.secret == secret
+            && host_matches(&self.host, host, port)
+            && self.headers.iter().any(|h| h.eq_ignore_ascii_case(header))
+    }
+}
+
+/// Check whether a rule's `host` or `host:port` matches `host` on `port`
+fn host_matches(rule_host: &str, host: &str, port: u16) -> bool {
+    rule_host.eq_ignore_ascii_case(host)
+        || rule_host.eq_ignore_ascii_case(&format!("{}:{}", host, port))
+}
+
+/// Host and method allowed by an `HttpPolicy`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpAllowRule {
+    /// Host name, or `host:port` to allow a single port
+    pub host: String,
+    /// Allowed methods, case-insensitive
+    pub methods: Vec<String>,
+}
+
+/// Outbound HTTP grant of a capsule. Requests go only to allowlisted hosts
+/// and methods; an empty allowlist denies every request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpPolicy {
+    /// Allowed hosts and their methods
+    pub allow: Vec<HttpAllowRule>,
+    /// Maximum request size in bytes: the body plus every header name and
+    /// value
+    pub max_request_bytes: usize,
+    /// Maximum response body size in bytes
+    pub max_response_bytes: usize,
+    /// Time allowed for each request, including reading the response
+    pub timeout_ms: u64,
+}
+
+impl Default for HttpPolicy {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            max_request_bytes: 16 * 1024,
+            max_response_bytes: 64 * 1024,
+            timeout_ms: 500,
+        }
+    }
+}
+
+impl HttpPolicy {
+    /// Allow `methods` on `host` (`host` or `host:port`)
+    pub fn allow(mut self, host: impl Into<String>, methods: &[&str]) -> Self {
+        self.allow.push(HttpAllowRule {
+            host: host.into(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+        });
+        self
+    }
+    
+    /// Check whether `method` may be sent to `host` on `port`
+    pub fn allows(&self, method: &str, host: &str, port: u16) -> bool {
+        self.allow.iter().any(|rule| {
+            host_matches(&rule.host, host, port)
+                && rule.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+        })
+    }
 }
 
 /// Security sandbox errors
@@ -177,11 +327,6 @@ impl SecuritySandbox {
         sandbox
     }
     
-    /// Create a sandbox with default limits
-    pub fn default() -> Self {
-        Self::new(ResourceLimits::default())
-    }
-    
     /// Create a sandbox for development
     pub fn development() -> Self {
         Self::new(ResourceLimits::development())
@@ -204,18 +349,19 @@ impl SecuritySandbox {
     
     /// Check if an import is allowed (for WASM validation)
     pub fn allows_import(&self, import_name: &str) -> bool {
+        // Allow specific system imports
+        match import_name {
+            "env::memory" => return true,
+            "env::abort" => return true, // AssemblyScript abort function
+            _ => {}
+        }
+        
         // Check if it's a host function we recognize
-        if import_name.starts_with("env::") {
-            let function_name = &import_name[5..]; // Remove "env::" prefix
+        if let Some(function_name) = import_name.strip_prefix("env::") {
             return self.allows_host_function(function_name);
         }
         
-        // Allow specific system imports
-        match import_name {
-            "env::memory" => true,
-            "env::abort" => true, // AssemblyScript abort function
-            _ => false,
-        }
+        false
     }
     
     /// Log an access attempt
@@ -302,6 +448,27 @@ impl SecuritySandbox {
                     self.host_function_allowlist.insert("random_bytes".to_string(), capability);
                     self.host_function_allowlist.insert("random_u32".to_string(), capability);
                 }
+                Capability::Log => {
+                    self.host_function_allowlist.insert("log_write".to_string(), capability);
+                }
+                Capability::State => {
+                    self.host_function_allowlist.insert("state_get".to_string(), capability);
+                    self.host_function_allowlist.insert("state_put".to_string(), capability);
+                    self.host_function_allowlist.insert("state_delete".to_string(), capability);
+                }
+                Capability::Http => {
+                    self.host_function_allowlist.insert("http_request".to_string(), capability);
+                }
+                Capability::Secrets => {
+                    self.host_function_allowlist
+                        .insert("secret_hmac_sha256".to_string(), capability);
+                    self.host_function_allowlist
+                        .insert("secret_hmac_verify".to_string(), capability);
+                }
+                Capability::Sign => {
+                    self.host_function_allowlist.insert("sign_ed25519".to_string(), capability);
+                    self.host_function_allowlist.insert("sign_public_key".to_string(), capability);
+                }
             }
         }
     }
@@ -328,6 +495,13 @@ impl SecuritySandbox {
     }
 }
 
+impl Default for SecuritySandbox {
+    /// Create a sandbox with default limits
+    fn default() -> Self {
+        Self::new(ResourceLimits::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -390,6 +564,16 @@ mod tests {
         assert!(sandbox.allows_import("env::abort"));
         assert!(!sandbox.allows_import("env::json_path"));
         assert!(!sandbox.allows_import("unknown::function"));
+        
+        // System imports need no capability
+        let sandbox = SecuritySandbox::new(ResourceLimits {
+            capabilities: Vec::new(),
+            ..Default::default()
+        });
+        assert!(sandbox.allows_import("env::memory"));
+        assert!(sandbox.allows_import("env::abort"));
+        assert!(!sandbox.allows_import("env::hash_commit"));
+        assert!(!sandbox.allows_import("other::memory"));
     }
     
     #[test]
@@ -429,4 +613,33 @@ mod tests {
         assert!(log[0].allowed);
         assert!(!log[1].allowed);
     }
+    
+    #[test]
+    fn test_http_policy_matching() {
+        let policy = HttpPolicy::default()
+            .allow("api.example.com", &["GET"])
+            .allow("127.0.0.1:8080", &["get", "POST"]);
+        
+        assert!(policy.allows("GET", "api.example.com", 443));
+        assert!(policy.allows("GET", "API.example.com", 8443));
+        assert!(!policy.allows("POST", "api.example.com", 443));
+        assert!(policy.allows("POST", "127.0.0.1", 8080));
+        assert!(policy.allows("GET", "127.0.0.1", 8080));
+        assert!(!policy.allows("GET", "127.0.0.1", 8081));
+        assert!(!policy.allows("GET", "evil.example.com", 443));
+        assert!(!HttpPolicy::default().allows("GET", "api.example.com", 443));
+    }
+    
+    #[test]
+    fn test_secret_header_matching() {
+        let mut limits = ResourceLimits::default();
+        limits.allow_secret_header("api-token", "api.example.com", &["Authorization"]);
+        
+        let allows = |secret, header, host| limits.allows_secret_header(secret, header, host, 443);
+        
+        assert!(allows("api-token", "authorization", "api.example.com"));
+        assert!(!allows("api-token", "x-echo", "api.example.com"));
+        assert!(!allows("api-token", "authorization", "evil.example.com"));
+        assert!(!allows("webhook", "authorization", "api.example.com"));
+    }
 }
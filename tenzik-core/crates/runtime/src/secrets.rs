@@ -0,0 +1,300 @@
+//! Node Secrets
+//!
+//! This module holds secrets on the node, such as webhook signing keys and
+//! API tokens, so capsules can use them without putting them in their input.
+//! A capsule granted `Capability::Secrets` names the secrets listed in
+//! `ResourceLimits::secrets` by identifier: `secret_hmac_sha256` and
+//! `secret_hmac_verify` compute an HMAC under a secret, and `http_request`
+//! can send one as a header to the hosts `ResourceLimits::secret_headers`
+//! binds it to. The host never writes secret bytes into guest memory, and
+//! receipts record only the identifier and version of each secret used.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Length of an HMAC-SHA256 tag in bytes
+pub const HMAC_SHA256_LEN: usize = 32;
+
+/// One version of a secret
+#[derive(Clone)]
+pub struct SecretValue {
+    version: u32,
+    bytes: Vec<u8>,
+}
+
+impl SecretValue {
+    /// Version of the secret, incremented on every rotation
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretValue")
+            .field("version", &self.version)
+            .field("bytes", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Backend holding the node's secrets
+pub trait SecretStore: Send + Sync {
+    /// Current version of the secret `id`, if the node holds it
+    fn get(&self, id: &str) -> Option<SecretValue>;
+}
+
+/// Secret store that lives as long as the runtime
+#[derive(Debug, Default)]
+pub struct MemorySecretStore {
+    secrets: Mutex<HashMap<String, SecretValue>>,
+}
+
+impl MemorySecretStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store or rotate the secret `id`, returning its new version (from 1)
+    pub fn insert(&self, id: impl Into<String>, bytes: impl Into<Vec<u8>>) -> u32 {
+        let mut secrets = self
+            .secrets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let id = id.into();
+        let version = secrets.get(&id).map_or(1, |secret| secret.version + 1);
+        secrets.insert(
+            id,
+            SecretValue {
+                version,
+                bytes: bytes.into(),
+            },
+        );
+        version
+    }
+}
+
+impl SecretStore for MemorySecretStore {
+    fn get(&self, id: &str) -> Option<SecretValue> {
+        let secrets = self
+            .secrets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        secrets.get(id).cloned()
+    }
+}
+
+/// Identifier and version of a secret used by a run, as recorded in the
+/// receipt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretRef {
+    /// Secret identifier
+    pub id: String,
+    /// Version that was used
+    pub version: u32,
+}
+
+/// Secrets granted to one run, resolved when it starts so a rotation during
+/// the run does not change what it sees
+#[derive(Debug, Default)]
+pub(crate) struct GrantedSecrets {
+    secrets: BTreeMap<String, SecretValue>,
+    used: BTreeSet<String>,
+}
+
+impl GrantedSecrets {
+    /// Look up every granted identifier the store holds
+    pub(crate) fn resolve(store: &dyn SecretStore, granted: &[String]) -> Self {
+        let secrets = granted
+            .iter()
+            .filter_map(|id| Some((id.clone(), store.get(id)?)))
+            .collect();
+        Self {
+            secrets,
+            used: BTreeSet::new(),
+        }
+    }
+
+    /// Bytes of the secret `id`, recording that the run used it
+    pub(crate) fn use_secret(&mut self, id: &str) -> Option<&[u8]> {
+        let secret = self.secrets.get(id)?;
+        self.used.insert(id.to_string());
+        Some(secret.bytes())
+    }
+
+    /// Identifiers and versions of the secrets used so far
+    pub(crate) fn used_refs(&self) -> Vec<SecretRef> {
+        self.used
+            .iter()
+            .map(|id| SecretRef {
+                id: id.clone(),
+                version: self.secrets[id].version(),
+            })
+            .collect()
+    }
+}
+
+/// HMAC-SHA256 of `data` under `key`
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; HMAC_SHA256_LEN] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Check an HMAC-SHA256 tag in constant time
+pub(crate) fn hmac_sha256_verify(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::WasmRuntime;
+    use crate::host::test_support::limits_with;
+    use crate::host::{HOST_ERR_SECRET_UNAVAILABLE, HOST_OK};
+    use crate::receipts::generate_test_signing_key;
+    use crate::sandbox::{Capability, ResourceLimits};
+
+    #[test]
+    fn test_rotation_bumps_version_and_redacts_debug() {
+        let store = MemorySecretStore::new();
+        assert_eq!(store.insert("webhook", "old"), 1);
+        assert_eq!(store.insert("webhook", "new"), 2);
+
+        let secret = store.get("webhook").unwrap();
+        assert_eq!(secret.version(), 2);
+        assert_eq!(secret.bytes(), b"new");
+        assert!(!format!("{:?}", secret).contains("new"));
+        assert!(store.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_granted_secrets_record_usage() {
+        let store = MemorySecretStore::new();
+        store.insert("a", "1");
+        store.insert("b", "2");
+        store.insert("b", "3");
+        let mut granted = GrantedSecrets::resolve(&store, &["b".into(), "missing".into()]);
+
+        assert!(granted.use_secret("a").is_none());
+        assert!(granted.use_secret("missing").is_none());
+        assert!(granted.used_refs().is_empty());
+        assert_eq!(granted.use_secret("b"), Some(&b"3"[..]));
+        assert_eq!(
+            granted.used_refs(),
+            [SecretRef {
+                id: "b".into(),
+                version: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_hmac_sha256_matches_rfc_4231() {
+        // Test case 2 of RFC 4231
+        let tag = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            hex::encode(tag),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(hmac_sha256_verify(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &tag
+        ));
+        assert!(!hmac_sha256_verify(b"Jefe", b"what do ya want?", &tag));
+    }
+
+    /// Capsule that computes the HMAC of its input under the "webhook"
+    /// secret, verifies it, and tries the ungranted "missing" secret.
+    /// Returns the tag followed by the three status codes.
+    const WEBHOOK_WAT: &str = r#"
+        (module
+          (import "env" "secret_hmac_sha256"
+            (func $hmac (param i32 i32 i32 i32 i32) (result i32)))
+          (import "env" "secret_hmac_verify"
+            (func $verify (param i32 i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "webhook")
+          (data (i32.const 16) "missing")
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (i32.store (i32.const 4128)
+              (call $hmac (i32.const 0) (i32.const 7)
+                (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (i32.store (i32.const 4132)
+              (call $verify (i32.const 0) (i32.const 7)
+                (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (i32.store (i32.const 4136)
+              (call $hmac (i32.const 16) (i32.const 7)
+                (local.get $ptr) (local.get $len) (i32.const 8192)))
+            (i32.or (i32.shl (i32.const 44) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    fn secret_limits(secrets: &[&str]) -> ResourceLimits {
+        let mut limits = limits_with(&[Capability::Secrets]);
+        limits.secrets = secrets.iter().map(|id| id.to_string()).collect();
+        limits
+    }
+
+    fn secret_runtime() -> WasmRuntime {
+        let store = MemorySecretStore::new();
+        store.insert("webhook", "first-key");
+        store.insert("webhook", "s3cret-key");
+        store.insert("missing", "never granted");
+        WasmRuntime::new(generate_test_signing_key())
+            .unwrap()
+            .with_secret_store(std::sync::Arc::new(store))
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_used_without_being_exposed() {
+        let runtime = secret_runtime();
+        let capsule = wat::parse_str(WEBHOOK_WAT).unwrap();
+        let payload = br#"{"event":"push"}"#;
+
+        let result = runtime
+            .execute(&capsule, payload, secret_limits(&["webhook"]))
+            .await
+            .unwrap();
+        let codes: Vec<i32> = result.output[32..]
+            .chunks(4)
+            .map(|c| i32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(result.output[..32], hmac_sha256(b"s3cret-key", payload));
+        assert_eq!(codes, [HOST_OK, 1, HOST_ERR_SECRET_UNAVAILABLE]);
+
+        let receipt = &result.receipt;
+        assert_eq!(
+            receipt.secrets_used,
+            [SecretRef {
+                id: "webhook".to_string(),
+                version: 2
+            }]
+        );
+        assert!(receipt.verify_node_signature().unwrap());
+        assert!(!receipt.to_json().unwrap().contains("s3cret"));
+
+        // Secrets held by the node but not granted stay unavailable
+        let result = runtime
+            .execute(&capsule, payload, secret_limits(&[]))
+            .await
+            .unwrap();
+        assert_eq!(
+            i32::from_le_bytes(result.output[32..36].try_into().unwrap()),
+            HOST_ERR_SECRET_UNAVAILABLE
+        );
+        assert!(result.receipt.secrets_used.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Capsule Signing Keys
+//!
+//! This module gives each capsule its own Ed25519 key, derived from the node
+//! key, the capsule's `capsule_id` and a fixed domain string. A capsule
+//! granted `Capability::Sign` signs bytes with `sign_ed25519` and reads its
+//! public key with `sign_public_key`; the secret key never leaves the host.
+//!
+//! The derivation adds a public tweak to the node's secret scalar, so the
+//! matching public key is the node public key plus the same tweak times the
+//! base point. Anyone holding a receipt can therefore derive the key that
+//! must verify a capsule's signatures from its `node_id` and `capsule_id`
+//! alone, and a capsule can never produce signatures under another
+//! capsule's key or the node key.
+
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::{EdwardsPoint, Scalar};
+use ed25519_dalek::hazmat::{self, ExpandedSecretKey};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use sha2::{Digest, Sha512};
+
+/// Domain separation prefix of the key tweak
+const KEY_DOMAIN: &[u8] = b"TENZIK_CAPSULE_SIGNING_KEY_V1";
+
+/// Domain separation prefix of the derived nonce prefix
+const NONCE_DOMAIN: &[u8] = b"TENZIK_CAPSULE_SIGNING_NONCE_V1";
+
+/// Length of an Ed25519 signature in bytes
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Length of an Ed25519 public key in bytes
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Scalar added to the node key to get the key of `capsule_id`
+fn key_tweak(node_key: &VerifyingKey, capsule_id: &str) -> Scalar {
+    let digest = Sha512::new()
+        .chain_update(KEY_DOMAIN)
+        .chain_update(node_key.as_bytes())
+        .chain_update(capsule_id.as_bytes())
+        .finalize();
+    Scalar::from_bytes_mod_order_wide(&digest.into())
+}
+
+/// Public key of the capsule `capsule_id` run by the node `node_key`
+pub fn capsule_verifying_key(node_key: &VerifyingKey, capsule_id: &str) -> VerifyingKey {
+    let tweak = key_tweak(node_key, capsule_id);
+    let point = EdwardsPoint::from(*node_key) + ED25519_BASEPOINT_TABLE * &tweak;
+    VerifyingKey::from(point)
+}
+
+/// Signing key of one capsule, derived from the node key
+pub(crate) struct CapsuleSigner {
+    /// Absent on replay, where only the public key is known
+    secret: Option<ExpandedSecretKey>,
+    public: VerifyingKey,
+}
+
+impl CapsuleSigner {
+    /// Derive the key of `capsule_id` from `node_key`
+    pub(crate) fn derive(node_key: &SigningKey, capsule_id: &str) -> Self {
+        let node_secret = ExpandedSecretKey::from(node_key.as_bytes());
+        let tweak = key_tweak(&node_key.verifying_key(), capsule_id);
+
+        // Every capsule gets its own nonce prefix so equal messages signed
+        // under different capsule keys never share a nonce
+        let nonce = Sha512::new()
+            .chain_update(NONCE_DOMAIN)
+            .chain_update(node_secret.hash_prefix)
+            .chain_update(capsule_id.as_bytes())
+            .finalize();
+        let mut hash_prefix = [0u8; 32];
+        hash_prefix.copy_from_slice(&nonce[..32]);
+
+        let secret = ExpandedSecretKey {
+            scalar: node_secret.scalar + tweak,
+            hash_prefix,
+        };
+        let public = VerifyingKey::from(&secret);
+        Self {
+            secret: Some(secret),
+            public,
+        }
+    }
+
+    /// Public half of the key of `capsule_id` run by the node `node_key`,
+    /// used to check signatures on replay
+    pub(crate) fn verifier(node_key: &VerifyingKey, capsule_id: &str) -> Self {
+        Self {
+            secret: None,
+            public: capsule_verifying_key(node_key, capsule_id),
+        }
+    }
+
+    /// Ed25519 signature of `message`, if the secret key is known
+    pub(crate) fn sign(&self, message: &[u8]) -> Option<[u8; SIGNATURE_LEN]> {
+        let secret = self.secret.as_ref()?;
+        Some(hazmat::raw_sign::<Sha512>(secret, message, &self.public).to_bytes())
+    }
+
+    /// Public key signatures verify under
+    pub(crate) fn verifying_key(&self) -> &VerifyingKey {
+        &self.public
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::{ExecutionError, ExecutionOptions, WasmRuntime};
+    use crate::host::test_support::limits_with;
+    use crate::receipts::generate_test_signing_key;
+    use crate::sandbox::Capability;
+    use ed25519_dalek::{Signature, Verifier};
+
+    #[test]
+    fn test_derived_key_is_public_from_node_id() {
+        let node_key = generate_test_signing_key();
+        let signer = CapsuleSigner::derive(&node_key, "capsule-a");
+
+        let derived = capsule_verifying_key(&node_key.verifying_key(), "capsule-a");
+        assert_eq!(signer.verifying_key(), &derived);
+        assert_ne!(derived, node_key.verifying_key());
+        assert_ne!(
+            derived,
+            capsule_verifying_key(&node_key.verifying_key(), "capsule-b")
+        );
+
+        let signature = Signature::from_bytes(&signer.sign(b"approved").unwrap());
+        assert!(derived.verify(b"approved", &signature).is_ok());
+        assert!(derived.verify(b"rejected", &signature).is_err());
+        assert!(node_key
+            .verifying_key()
+            .verify(b"approved", &signature)
+            .is_err());
+    }
+
+    #[test]
+    fn test_capsules_do_not_share_nonces() {
+        let node_key = generate_test_signing_key();
+        let a = CapsuleSigner::derive(&node_key, "capsule-a")
+            .sign(b"same")
+            .unwrap();
+        let b = CapsuleSigner::derive(&node_key, "capsule-b")
+            .sign(b"same")
+            .unwrap();
+
+        // The first half of a signature is the nonce commitment R
+        assert_ne!(a[..32], b[..32]);
+        assert_eq!(
+            Some(a),
+            CapsuleSigner::derive(&node_key, "capsule-a").sign(b"same")
+        );
+    }
+
+    /// Signs the input, then writes the signature and the public key
+    const SIGN_WAT: &str = r#"
+        (module
+          (import "env" "sign_ed25519" (func $sign (param i32 i32 i32) (result i32)))
+          (import "env" "sign_public_key" (func $public_key (param i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (drop (call $sign (local.get $ptr) (local.get $len) (i32.const 4096)))
+            (drop (call $public_key (i32.const 4160)))
+            (i32.or (i32.shl (i32.const 96) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    #[tokio::test]
+    async fn test_signatures_verify_under_derived_key() {
+        let limits = limits_with(&[Capability::Sign]);
+        let capsule = wat::parse_str(SIGN_WAT).unwrap();
+        let runtime = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let result = runtime
+            .execute(&capsule, b"approve #42", limits.clone())
+            .await
+            .unwrap();
+
+        // A verifier derives the key from the receipt alone
+        let key = result.receipt.capsule_verifying_key().unwrap();
+        let signature = Signature::from_bytes(result.output[..64].try_into().unwrap());
+        assert_eq!(result.output[64..], key.to_bytes());
+        assert!(key.verify(b"approve #42", &signature).is_ok());
+        assert_ne!(key, runtime.public_key());
+
+        // Another node replays the signatures from the transcript, checking
+        // them against the recording node's capsule key
+        let transcript = result.transcript.clone().unwrap();
+        let other = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let options = ExecutionOptions::default()
+            .with_replay_transcript(transcript.clone())
+            .with_replay_node_key(runtime.public_key());
+        let replayed = other
+            .execute_with_options(&capsule, b"approve #42", limits.clone(), options)
+            .await
+            .unwrap();
+        assert_eq!(replayed.output, result.output);
+
+        // A forged signature, or one checked against the wrong node, diverges
+        let mut forged = transcript.clone();
+        forged.calls[0].writes[0].data[0] ^= 1;
+        let cases = [
+            (forged, runtime.public_key()),
+            (transcript, other.public_key()),
+        ];
+        for (transcript, node_key) in cases {
+            let options = ExecutionOptions::default()
+                .with_replay_transcript(transcript)
+                .with_replay_node_key(node_key);
+            let result = other
+                .execute_with_options(&capsule, b"approve #42", limits.clone(), options)
+                .await;
+            match result {
+                Err(ExecutionError::ReplayDiverged { divergence }) => {
+                    assert_eq!(divergence.index, 0);
+                    assert!(divergence.actual.ends_with("with an invalid signature"));
+                }
+                other => panic!("expected divergence, got {:?}", other.map(|r| r.output)),
+            }
+        }
+    }
+}
@@ -0,0 +1,534 @@
+//! Capsule State
+//!
+//! This module gives capsules a small persistent key-value store through the
+//! `state_get`, `state_put` and `state_delete` host functions. Each capsule
+//! sees its own namespace, keyed by its `capsule_id`.
+//!
+//! A run works on an in-memory copy of its namespace that is written back
+//! only if the run succeeds. The Merkle roots of the namespace before and
+//! after the run are signed into the receipt, so a verifier holding the
+//! pre-state can check every state transition.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::merkle;
+
+/// Domain separation prefix of state tree leaves
+const LEAF_DOMAIN: &[u8] = b"TENZIK_STATE_LEAF_V1";
+
+/// Domain separation prefix of the root of an empty namespace
+const EMPTY_DOMAIN: &[u8] = b"TENZIK_STATE_EMPTY_V1";
+
+/// Largest key a capsule may store, in bytes
+pub const MAX_STATE_KEY_LEN: usize = 256;
+
+/// Default limit on the key and value bytes held by one namespace
+pub const DEFAULT_MAX_STATE_BYTES: usize = 1024 * 1024;
+
+/// Key-value entries of one namespace
+pub type StateEntries = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// State store errors
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("State I/O error for {namespace}: {source}")]
+    Io {
+        namespace: String,
+        source: std::io::Error,
+    },
+
+    #[error("Corrupt state for {namespace}: {reason}")]
+    Corrupt { namespace: String, reason: String },
+}
+
+/// Backend holding every capsule namespace
+pub trait StateStore: Send + Sync {
+    /// Load all entries of `namespace`; a missing namespace is empty
+    fn load(&self, namespace: &str) -> Result<StateEntries, StateError>;
+
+    /// Replace all entries of `namespace`
+    fn store(&self, namespace: &str, entries: &StateEntries) -> Result<(), StateError>;
+}
+
+/// State store that lives as long as the runtime
+#[derive(Debug, Default)]
+pub struct MemoryStateStore {
+    namespaces: Mutex<HashMap<String, StateEntries>>,
+}
+
+impl MemoryStateStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn load(&self, namespace: &str) -> Result<StateEntries, StateError> {
+        let namespaces = self
+            .namespaces
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(namespaces.get(namespace).cloned().unwrap_or_default())
+    }
+
+    fn store(&self, namespace: &str, entries: &StateEntries) -> Result<(), StateError> {
+        let mut namespaces = self
+            .namespaces
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        namespaces.insert(namespace.to_string(), entries.clone());
+        Ok(())
+    }
+}
+
+/// State store keeping one JSON file per namespace in a directory
+#[derive(Debug)]
+pub struct FileStateStore {
+    dir: PathBuf,
+}
+
+/// On-disk form of a namespace: hex keys mapped to hex values
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    entries: BTreeMap<String, String>,
+}
+
+impl FileStateStore {
+    /// Use `dir` for state files, creating it if needed
+    pub fn new(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, namespace: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", namespace))
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn load(&self, namespace: &str) -> Result<StateEntries, StateError> {
+        let text = match fs::read_to_string(self.path(namespace)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(StateEntries::new()),
+            Err(source) => {
+                return Err(StateError::Io {
+                    namespace: namespace.to_string(),
+                    source,
+                })
+            }
+        };
+
+        let corrupt = |reason: String| StateError::Corrupt {
+            namespace: namespace.to_string(),
+            reason,
+        };
+        let file: StateFile = serde_json::from_str(&text).map_err(|e| corrupt(e.to_string()))?;
+        file.entries
+            .iter()
+            .map(|(key, value)| {
+                Ok((
+                    hex::decode(key).map_err(|e| corrupt(e.to_string()))?,
+                    hex::decode(value).map_err(|e| corrupt(e.to_string()))?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Write the namespace atomically (temp file + rename)
+    fn store(&self, namespace: &str, entries: &StateEntries) -> Result<(), StateError> {
+        let file = StateFile {
+            entries: entries
+                .iter()
+                .map(|(key, value)| (hex::encode(key), hex::encode(value)))
+                .collect(),
+        };
+        let text = serde_json::to_string(&file).map_err(|e| StateError::Corrupt {
+            namespace: namespace.to_string(),
+            reason: e.to_string(),
+        })?;
+
+        let path = self.path(namespace);
+        let tmp = path.with_extension("json.tmp");
+        let io_error = |source| StateError::Io {
+            namespace: namespace.to_string(),
+            source,
+        };
+        fs::write(&tmp, text).map_err(io_error)?;
+        fs::rename(&tmp, &path).map_err(io_error)
+    }
+}
+
+/// Hex Merkle root over the entries, sorted by key.
+///
+/// Leaves hash the length-prefixed key and value and are combined with the
+/// shared `merkle.rs` tree.
+pub fn state_root(entries: &StateEntries) -> String {
+    let leaves: Vec<blake3::Hash> = entries
+        .iter()
+        .map(|(key, value)| {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(LEAF_DOMAIN);
+            hasher.update(&(key.len() as u64).to_le_bytes());
+            hasher.update(key);
+            hasher.update(&(value.len() as u64).to_le_bytes());
+            hasher.update(value);
+            hasher.finalize()
+        })
+        .collect();
+
+    merkle::merkle_root(&leaves)
+        .unwrap_or_else(|| blake3::hash(EMPTY_DOMAIN))
+        .to_hex()
+        .to_string()
+}
+
+/// Why a `state_put` was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PutError {
+    /// The key is longer than `MAX_STATE_KEY_LEN`
+    KeyTooLong,
+    /// The namespace would exceed its byte limit
+    Full,
+}
+
+/// Working copy of one namespace during a run
+#[derive(Debug, Default)]
+pub(crate) struct CapsuleState {
+    entries: StateEntries,
+    size_bytes: usize,
+    max_bytes: usize,
+}
+
+impl CapsuleState {
+    /// Start from the stored entries of the namespace
+    pub(crate) fn new(entries: StateEntries, max_bytes: usize) -> Self {
+        let size_bytes = entries.iter().map(|(k, v)| k.len() + v.len()).sum();
+        Self {
+            entries,
+            size_bytes,
+            max_bytes,
+        }
+    }
+
+    pub(crate) fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub(crate) fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), PutError> {
+        if key.len() > MAX_STATE_KEY_LEN {
+            return Err(PutError::KeyTooLong);
+        }
+        let replaced = self.entries.get(key).map_or(0, |old| key.len() + old.len());
+        let size_bytes = self.size_bytes - replaced + key.len() + value.len();
+        if size_bytes > self.max_bytes {
+            return Err(PutError::Full);
+        }
+        self.size_bytes = size_bytes;
+        self.entries.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    /// Remove a key, returning whether it was present
+    pub(crate) fn delete(&mut self, key: &[u8]) -> bool {
+        match self.entries.remove(key) {
+            Some(value) => {
+                self.size_bytes -= key.len() + value.len();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn entries(&self) -> &StateEntries {
+        &self.entries
+    }
+}
+
+/// Per-namespace locks so runs of the same capsule see each other's state
+/// transitions in order
+#[derive(Debug, Default)]
+pub(crate) struct NamespaceLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl NamespaceLocks {
+    /// Lock `namespace` until the returned guard is dropped
+    pub(crate) async fn lock(&self, namespace: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self
+                .locks
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            locks.entry(namespace.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::{ExecutionError, ExecutionOptions, RuntimeConfig, WasmRuntime};
+    use crate::host::test_support::limits_with;
+    use crate::receipts::{
+        generate_test_signing_key, ExecutionReceipt, ReceiptVerifier, ReexecutionVerdict,
+    };
+    use crate::sandbox::{Capability, ResourceLimits};
+
+    fn entries(pairs: &[(&str, &str)]) -> StateEntries {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_state_root_covers_every_entry() {
+        let base = entries(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let root = state_root(&base);
+
+        assert_eq!(root, state_root(&base.clone()));
+        assert_ne!(root, state_root(&entries(&[("a", "1"), ("b", "2")])));
+        assert_ne!(
+            root,
+            state_root(&entries(&[("a", "1"), ("b", "2"), ("c", "4")]))
+        );
+        assert_ne!(root, state_root(&entries(&[("a", "1"), ("b", "23")])));
+        assert_ne!(
+            state_root(&StateEntries::new()),
+            state_root(&entries(&[("", "")]))
+        );
+    }
+
+    #[test]
+    fn test_capsule_state_enforces_limits() {
+        let mut state = CapsuleState::new(entries(&[("k", "vvv")]), 8);
+
+        assert_eq!(state.put(b"k", b"vvvvvvv"), Ok(()));
+        assert_eq!(state.put(b"x", b""), Err(PutError::Full));
+        assert!(state.delete(b"k"));
+        assert!(!state.delete(b"k"));
+        assert_eq!(state.put(b"x", b"1234567"), Ok(()));
+        assert_eq!(
+            state.put(&[0; MAX_STATE_KEY_LEN + 1], b""),
+            Err(PutError::KeyTooLong)
+        );
+    }
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = FileStateStore::new(dir.path()).unwrap();
+        let data = entries(&[("count", "\u{1}"), ("seen", "")]);
+
+        assert!(store.load("capsule").unwrap().is_empty());
+        store.store("capsule", &data).unwrap();
+        assert_eq!(store.load("capsule").unwrap(), data);
+        assert!(store.load("other").unwrap().is_empty());
+    }
+
+    /// Capsule that increments a little-endian counter stored under
+    /// "count" and returns it. A non-empty input traps after the update.
+    const COUNTER_WAT: &str = r#"
+        (module
+          (import "env" "state_get" (func $get (param i32 i32 i32 i32) (result i32)))
+          (import "env" "state_put" (func $put (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "count")
+          (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+            (drop (call $get (i32.const 0) (i32.const 5) (i32.const 4096) (i32.const 4)))
+            (i32.store (i32.const 4096) (i32.add (i32.load (i32.const 4096)) (i32.const 1)))
+            (drop (call $put (i32.const 0) (i32.const 5) (i32.const 4096) (i32.const 4)))
+            (if (i32.ne (local.get $len) (i32.const 0)) (then unreachable))
+            (i32.or (i32.shl (i32.const 4) (i32.const 16)) (i32.const 4096))))
+    "#;
+
+    fn state_limits() -> ResourceLimits {
+        limits_with(&[Capability::State])
+    }
+
+    #[tokio::test]
+    async fn test_state_persists_and_roots_chain() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = RuntimeConfig {
+            state_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let capsule = wat::parse_str(COUNTER_WAT).unwrap();
+        let runtime =
+            WasmRuntime::with_config(generate_test_signing_key(), config.clone()).unwrap();
+
+        let first = runtime
+            .execute(&capsule, b"", state_limits())
+            .await
+            .unwrap();
+        assert_eq!(first.output, 1u32.to_le_bytes());
+        assert_eq!(
+            first.receipt.pre_state_root,
+            state_root(&StateEntries::new())
+        );
+
+        // State runs always record a transcript, and replaying it recomputes
+        // the state calls from the recorded pre-state without touching the
+        // store
+        let transcript = first.transcript.clone().unwrap();
+        assert_eq!(
+            first.receipt.replay.transcript_commit,
+            transcript.commitment()
+        );
+        let replayed = runtime
+            .execute_with_options(
+                &capsule,
+                b"",
+                state_limits(),
+                ExecutionOptions::default().with_replay_transcript(transcript),
+            )
+            .await
+            .unwrap();
+        assert_eq!(replayed.output, first.output);
+        assert_eq!(
+            state_root(
+                &runtime
+                    .state_store()
+                    .load(&first.receipt.capsule_id)
+                    .unwrap()
+            ),
+            first.receipt.post_state_root
+        );
+
+        // A trapping run leaves the state untouched
+        assert!(runtime
+            .execute(&capsule, b"!", state_limits())
+            .await
+            .is_err());
+
+        // A new runtime over the same directory continues the counter
+        let runtime = WasmRuntime::with_config(generate_test_signing_key(), config).unwrap();
+        let second = runtime
+            .execute(&capsule, b"", state_limits())
+            .await
+            .unwrap();
+        assert_eq!(second.output, 2u32.to_le_bytes());
+        assert_eq!(second.receipt.pre_state_root, first.receipt.post_state_root);
+        assert_eq!(
+            second.receipt.post_state_root,
+            state_root(
+                &runtime
+                    .state_store()
+                    .load(&second.receipt.capsule_id)
+                    .unwrap()
+            )
+        );
+        assert!(second.receipt.verify_node_signature().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reexecution_recomputes_state_roots() {
+        let node_key = generate_test_signing_key();
+        let runtime = WasmRuntime::new(node_key.clone()).unwrap();
+        let capsule = wat::parse_str(COUNTER_WAT).unwrap();
+        runtime
+            .execute(&capsule, b"", state_limits())
+            .await
+            .unwrap();
+        let second = runtime
+            .execute(&capsule, b"", state_limits())
+            .await
+            .unwrap();
+        let transcript = second.transcript.clone().unwrap();
+        assert_eq!(
+            transcript.pre_state_entries(),
+            entries(&[("count", "\u{1}\0\0\0")])
+        );
+
+        // Another node reproduces both state roots from the transcript
+        let verifier = WasmRuntime::new(generate_test_signing_key()).unwrap();
+        let verdict = ReceiptVerifier::default()
+            .verify_by_reexecution(&verifier, &capsule, b"", &second.receipt, Some(&transcript))
+            .await
+            .unwrap();
+        assert_eq!(verdict, ReexecutionVerdict::Match);
+
+        // A receipt claiming another post-state root is caught
+        let forged = ExecutionReceipt::builder(
+            &capsule,
+            b"",
+            &second.output,
+            second.metrics.clone(),
+            second.receipt.replay.clone(),
+            second.receipt.nonce,
+        )
+        .state_roots(
+            second.receipt.pre_state_root.clone(),
+            state_root(&StateEntries::new()),
+        )
+        .finish(&node_key);
+        let verdict = ReceiptVerifier::default()
+            .verify_by_reexecution(&verifier, &capsule, b"", &forged, Some(&transcript))
+            .await
+            .unwrap();
+        assert_eq!(
+            verdict,
+            ReexecutionVerdict::StateMismatch {
+                expected: state_root(&StateEntries::new()),
+                actual: second.receipt.post_state_root.clone(),
+            }
+        );
+
+        // State reads are recomputed, so an edited value diverges
+        let mut edited = transcript;
+        edited.calls[0].writes[0].data[0] = 5;
+        let result = verifier
+            .execute_with_options(
+                &capsule,
+                b"",
+                state_limits(),
+                ExecutionOptions::default().with_replay_transcript(edited),
+            )
+            .await;
+        match result {
+            Err(ExecutionError::ReplayDiverged { divergence }) => {
+                assert_eq!(divergence.index, 0);
+                assert!(divergence.expected.starts_with("state_get(0, 5, 4096, 4)"));
+            }
+            other => panic!("expected divergence, got {:?}", other.map(|r| r.output)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_state_updates_are_serialized() {
+        let runtime = std::sync::Arc::new(WasmRuntime::new(generate_test_signing_key()).unwrap());
+        let capsule: std::sync::Arc<[u8]> = wat::parse_str(COUNTER_WAT).unwrap().into();
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let runtime = runtime.clone();
+                let capsule = capsule.clone();
+                tokio::spawn(async move {
+                    runtime
+                        .execute(&capsule, b"", state_limits())
+                        .await
+                        .unwrap()
+                        .output
+                })
+            })
+            .collect();
+
+        let mut counts = Vec::new();
+        for handle in handles {
+            counts.push(u32::from_le_bytes(
+                handle.await.unwrap().try_into().unwrap(),
+            ));
+        }
+        counts.sort();
+        assert_eq!(counts, (1..=16).collect::<Vec<u32>>());
+    }
+}
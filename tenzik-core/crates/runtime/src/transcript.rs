@@ -0,0 +1,537 @@
+//! Host Call Transcripts
+//!
+//! This module records every host call a capsule makes: the function, its
+//! arguments, a hash of the guest bytes it read, the request it sent if it
+//! left the sandbox (`http_request`), the bytes the host wrote into guest
+//! memory and the value it returned. A
+//! transcript's Blake3 commitment is signed into the receipt.
+//!
+//! In replay mode each call must match the next record. Calls that only
+//! depend on the guest, the signed logical clock and random seed, or the
+//! capsule state recorded at the start of the run are computed again and
+//! must reproduce the recorded writes and result; HTTP responses, secret
+//! HMACs and signatures are answered from the record. A verifier can thus
+//! reproduce a run without access to the original environment. The first
+//! call that does not match the transcript stops the run and is reported as
+//! a [`ReplayDivergence`].
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::receipts::ReceiptError;
+use crate::state::StateEntries;
+
+/// Domain separation prefix of the transcript commitment
+const TRANSCRIPT_DOMAIN: &[u8] = b"TENZIK_TRANSCRIPT_V2";
+
+/// Bytes written into guest memory by a host call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestWrite {
+    /// Guest address of the write
+    pub ptr: u32,
+    /// Bytes written
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// One host call and its observable effects
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostCallRecord {
+    /// Host function name
+    pub function: String,
+    /// Arguments passed by the guest
+    pub args: Vec<i64>,
+    /// Hex Blake3 hash of the guest bytes the call read; empty for calls
+    /// that read none
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub read_hash: String,
+    /// Guest bytes the call sent out of the sandbox, such as an HTTP
+    /// request; empty for calls that stay inside the host
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "hex_bytes")]
+    pub request: Vec<u8>,
+    /// Writes into guest memory, in order
+    pub writes: Vec<GuestWrite>,
+    /// Value returned to the guest
+    pub result: i64,
+}
+
+/// One entry of the capsule state a run started from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateRecord {
+    /// Entry key
+    #[serde(with = "hex_bytes")]
+    pub key: Vec<u8>,
+    /// Entry value
+    #[serde(with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+/// Ordered record of every host call made during an execution
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    /// Host calls in the order they were made
+    pub calls: Vec<HostCallRecord>,
+    /// Capsule state the run started from, sorted by key; empty for runs
+    /// not granted `State`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_state: Vec<StateRecord>,
+}
+
+impl Transcript {
+    /// Number of recorded host calls
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Whether no host calls were recorded
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Hex Blake3 commitment over the canonical binary encoding
+    pub fn commitment(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(TRANSCRIPT_DOMAIN);
+        hasher.update(&(self.calls.len() as u64).to_le_bytes());
+        for call in &self.calls {
+            hasher.update(&(call.function.len() as u64).to_le_bytes());
+            hasher.update(call.function.as_bytes());
+            hasher.update(&(call.args.len() as u64).to_le_bytes());
+            for arg in &call.args {
+                hasher.update(&arg.to_le_bytes());
+            }
+            hasher.update(&(call.read_hash.len() as u64).to_le_bytes());
+            hasher.update(call.read_hash.as_bytes());
+            hasher.update(&(call.request.len() as u64).to_le_bytes());
+            hasher.update(&call.request);
+            hasher.update(&(call.writes.len() as u64).to_le_bytes());
+            for write in &call.writes {
+                hasher.update(&write.ptr.to_le_bytes());
+                hasher.update(&(write.data.len() as u64).to_le_bytes());
+                hasher.update(&write.data);
+            }
+            hasher.update(&call.result.to_le_bytes());
+        }
+        hasher.update(&(self.pre_state.len() as u64).to_le_bytes());
+        for entry in &self.pre_state {
+            hasher.update(&(entry.key.len() as u64).to_le_bytes());
+            hasher.update(&entry.key);
+            hasher.update(&(entry.value.len() as u64).to_le_bytes());
+            hasher.update(&entry.value);
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Capsule state the run started from
+    pub fn pre_state_entries(&self) -> StateEntries {
+        self.pre_state
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .collect()
+    }
+
+    /// Serialize to JSON
+    pub fn to_json(&self) -> Result<String, ReceiptError> {
+        serde_json::to_string(self).map_err(|e| ReceiptError::SerializationError { source: e })
+    }
+
+    /// Deserialize from JSON
+    pub fn from_json(json: &str) -> Result<Self, ReceiptError> {
+        serde_json::from_str(json).map_err(|e| ReceiptError::SerializationError { source: e })
+    }
+}
+
+/// First point where a replayed run departed from its transcript
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    /// Index of the host call that diverged
+    pub index: usize,
+    /// What the transcript expected
+    pub expected: String,
+    /// What the replayed run did
+    pub actual: String,
+}
+
+impl fmt::Display for ReplayDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "host call {}: expected {}, got {}",
+            self.index, self.expected, self.actual
+        )
+    }
+}
+
+/// Transcript handling for one execution
+#[derive(Debug, Default)]
+pub(crate) enum TranscriptMode {
+    /// Host calls run normally and are not recorded
+    #[default]
+    Off,
+    /// Host calls run normally and are appended to the transcript
+    Record(Transcript),
+    /// Host calls are checked against the transcript, and recomputed or
+    /// answered from it
+    Replay {
+        transcript: Transcript,
+        position: usize,
+        divergence: Option<ReplayDivergence>,
+        /// Writes made by the call being recomputed
+        writes: Vec<GuestWrite>,
+    },
+}
+
+impl TranscriptMode {
+    /// Replay `transcript`
+    pub(crate) fn replay(transcript: Transcript) -> Self {
+        TranscriptMode::Replay {
+            transcript,
+            position: 0,
+            divergence: None,
+            writes: Vec::new(),
+        }
+    }
+
+    /// Whether host calls are neither recorded nor replayed
+    pub(crate) fn is_off(&self) -> bool {
+        matches!(self, TranscriptMode::Off)
+    }
+
+    /// Start recording a call
+    pub(crate) fn begin_record(
+        &mut self,
+        function: &str,
+        args: &[i64],
+        read_hash: &str,
+        request: &[u8],
+    ) {
+        if let TranscriptMode::Record(transcript) = self {
+            transcript.calls.push(HostCallRecord {
+                function: function.to_string(),
+                args: args.to_vec(),
+                read_hash: read_hash.to_string(),
+                request: request.to_vec(),
+                writes: Vec::new(),
+                result: 0,
+            });
+        }
+    }
+
+    /// Record the capsule state the run starts from
+    pub(crate) fn record_pre_state(&mut self, entries: &StateEntries) {
+        if let TranscriptMode::Record(transcript) = self {
+            transcript.pre_state = entries
+                .iter()
+                .map(|(key, value)| StateRecord {
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect();
+        }
+    }
+
+    /// Record a write made by the call in progress
+    pub(crate) fn record_write(&mut self, ptr: i32, data: &[u8]) {
+        let write = GuestWrite {
+            ptr: ptr as u32,
+            data: data.to_vec(),
+        };
+        match self {
+            TranscriptMode::Record(transcript) => {
+                if let Some(call) = transcript.calls.last_mut() {
+                    call.writes.push(write);
+                }
+            }
+            TranscriptMode::Replay { writes, .. } => writes.push(write),
+            TranscriptMode::Off => {}
+        }
+    }
+
+    /// Record the value returned by the call in progress
+    pub(crate) fn finish_record(&mut self, result: i64) {
+        if let TranscriptMode::Record(transcript) = self {
+            if let Some(call) = transcript.calls.last_mut() {
+                call.result = result;
+            }
+        }
+    }
+
+    /// Take the next recorded call if it matches `function`, `args`,
+    /// `read_hash` and `request`, otherwise remember where the run diverged
+    pub(crate) fn next_replayed(
+        &mut self,
+        function: &str,
+        args: &[i64],
+        read_hash: &str,
+        request: &[u8],
+    ) -> Result<HostCallRecord, ReplayDivergence> {
+        let TranscriptMode::Replay {
+            transcript,
+            position,
+            divergence,
+            writes,
+        } = self
+        else {
+            unreachable!("next_replayed called outside replay mode");
+        };
+        writes.clear();
+
+        let index = *position;
+        let actual = describe_call(function, args, read_hash, request);
+        let result = match transcript.calls.get(index) {
+            Some(call)
+                if call.function == function
+                    && call.args == args
+                    && call.read_hash == read_hash
+                    && call.request == request =>
+            {
+                Ok(call.clone())
+            }
+            Some(call) => Err(ReplayDivergence {
+                index,
+                expected: call.describe(),
+                actual,
+            }),
+            None => Err(ReplayDivergence {
+                index,
+                expected: "end of transcript".to_string(),
+                actual,
+            }),
+        };
+
+        match &result {
+            Ok(_) => *position += 1,
+            Err(e) => *divergence = Some(e.clone()),
+        }
+        result
+    }
+
+    /// Check that the call last taken with `next_replayed`, run again,
+    /// made the recorded writes and returned `result`
+    pub(crate) fn check_recomputed(&mut self, result: i64) -> Result<(), ReplayDivergence> {
+        let TranscriptMode::Replay {
+            transcript,
+            position,
+            writes,
+            ..
+        } = self
+        else {
+            unreachable!("check_recomputed called outside replay mode");
+        };
+
+        let call = &transcript.calls[*position - 1];
+        if call.writes == *writes && call.result == result {
+            return Ok(());
+        }
+        let expected = describe_effects(&call.writes, call.result);
+        let actual = describe_effects(writes, result);
+        Err(self.diverge(expected, actual))
+    }
+
+    /// Report that the call last taken with `next_replayed` did not behave
+    /// as its record claims
+    pub(crate) fn diverge(&mut self, expected: String, actual: String) -> ReplayDivergence {
+        let TranscriptMode::Replay {
+            transcript,
+            position,
+            divergence,
+            ..
+        } = self
+        else {
+            unreachable!("diverge called outside replay mode");
+        };
+
+        let index = *position - 1;
+        let call = transcript.calls[index].describe();
+        let reported = ReplayDivergence {
+            index,
+            expected: format!("{} {}", call, expected),
+            actual: format!("{} {}", call, actual),
+        };
+        *divergence = Some(reported.clone());
+        reported
+    }
+
+    /// Capsule state the replayed run starts from
+    pub(crate) fn replayed_pre_state(&self) -> StateEntries {
+        match self {
+            TranscriptMode::Replay { transcript, .. } => transcript.pre_state_entries(),
+            _ => StateEntries::new(),
+        }
+    }
+
+    /// Whether host calls are checked against a transcript
+    pub(crate) fn is_replay(&self) -> bool {
+        matches!(self, TranscriptMode::Replay { .. })
+    }
+
+    /// Divergence that stopped a replayed run, if any
+    pub(crate) fn divergence(&self) -> Option<&ReplayDivergence> {
+        match self {
+            TranscriptMode::Replay { divergence, .. } => divergence.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Finish the run, returning the transcript it recorded or replayed.
+    ///
+    /// A replayed run that made fewer calls than the transcript holds
+    /// diverges at the first unreplayed call.
+    pub(crate) fn finish(self) -> Result<Option<Transcript>, ReplayDivergence> {
+        match self {
+            TranscriptMode::Off => Ok(None),
+            TranscriptMode::Record(transcript) => Ok(Some(transcript)),
+            TranscriptMode::Replay {
+                transcript,
+                position,
+                ..
+            } => match transcript.calls.get(position) {
+                Some(call) => Err(ReplayDivergence {
+                    index: position,
+                    expected: call.describe(),
+                    actual: "end of execution".to_string(),
+                }),
+                None => Ok(Some(transcript)),
+            },
+        }
+    }
+}
+
+/// Render what a call did for divergence reports: its result and the start
+/// of the Blake3 hash of its writes
+fn describe_effects(writes: &[GuestWrite], result: i64) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for write in writes {
+        hasher.update(&write.ptr.to_le_bytes());
+        hasher.update(&(write.data.len() as u64).to_le_bytes());
+        hasher.update(&write.data);
+    }
+    let hash = hasher.finalize().to_hex();
+    format!("returning {} after writes {}", result, &hash[..16])
+}
+
+impl HostCallRecord {
+    /// Render the call for divergence reports
+    fn describe(&self) -> String {
+        describe_call(&self.function, &self.args, &self.read_hash, &self.request)
+    }
+}
+
+/// Render a call as `name(arg, ...)` for divergence reports, followed by
+/// the start of the hash of the bytes it read and of its request, if any
+fn describe_call(function: &str, args: &[i64], read_hash: &str, request: &[u8]) -> String {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let mut call = format!("{}({})", function, args.join(", "));
+    if !read_hash.is_empty() {
+        call = format!("{} reading {}", call, &read_hash[..16.min(read_hash.len())]);
+    }
+    if !request.is_empty() {
+        let hash = blake3::hash(request).to_hex();
+        call = format!("{} with request {}", call, &hash[..16]);
+    }
+    call
+}
+
+/// Serialize byte vectors as hex strings
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        hex::decode(text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(function: &str, args: &[i64], result: i64) -> HostCallRecord {
+        HostCallRecord {
+            function: function.to_string(),
+            args: args.to_vec(),
+            read_hash: String::new(),
+            request: Vec::new(),
+            writes: vec![GuestWrite {
+                ptr: 16,
+                data: vec![1, 2, 3],
+            }],
+            result,
+        }
+    }
+
+    #[test]
+    fn test_commitment_covers_every_field() {
+        let transcript = Transcript {
+            calls: vec![call("random_u32", &[], 7)],
+            pre_state: Vec::new(),
+        };
+        let mut changed = transcript.clone();
+        changed.calls[0].writes[0].data[0] = 9;
+        let mut read_changed = transcript.clone();
+        read_changed.calls[0].read_hash = blake3::hash(b"input").to_hex().to_string();
+
+        assert_eq!(transcript.commitment(), transcript.clone().commitment());
+        assert_ne!(transcript.commitment(), changed.commitment());
+        assert_ne!(transcript.commitment(), read_changed.commitment());
+        let mut state_changed = transcript.clone();
+        state_changed.pre_state.push(StateRecord {
+            key: b"count".to_vec(),
+            value: vec![1],
+        });
+        assert_ne!(transcript.commitment(), state_changed.commitment());
+        assert_ne!(transcript.commitment(), Transcript::default().commitment());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let transcript = Transcript {
+            calls: vec![call("hash_commit", &[0, 4, 4096], 0)],
+            pre_state: vec![StateRecord {
+                key: b"k".to_vec(),
+                value: b"v".to_vec(),
+            }],
+        };
+        let json = transcript.to_json().unwrap();
+        assert!(json.contains("\"010203\""));
+        assert_eq!(Transcript::from_json(&json).unwrap(), transcript);
+    }
+
+    #[test]
+    fn test_replay_reports_first_divergence() {
+        let transcript = Transcript {
+            calls: vec![call("time_now_ms", &[], 5), call("random_u32", &[], 7)],
+            pre_state: Vec::new(),
+        };
+        let mut mode = TranscriptMode::replay(transcript);
+
+        assert_eq!(
+            mode.next_replayed("time_now_ms", &[], "", &[])
+                .unwrap()
+                .result,
+            5
+        );
+        let divergence = mode
+            .next_replayed("random_bytes", &[0, 8], "", &[])
+            .unwrap_err();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(divergence.expected, "random_u32()");
+        assert_eq!(divergence.actual, "random_bytes(0, 8)");
+        assert_eq!(mode.divergence(), Some(&divergence));
+    }
+
+    #[test]
+    fn test_replay_detects_missing_calls() {
+        let transcript = Transcript {
+            calls: vec![call("time_now_ms", &[], 5)],
+            pre_state: Vec::new(),
+        };
+        let divergence = TranscriptMode::replay(transcript).finish().unwrap_err();
+        assert_eq!(divergence.index, 0);
+        assert_eq!(divergence.actual, "end of execution");
+    }
+}
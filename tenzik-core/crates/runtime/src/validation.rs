@@ -3,23 +3,32 @@
 //! This module provides validation for WebAssembly capsules before execution.
 //! It ensures capsules meet Tenzik's size, security, and interface requirements.
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use thiserror::Error;
 use wasmtime::{Engine, Module};
 
+use crate::bytecode::{self, BytecodeLimits};
+use crate::features::WasmFeaturePolicy;
+
 /// Maximum capsule size in bytes (5KB default, configurable)
 pub const DEFAULT_MAX_CAPSULE_SIZE: usize = 5 * 1024; // 5KB
 
 /// Required exports for Tenzik capsules
 pub const REQUIRED_EXPORTS: &[&str] = &["run", "memory"];
 
+/// Exports that must be present together (ABI v1 allocator pair)
+pub const PAIRED_EXPORTS: &[(&str, &str)] = &[
+    ("alloc", "dealloc"),
+    ("dealloc", "alloc"),
+];
+
 /// Allowed import prefixes for security
 pub const ALLOWED_IMPORT_PREFIXES: &[&str] = &[
     "env::",      // Host environment functions
 ];
 
 /// Validation errors
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum ValidationError {
     #[error("Capsule size {size} bytes exceeds maximum {max_size} bytes")]
     SizeExceeded { size: usize, max_size: usize },
@@ -35,6 +44,27 @@ pub enum ValidationError {
     
     #[error("Module compilation failed: {reason}")]
     CompilationFailed { reason: String },
+    
+    #[error("Floating point {operator} in function {function_index} at offset {offset:#x}")]
+    FloatingPoint { function_index: u32, operator: String, offset: usize },
+    
+    #[error("Start function {function_index} declared at offset {offset:#x}")]
+    StartFunction { function_index: u32, offset: usize },
+    
+    #[error("Memory {index} has {pages} initial pages (max {max_pages}) at offset {offset:#x}")]
+    MemoryTooLarge { index: u32, pages: u64, max_pages: u64, offset: usize },
+    
+    #[error("Table {index} has {elements} elements (max {max_elements}) at offset {offset:#x}")]
+    TableTooLarge { index: u32, elements: u64, max_elements: u64, offset: usize },
+    
+    #[error("Module has {count} functions (max {max}) at offset {offset:#x}")]
+    TooManyFunctions { count: u64, max: u32, offset: usize },
+    
+    #[error("Function {function_index} has {count} locals (max {max}) at offset {offset:#x}")]
+    TooManyLocals { function_index: u32, count: u64, max: u32, offset: usize },
+    
+    #[error("Function {function_index} nests {depth} blocks (max {max}) at offset {offset:#x}")]
+    NestingTooDeep { function_index: u32, depth: u32, max: u32, offset: usize },
 }
 
 /// Validation result containing detailed information about the capsule
@@ -99,40 +129,79 @@ pub struct WasmValidator {
     strict_imports: bool,
     /// Whether to require all standard exports
     require_standard_exports: bool,
+    /// Limits checked by the bytecode analysis
+    bytecode: BytecodeLimits,
+    /// Whether the engine canonicalizes NaNs, which allows floating point
+    nan_canonicalization: bool,
 }
 
 impl WasmValidator {
     /// Create a new validator with default settings
     pub fn new() -> Result<Self> {
-        let engine = Engine::default();
-        
-        Ok(Self {
-            max_size_bytes: DEFAULT_MAX_CAPSULE_SIZE,
-            engine,
-            strict_imports: true,
-            require_standard_exports: true,
-        })
+        Self::with_config(ValidatorConfig::default())
     }
     
-    /// Create a new validator with custom configuration
+    /// Create a new validator with custom configuration, compiling with an
+    /// engine built from `config.features`
     pub fn with_config(config: ValidatorConfig) -> Result<Self> {
-        let engine = Engine::default();
-        
+        Self::with_engine(config.features.engine()?, config)
+    }
+    
+    /// Create a validator that compiles with the given engine, so the module
+    /// produced during validation can be reused for execution. The engine
+    /// should apply `config.features`.
+    pub fn with_engine(engine: Engine, config: ValidatorConfig) -> Result<Self> {
         Ok(Self {
             max_size_bytes: config.max_size_bytes,
             engine,
             strict_imports: config.strict_imports,
             require_standard_exports: config.require_standard_exports,
+            bytecode: config.bytecode,
+            nan_canonicalization: config.features.nan_canonicalization,
         })
     }
     
     /// Validate a WASM capsule from bytes
     pub fn validate(&self, wasm_bytes: &[u8]) -> Result<ValidationResult> {
+        self.validate_and_compile(wasm_bytes)
+            .map(|(result, _)| result)
+    }
+    
+    /// Validate a WASM capsule and return the compiled module when it passes
+    pub fn validate_and_compile(&self, wasm_bytes: &[u8]) -> Result<(ValidationResult, Option<Module>)> {
+        let size_bytes = wasm_bytes.len();
+        
+        // Check size limits first (fast check)
+        if size_bytes > self.max_size_bytes {
+            let errors = vec![ValidationError::SizeExceeded {
+                size: size_bytes,
+                max_size: self.max_size_bytes,
+            }];
+            return Ok((ValidationResult::failure(size_bytes, errors), None));
+        }
+        
+        // Attempt to parse and compile the module
+        let module = match Module::from_binary(&self.engine, wasm_bytes) {
+            Ok(module) => module,
+            Err(e) => {
+                let errors = vec![ValidationError::CompilationFailed {
+                    reason: e.to_string(),
+                }];
+                return Ok((ValidationResult::failure(size_bytes, errors), None));
+            }
+        };
+        
+        let result = self.validate_module(wasm_bytes, &module)?;
+        let module = result.is_valid.then_some(module);
+        Ok((result, module))
+    }
+    
+    /// Validate an already compiled module built from `wasm_bytes`
+    pub fn validate_module(&self, wasm_bytes: &[u8], module: &Module) -> Result<ValidationResult> {
         let size_bytes = wasm_bytes.len();
         let mut errors = Vec::new();
         let mut warnings = Vec::new();
         
-        // Check size limits first (fast check)
         if size_bytes > self.max_size_bytes {
             errors.push(ValidationError::SizeExceeded {
                 size: size_bytes,
@@ -150,20 +219,9 @@ impl WasmValidator {
             ));
         }
         
-        // Attempt to parse and compile the module
-        let module = match Module::from_binary(&self.engine, wasm_bytes) {
-            Ok(module) => module,
-            Err(e) => {
-                errors.push(ValidationError::CompilationFailed {
-                    reason: e.to_string(),
-                });
-                return Ok(ValidationResult::failure(size_bytes, errors));
-            }
-        };
-        
         // Extract exports and imports
-        let exports = self.extract_exports(&module)?;
-        let imports = self.extract_imports(&module)?;
+        let exports = self.extract_exports(module)?;
+        let imports = self.extract_imports(module)?;
         
         // Validate required exports
         if self.require_standard_exports {
@@ -174,6 +232,15 @@ impl WasmValidator {
                     });
                 }
             }
+            
+            // An ABI v1 capsule must export both halves of its allocator
+            for (present, partner) in PAIRED_EXPORTS {
+                if exports.iter().any(|e| e == present) && !exports.iter().any(|e| e == partner) {
+                    errors.push(ValidationError::MissingRequiredExport {
+                        export: partner.to_string(),
+                    });
+                }
+            }
         }
         
         // Validate imports against allowlist
@@ -187,6 +254,11 @@ impl WasmValidator {
             }
         }
         
+        // Walk the code for nondeterministic or abusive constructs
+        let report = bytecode::analyze(wasm_bytes, &self.bytecode, self.nan_canonicalization);
+        errors.extend(report.errors);
+        warnings.extend(report.warnings);
+        
         // Create result
         if errors.is_empty() {
             let mut result = ValidationResult::success(size_bytes, exports, imports);
@@ -256,6 +328,10 @@ pub struct ValidatorConfig {
     pub strict_imports: bool,
     /// Whether to require standard Tenzik exports
     pub require_standard_exports: bool,
+    /// WebAssembly features capsules may use; must match the runtime's
+    pub features: WasmFeaturePolicy,
+    /// Limits enforced by static analysis of the code
+    pub bytecode: BytecodeLimits,
 }
 
 impl Default for ValidatorConfig {
@@ -264,6 +340,8 @@ impl Default for ValidatorConfig {
             max_size_bytes: DEFAULT_MAX_CAPSULE_SIZE,
             strict_imports: true,
             require_standard_exports: true,
+            features: WasmFeaturePolicy::default(),
+            bytecode: BytecodeLimits::default(),
         }
     }
 }
@@ -308,6 +386,8 @@ mod tests {
             max_size_bytes: 100,
             require_standard_exports: false, // Skip export validation for this test
             strict_imports: false, // Skip import validation for this test
+            features: WasmFeaturePolicy::default(),
+            bytecode: BytecodeLimits::default(),
         }).unwrap();
         
         // Create a minimal valid WASM module that's 85 bytes (85% of 100 byte limit)
@@ -320,6 +400,48 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_features_outside_policy_fail_validation() {
+        let wasm = wat::parse_str(r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "run") (param i32 i32) (result i32)
+                (memory.fill (i32.const 0) (i32.const 0) (i32.const 1))
+                (i32.const 0)))
+        "#).unwrap();
+        
+        let result = WasmValidator::new().unwrap().validate(&wasm).unwrap();
+        assert!(!result.is_valid);
+        assert!(matches!(result.errors[0], ValidationError::CompilationFailed { .. }));
+        
+        let validator = WasmValidator::with_config(ValidatorConfig {
+            features: WasmFeaturePolicy {
+                bulk_memory: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }).unwrap();
+        assert!(validator.validate(&wasm).unwrap().is_valid);
+    }
+    
+    #[test]
+    fn test_alloc_requires_dealloc() {
+        let validator = WasmValidator::new().unwrap();
+        let wasm = wat::parse_str(r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "run") (param i32 i32) (result i32) (i32.const 0)))
+        "#).unwrap();
+        
+        let result = validator.validate(&wasm).unwrap();
+        assert!(!result.is_valid);
+        assert!(matches!(
+            &result.errors[0],
+            ValidationError::MissingRequiredExport { export } if export == "dealloc"
+        ));
+    }
+    
     /// Helper to create a minimal valid WASM module for testing
     fn create_minimal_wasm_module() -> Vec<u8> {
         // Minimal WASM module with magic number and version